@@ -0,0 +1,489 @@
+//! Parser for tables written as GNU as/mwasm style data directives.
+//!
+//! Supported directives are `.byte`, `.2byte`/`.short`/`.half`/`.word` (2 bytes, as
+//! for PowerPC), `.4byte`/`.long`/`.int`, `.balign`, `.skip`/`.space` and `.section`.
+//! Several statements can be given on one line, separated by `;`.
+//! Symbol directives such as `.obj`, `.global` or `.type` are ignored. Values can be
+//! numbers (hex, binary, octal or decimal) or symbol references with an optional
+//! addend, and several values can be given per directive.
+//!
+//! If the file has an `extab` section, only its data is used. Otherwise, the data of
+//! the first section with any is used.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::input::TableInput;
+
+/// Error found while parsing, at the given line.
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+const EXTAB_SECTION_NAME: &str = "extab";
+
+/// Directives which don't affect the data, and are skipped.
+const IGNORED_DIRECTIVES: [&str; 13] = [
+    ".obj", ".endobj", ".sym", ".endsym", ".hidden", ".global", ".globl", ".local", ".weak",
+    ".type", ".size", ".file", ".ident",
+];
+
+/// Value of a data directive.
+enum Value {
+    Number(i64),
+    Symbol { name: String, addend: i64 },
+}
+
+/// Data of a section, along with the symbol references in it.
+#[derive(Default)]
+struct SectionData {
+    name: Option<String>,
+    bytes: Vec<u8>,
+    relocation_names: HashMap<u32, String>,
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$' || c == '@'
+}
+
+/// Parses a number in the forms accepted by GNU as: `0x` hex, `0b` binary, octal with
+/// a leading zero, or decimal.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, text),
+    };
+    let lower = text.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if lower.len() > 1 && lower.starts_with('0') {
+        i64::from_str_radix(&lower[1..], 8).ok()?
+    } else {
+        lower.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Removes comments from the line and splits it into statements, keeping track of
+/// block comments which span lines. Comments start with `#` or `//`, or are enclosed in
+/// `/*` and `*/`, and statements are separated by `;`.
+fn split_statements(line: &str, in_block_comment: &mut bool) -> Vec<String> {
+    let mut statements: Vec<String> = vec![];
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if *in_block_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                *in_block_comment = false;
+            }
+            continue;
+        }
+        if in_string {
+            if c == '\\' {
+                out.push(c);
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+                continue;
+            }
+            in_string = c != '"';
+            out.push(c);
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            ';' => statements.push(std::mem::take(&mut out)),
+            '#' => break,
+            '/' if chars.peek() == Some(&'/') => break,
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                *in_block_comment = true;
+            }
+            _ => out.push(c),
+        }
+    }
+
+    statements.push(out);
+    statements
+}
+
+/// Splits the text on commas which aren't in a quoted symbol name.
+fn split_values(text: &str) -> Vec<&str> {
+    let mut values: Vec<&str> = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            values.push(text[start..i].trim());
+            start = i + 1;
+        }
+    }
+    values.push(text[start..].trim());
+    values
+}
+
+/// Reads a symbol name at the start of the text, either plain or quoted, returning it
+/// along with the remaining text.
+fn read_symbol(text: &str) -> Option<(String, &str)> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut name = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((name, &rest[i + 1..])),
+                '\\' => name.push(chars.next()?.1),
+                _ => name.push(c),
+            }
+        }
+        None
+    } else {
+        let end = text
+            .find(|c: char| !is_symbol_char(c))
+            .unwrap_or(text.len());
+        if end == 0 || text.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        Some((String::from(&text[..end]), &text[end..]))
+    }
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if text.is_empty() {
+        return Err(String::from("missing value"));
+    }
+    if let Some(value) = parse_number(text) {
+        return Ok(Value::Number(value));
+    }
+
+    let (name, rest) = read_symbol(text).ok_or_else(|| format!("invalid value \"{text}\""))?;
+    let rest = rest.trim();
+    let addend = if rest.is_empty() {
+        0
+    } else if let Some(addend) = rest.strip_prefix('+') {
+        parse_number(addend.trim()).ok_or_else(|| format!("invalid addend \"{addend}\""))?
+    } else if rest.starts_with('-') {
+        parse_number(rest).ok_or_else(|| format!("invalid addend \"{rest}\""))?
+    } else {
+        return Err(format!("invalid value \"{text}\""));
+    };
+    Ok(Value::Symbol { name, addend })
+}
+
+/// Writes a number into the section, checking that it fits in the given size as either
+/// a signed or unsigned value.
+fn write_number(section: &mut SectionData, value: i64, size: u32) -> Result<(), String> {
+    let bits = size * 8;
+    let min = -(1i64 << (bits - 1));
+    let max = (1i64 << bits) - 1;
+    if value < min || value > max {
+        return Err(format!(
+            "value {value:#X} does not fit in a {size} byte value"
+        ));
+    }
+    let bytes = (value as u64).to_be_bytes();
+    section
+        .bytes
+        .extend_from_slice(&bytes[(8 - size as usize)..]);
+    Ok(())
+}
+
+fn parse_data(section: &mut SectionData, args: &str, size: u32) -> Result<(), String> {
+    for text in split_values(args) {
+        match parse_value(text)? {
+            Value::Number(value) => write_number(section, value, size)?,
+            Value::Symbol { name, addend } => {
+                if size != 4 {
+                    return Err(format!("symbol reference {name} must be 4 bytes"));
+                }
+
+                //The table holds zero in place of the reference, as in a relocatable
+                //object. The addend is kept in the name.
+                let name = match addend {
+                    0 => name,
+                    addend if addend < 0 => format!("{name}-{:#X}", -addend),
+                    addend => format!("{name}+{addend:#X}"),
+                };
+                section
+                    .relocation_names
+                    .insert(section.bytes.len() as u32, name);
+                write_number(section, 0, 4)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses the size and optional fill value of an alignment or skip directive.
+fn parse_size_and_fill(args: &str) -> Result<(u32, u8), String> {
+    let values = split_values(args);
+    if values.len() > 2 {
+        return Err(String::from("too many values"));
+    }
+    let size = match parse_number(values[0]) {
+        Some(size) if (0..=0x1000000).contains(&size) => size as u32,
+        _ => return Err(format!("invalid size \"{}\"", values[0])),
+    };
+    let fill = match values.get(1) {
+        Some(text) => match parse_number(text) {
+            Some(fill) if (-0x80..=0xFF).contains(&fill) => fill as u8,
+            _ => return Err(format!("invalid fill value \"{text}\"")),
+        },
+        None => 0,
+    };
+    Ok((size, fill))
+}
+
+/// Parses the table in the given text, returning the table bytes and the names of the
+/// referenced symbols, keyed by their offset in the table.
+pub fn parse_asm(text: &str) -> Result<TableInput, AsmError> {
+    let mut sections: Vec<SectionData> = vec![SectionData::default()];
+    let mut current: usize = 0;
+    let mut in_block_comment = false;
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let error = |message: String| AsmError {
+            line: line_num,
+            message,
+        };
+
+        for statement in split_statements(line, &mut in_block_comment) {
+            let mut rest = statement.trim();
+
+            //Skip any labels at the start of the statement
+            while let Some((_, after)) = read_symbol(rest) {
+                match after.trim_start().strip_prefix(':') {
+                    Some(after) => rest = after.trim_start(),
+                    None => break,
+                }
+            }
+            if rest.is_empty() {
+                continue;
+            }
+
+            let (directive, args) = match rest.find(char::is_whitespace) {
+                Some(index) => (&rest[..index], rest[index..].trim()),
+                None => (rest, ""),
+            };
+
+            let section = &mut sections[current];
+            let result = match directive {
+                ".byte" => parse_data(section, args, 1),
+                ".2byte" | ".short" | ".half" | ".hword" | ".word" => parse_data(section, args, 2),
+                ".4byte" | ".long" | ".int" => parse_data(section, args, 4),
+                ".balign" => parse_size_and_fill(args).and_then(|(alignment, fill)| {
+                    if !alignment.is_power_of_two() {
+                        return Err(format!("alignment {alignment} is not a power of two"));
+                    }
+                    while section.bytes.len() as u32 % alignment != 0 {
+                        section.bytes.push(fill);
+                    }
+                    Ok(())
+                }),
+                ".skip" | ".space" => parse_size_and_fill(args).map(|(size, fill)| {
+                    section
+                        .bytes
+                        .extend(std::iter::repeat(fill).take(size as usize));
+                }),
+                ".section" | ".text" | ".data" | ".rodata" => {
+                    let name = if directive == ".section" {
+                        let name = split_values(args)[0];
+                        read_symbol(name)
+                            .map(|(name, _)| name)
+                            .ok_or_else(|| error(String::from("missing section name")))?
+                    } else {
+                        String::from(directive)
+                    };
+                    current = match sections
+                        .iter()
+                        .position(|section| section.name.as_deref() == Some(name.as_str()))
+                    {
+                        Some(index) => index,
+                        None => {
+                            sections.push(SectionData {
+                                name: Some(name),
+                                ..Default::default()
+                            });
+                            sections.len() - 1
+                        }
+                    };
+                    Ok(())
+                }
+                _ if IGNORED_DIRECTIVES.contains(&directive) => Ok(()),
+                _ => Err(format!("unknown directive {directive}")),
+            };
+            result.map_err(error)?;
+        }
+    }
+
+    //Use the extab section if there is one, or the first section with any data
+    let index = sections
+        .iter()
+        .position(|section| section.name.as_deref() == Some(EXTAB_SECTION_NAME))
+        .or_else(|| {
+            sections
+                .iter()
+                .position(|section| !section.bytes.is_empty())
+        })
+        .unwrap_or(0);
+    let section = sections.swap_remove(index);
+    Ok((section.bytes, section.relocation_names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_asm;
+
+    /// Table as written by decomp-toolkit, followed by its extabindex entry.
+    const DTK_ASM: &str = r#"
+.section extab, "a"  # 0x800054A0 - 0x800054B8
+
+.obj "@etb_800034A0", local
+.hidden "@etb_800034A0"
+	.4byte 0x10080000
+	.4byte 0x00000004
+	.4byte 0x00020010 ; .4byte 0x00000000
+	.4byte 0x82000008
+	.4byte "__dt__3FooFv"
+.endobj "@etb_800034A0"
+
+.section extabindex, "a"  # 0x800054B8 - 0x800054C4
+
+.obj "@eti_800054B8", local
+.hidden "@eti_800054B8"
+	.4byte main
+	.4byte 0x00000008
+	.4byte "@etb_800034A0"
+.endobj "@eti_800054B8"
+"#;
+
+    #[rustfmt::skip]
+    const DTK_TABLE: [u8; 24] = [
+        0x10, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x04, 0x00, 0x02, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x82, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, //Destroy local
+    ];
+
+    #[test]
+    fn dtk_table() {
+        let (bytes, names) = parse_asm(DTK_ASM).unwrap();
+        assert_eq!(bytes, DTK_TABLE);
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[&0x14], "__dt__3FooFv");
+    }
+
+    #[test]
+    fn comments_and_statements() {
+        let text = "
+# Comment
+/* Comment spanning
+   lines; .byte 0xFF */ .byte 1 // Comment
+label: other_label: .byte 2; .2byte 3 ;.byte 4, 5
+\".quoted;label\": .byte 6 # .byte 0xFF
+";
+        let (bytes, names) = parse_asm(text).unwrap();
+        assert_eq!(bytes, [1, 2, 0, 3, 4, 5, 6]);
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn symbol_references() {
+        let text = "
+.4byte foo, foo+8, \"bar, baz\" + 0x10, foo - 4
+.long \"quoted \\\"name\\\"\"
+";
+        let (bytes, names) = parse_asm(text).unwrap();
+        assert_eq!(bytes, [0; 20]);
+        assert_eq!(names[&0x0], "foo");
+        assert_eq!(names[&0x4], "foo+0x8");
+        assert_eq!(names[&0x8], "bar, baz+0x10");
+        assert_eq!(names[&0xC], "foo-0x4");
+        assert_eq!(names[&0x10], "quoted \"name\"");
+    }
+
+    #[test]
+    fn alignment_and_skip() {
+        let text = "
+.byte 1
+.balign 4
+.byte 2
+.balign 8, 0xFF
+.skip 2
+.space 3, 0xAA
+.balign 4
+";
+        let (bytes, _) = parse_asm(text).unwrap();
+        assert_eq!(
+            bytes,
+            [1, 0, 0, 0, 2, 0xFF, 0xFF, 0xFF, 0, 0, 0xAA, 0xAA, 0xAA, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn first_section_with_data() {
+        let text = "
+.section .bss
+.section .data
+.byte 1
+.text
+.byte 2
+";
+        let (bytes, _) = parse_asm(text).unwrap();
+        assert_eq!(bytes, [1]);
+    }
+
+    #[test]
+    fn error_lines() {
+        let errors = [
+            (".byte 1\n.fill 4", 2, "unknown directive .fill"),
+            (
+                ".byte 1\n\n.byte 0x100",
+                3,
+                "value 0x100 does not fit in a 1 byte value",
+            ),
+            (
+                "/*\n*/ .2byte foo",
+                2,
+                "symbol reference foo must be 4 bytes",
+            ),
+            (".byte 1; .4byte foo+bar", 1, "invalid addend \"bar\""),
+            ("\n.balign 3", 2, "alignment 3 is not a power of two"),
+            (".skip 4, 0x100", 1, "invalid fill value \"0x100\""),
+            (".byte", 1, "missing value"),
+        ];
+        for (text, line, message) in errors {
+            let error = parse_asm(text).unwrap_err();
+            assert_eq!((error.line, error.message.as_str()), (line, message));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+
+use crate::asm;
+
+/// Format of the table given as input.
+#[derive(Copy, Clone)]
+pub enum InputFormat {
+    /// Text file of data directives.
+    Asm,
+    /// Raw table bytes.
+    Binary,
+    /// Whitespace separated hex bytes.
+    Hex,
+    /// Hex dump in the format written by `xxd`.
+    Xxd,
+}
+
+impl InputFormat {
+    pub fn from_name(name: &str) -> Option<InputFormat> {
+        match name {
+            "asm" => Some(InputFormat::Asm),
+            "binary" => Some(InputFormat::Binary),
+            "hex" => Some(InputFormat::Hex),
+            "xxd" => Some(InputFormat::Xxd),
+            _ => None,
+        }
+    }
+}
+
+/// Table bytes, along with the names of the referenced functions keyed by their
+/// offset in the table.
+pub type TableInput = (Vec<u8>, HashMap<u32, String>);
+
+/// Reads the given file, or stdin if the path is `-`.
+fn read_source(path: &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        let mut data: Vec<u8> = vec![];
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read stdin: {e}"))?;
+        Ok(data)
+    } else {
+        fs::read(path).map_err(|e| format!("Failed to open file \"{path}\": {e}"))
+    }
+}
+
+fn source_to_string(data: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(data).map_err(|_| String::from("Error: Input is not valid UTF-8 text"))
+}
+
+/// Reads the table in the given file (or stdin if the path is `-`) in the given format.
+pub fn read_table(path: &str, format: InputFormat) -> Result<TableInput, String> {
+    let data = read_source(path)?;
+    match format {
+        InputFormat::Asm => {
+            let text = source_to_string(data)?;
+            asm::parse_asm(&text).map_err(|e| {
+                let name = if path == "-" { "<stdin>" } else { path };
+                format!("{name}:{e}")
+            })
+        }
+        InputFormat::Binary => Ok((data, HashMap::new())),
+        InputFormat::Hex => Ok((parse_hex(&source_to_string(data)?)?, HashMap::new())),
+        InputFormat::Xxd => Ok((parse_xxd(&source_to_string(data)?)?, HashMap::new())),
+    }
+}
+
+/// Parses whitespace separated hex values. Each value can hold any number of bytes,
+/// so both `08 08 00 00` and `08080000` are accepted.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = vec![];
+
+    for value in text.split_whitespace() {
+        let digits = value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+            .unwrap_or(value);
+        if digits.is_empty()
+            || digits.len() % 2 != 0
+            || !digits.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(format!("Error: Invalid hex value \"{value}\""));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).unwrap());
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Parses a hex dump in the format written by `xxd`, where each line has the offset,
+/// the hex bytes and their text form:
+///
+/// `00000000: 0808 0000 0000 0000  ........`
+fn parse_xxd(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (offset, rest) = match line.split_once(':') {
+            Some(val) => val,
+            None => return Err(format!("Error: Line {line_num} has no offset")),
+        };
+        let offset = match usize::from_str_radix(offset.trim(), 16) {
+            Ok(val) => val,
+            Err(_) => return Err(format!("Error: Line {line_num} has an invalid offset")),
+        };
+        if offset != bytes.len() {
+            return Err(format!(
+                "Error: Line {line_num} starts at offset 0x{offset:X}, expected 0x{:X}",
+                bytes.len()
+            ));
+        }
+
+        //The hex bytes are separated from their text form by two spaces
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+        let hex = rest.split("  ").next().unwrap_or("");
+        let line_bytes = parse_hex(hex).map_err(|e| format!("{e} on line {line_num}"))?;
+        bytes.extend_from_slice(&line_bytes);
+    }
+
+    Ok(bytes)
+}
+
+/// Selects the given range of the table input, moving the relocation names along
+/// with it. The range goes to the end of the input if no length is given.
+pub fn select_range(
+    input: TableInput,
+    offset: u32,
+    length: Option<u32>,
+) -> Result<TableInput, String> {
+    let (data, relocation_names) = input;
+    let size = data.len() as u32;
+    let end = match length {
+        Some(length) => offset.checked_add(length),
+        None => Some(size),
+    };
+    let end = match end {
+        Some(end) if offset <= end && end <= size => end,
+        _ => {
+            return Err(format!(
+                "Error: Range is outside of the input, which is 0x{size:X} bytes long"
+            ))
+        }
+    };
+
+    let relocation_names: HashMap<u32, String> = relocation_names
+        .into_iter()
+        .filter(|(reloc_offset, _)| offset <= *reloc_offset && *reloc_offset < end)
+        .map(|(reloc_offset, name)| (reloc_offset - offset, name))
+        .collect();
+    Ok((
+        data[offset as usize..end as usize].to_vec(),
+        relocation_names,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{parse_hex, parse_xxd, select_range};
+
+    #[test]
+    fn hex() {
+        let bytes = parse_hex("08 08 00 00\n0x00000010 0X0004\t0010").unwrap();
+        assert_eq!(bytes, [8, 8, 0, 0, 0, 0, 0, 0x10, 0, 4, 0, 0x10]);
+        assert!(parse_hex("  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_hex() {
+        for text in ["08 080", "0x", "08 0G", "0x0x08"] {
+            assert!(parse_hex(text).is_err(), "{text}");
+        }
+        assert_eq!(
+            parse_hex("08 080").unwrap_err(),
+            "Error: Invalid hex value \"080\""
+        );
+    }
+
+    #[test]
+    fn xxd() {
+        let text = "\
+00000000: 1008 0000 0000 0004 0002 0010 0000 0000  ................
+00000010: 8200 0008 8000 1234                      .......4
+
+";
+        let bytes = parse_xxd(text).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x10, 0x08, 0, 0, 0, 0, 0, 4, 0, 2, 0, 0x10, 0, 0, 0, 0, 0x82, 0, 0, 8, 0x80, 0,
+                0x12, 0x34,
+            ]
+        );
+    }
+
+    #[test]
+    fn xxd_text_column() {
+        //The text column can hold anything, including two spaces and hex digits
+        let text = "00000000: 4142 2020 4344  AB  CD\n";
+        assert_eq!(parse_xxd(text).unwrap(), b"AB  CD");
+    }
+
+    #[test]
+    fn invalid_xxd() {
+        let errors = [
+            ("0000000 1008 0000  ....", "Error: Line 1 has no offset"),
+            (
+                "0000000G: 1008 0000  ....",
+                "Error: Line 1 has an invalid offset",
+            ),
+            (
+                "00000000: 1008  ..\n00000004: 0000  ..",
+                "Error: Line 2 starts at offset 0x4, expected 0x2",
+            ),
+            (
+                "00000000: 1008 000  ...",
+                "Error: Invalid hex value \"000\" on line 1",
+            ),
+        ];
+        for (text, message) in errors {
+            assert_eq!(parse_xxd(text).unwrap_err(), message);
+        }
+    }
+
+    fn input() -> (Vec<u8>, HashMap<u32, String>) {
+        let names = [(4, "foo"), (12, "bar")]
+            .into_iter()
+            .map(|(offset, name)| (offset, String::from(name)))
+            .collect();
+        ((0..16).collect(), names)
+    }
+
+    #[test]
+    fn range() {
+        let (bytes, names) = select_range(input(), 8, Some(4)).unwrap();
+        assert_eq!(bytes, [8, 9, 10, 11]);
+        assert!(names.is_empty());
+
+        //Names move along with the selected bytes
+        let (bytes, names) = select_range(input(), 4, None).unwrap();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(names[&0], "foo");
+        assert_eq!(names[&8], "bar");
+
+        let (bytes, _) = select_range(input(), 16, None).unwrap();
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn range_outside_input() {
+        let error = "Error: Range is outside of the input, which is 0x10 bytes long";
+        for (offset, length) in [(17, None), (8, Some(9)), (16, Some(1)), (1, Some(u32::MAX))] {
+            assert_eq!(
+                select_range(input(), offset, length).unwrap_err(),
+                error,
+                "{offset} {length:?}"
+            );
+        }
+    }
+}
@@ -1,95 +1,469 @@
 use cwextab::*;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::process::ExitCode;
 
-fn test_decode(data: &[u8], funcs: Vec<String>) {
-    let result = decode_extab(data);
-    let data: ExceptionTableData = match result {
-        Ok(val) => val,
-        Err(e) => {
-            panic!(
-                concat!("Something went wrong with decoding :<\n", "Error: {}"),
-                e.to_string()
-            );
+mod asm;
+mod input;
+
+use input::{InputFormat, TableInput};
+
+/// Output format for decoded tables.
+#[derive(Copy, Clone)]
+enum Format {
+    Text,
+    Json,
+    Yaml,
+    Source,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "yaml" => Some(Format::Yaml),
+            "source" => Some(Format::Source),
+            _ => None,
         }
-    };
+    }
+}
+
+/// Decoded table along with its resolved dtor references, for JSON and YAML output.
+#[derive(serde::Serialize)]
+struct TableOutput<'a> {
+    table: &'a ExceptionTableData,
+    dtor_references: Vec<DtorReference>,
+    //Names of the functions and types referenced by the table, keyed by their offset
+    symbol_names: BTreeMap<u32, &'a str>,
+}
+
+/// Decodes the table and prints it in the given format.
+fn test_decode(
+    data: &[u8],
+    mut relocation_names: HashMap<u32, String>,
+    format: Format,
+    symbols: Option<&SymbolMap>,
+) -> Result<(), String> {
+    let data = decode_extab(data).map_err(|e| format!("Error: {e}"))?;
+
+    //Name the references which the input has no symbols for from the symbol map
+    if let Some(symbols) = symbols {
+        for (offset, name) in data.resolve_symbols(symbols) {
+            relocation_names.entry(offset).or_insert(name);
+        }
+    }
 
     //Convert the table struct to a string and print it.
-    let result = data.to_string(funcs);
-    let text: String = match result {
-        Some(val) => val,
-        None => {
-            panic!("Something went wrong with converting to text :<");
+    let text: String = match format {
+        Format::Text => data.to_string_with_relocations(&relocation_names),
+        Format::Source => data
+            .to_pseudo_source(&relocation_names)
+            .map_err(|e| format!("Error: {e}"))?,
+        Format::Json | Format::Yaml => {
+            let output = TableOutput {
+                table: &data,
+                dtor_references: data.resolve_dtor_references(&relocation_names),
+                symbol_names: relocation_names
+                    .iter()
+                    .map(|(offset, name)| (*offset, name.as_str()))
+                    .collect(),
+            };
+            let serialized = if let Format::Json = format {
+                serde_json::to_string_pretty(&output).map_err(|e| e.to_string())
+            } else {
+                serde_yaml::to_string(&output).map_err(|e| e.to_string())
+            };
+            serialized.map_err(|e| format!("Error: Failed to serialize table: {e}"))?
         }
     };
 
     println!("{}", text);
+    Ok(())
 }
 
-fn read_all_lines_from_file(path: &str) -> Vec<String> {
-    let file = File::open(path).expect(&format!("Failed to open file \"{}\"", path));
-    let reader = BufReader::new(file);
-    reader.lines().map(|line| line.expect("Could not parse line")).collect()
+/// Options for reading the input tables.
+struct InputOptions {
+    format: InputFormat,
+    offset: u32,
+    length: Option<u32>,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+impl InputOptions {
+    fn read_table(&self, path: &str) -> Result<TableInput, String> {
+        let input = input::read_table(path, self.format)?;
+        self.select_range(input)
+    }
 
-    if args.len() > 1 {
-        let mut table_bytes: Vec<u8> = vec![];
-        let mut func_names: Vec<String> = vec![];
+    fn select_range(&self, input: TableInput) -> Result<TableInput, String> {
+        input::select_range(input, self.offset, self.length)
+    }
+}
 
-        let lines = read_all_lines_from_file(&args[1]);
+/// Decodes and validates the table in each file, printing any problems found.
+/// Returns false if any table failed to decode or has errors.
+fn validate(paths: &[String], input_options: &InputOptions) -> bool {
+    let mut valid = true;
 
-        //println!("Lines: {}", num_lines);
+    for path in paths {
+        let (table_bytes, _) = match input_options.read_table(path) {
+            Ok(val) => val,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                valid = false;
+                continue;
+            }
+        };
+        let data = match decode_extab(&table_bytes) {
+            Ok(val) => val,
+            Err(e) => {
+                eprintln!("{path}: error: {e}");
+                valid = false;
+                continue;
+            }
+        };
 
-        //Parse the table in the given text file
-        for line in lines {
-            let cur_line: String = line;
-            let parts: Vec<&str> = cur_line.trim().split(' ').collect();
+        let diagnostics = data.validate();
+        if diagnostics.is_empty() {
+            println!("{path}: ok");
+        }
+        for diagnostic in &diagnostics {
+            eprintln!("{path}: {diagnostic}");
+            if diagnostic.severity == Severity::Error {
+                valid = false;
+            }
+        }
+    }
 
-            let data_size: u32 =
-            if parts[0].starts_with(".4byte") {
-                4
-            } else if parts[0].starts_with(".2byte") {
-                2
-            } else {
-                println!("Error: Invalid line in table, must start with .4byte");
-                return;
-            };
+    valid
+}
 
-            let value: String = parts[1].to_string();
+/// Decodes the table in the file, naming the references which the input has no
+/// symbols for from the symbol map.
+fn read_named_table(
+    path: &str,
+    input_options: &InputOptions,
+    symbols: Option<&SymbolMap>,
+) -> Result<(ExceptionTableData, HashMap<u32, String>), String> {
+    let (table_bytes, mut relocation_names) = input_options.read_table(path)?;
+    let data = decode_extab(&table_bytes).map_err(|e| format!("{path}: error: {e}"))?;
+    if let Some(symbols) = symbols {
+        for (offset, name) in data.resolve_symbols(symbols) {
+            relocation_names.entry(offset).or_insert(name);
+        }
+    }
+    Ok((data, relocation_names))
+}
 
-            let mut line_val: u32 = 0; //Value for current line (16/32 bit)
+/// Compares the tables in the two files, printing their differences. Returns false if
+/// the tables differ.
+fn diff(
+    left_path: &str,
+    right_path: &str,
+    input_options: &InputOptions,
+    symbols: Option<&SymbolMap>,
+) -> Result<bool, String> {
+    let (left, left_names) = read_named_table(left_path, input_options, symbols)?;
+    let (right, right_names) = read_named_table(right_path, input_options, symbols)?;
 
-            //Hex value
-            if let Some(hex_string) = value.strip_prefix("0x") {
-                line_val = u32::from_str_radix(hex_string, 16).expect("Failed to parse hex value");
-            } else {
-                //Otherwise, treat as a function name
-                let length: usize = value.len();
-                let func_name: String = if value.starts_with('"') && value.ends_with('"') {
-                    value[1..length - 1].to_string()
-                } else {
-                    value.to_string()
-                };
-
-                func_names.push(func_name);
+    let differences = left.diff(&left_names, &right, &right_names);
+    if differences.is_empty() {
+        println!("Tables match");
+    }
+    for difference in &differences {
+        println!("{difference}");
+    }
+    Ok(differences.is_empty())
+}
+
+/// Thrown type given to the simulate mode, either by address or by symbol name.
+enum ThrownType {
+    Address(u32),
+    Name(String),
+}
+
+/// Condition flag given to the simulate mode, either a register or a stack slot
+/// relative to the table's frame base.
+enum ConditionSlot {
+    Register(u32),
+    Stack(u32),
+}
+
+/// Options for simulating a throw.
+struct SimulateOptions {
+    pc: u32,
+    thrown_type: Option<ThrownType>,
+    conditions: Vec<(ConditionSlot, bool)>,
+}
+
+/// Simulates an exception thrown at the given PC in the table in the file, printing
+/// each step of unwinding.
+fn simulate(
+    path: &str,
+    input_options: &InputOptions,
+    symbols: Option<&SymbolMap>,
+    simulate_options: &SimulateOptions,
+) -> Result<(), String> {
+    let (data, relocation_names) = read_named_table(path, input_options, symbols)?;
+
+    let base = data.frame_base();
+    let mut frame = FrameModel::new();
+    for (slot, value) in &simulate_options.conditions {
+        let location = match slot {
+            ConditionSlot::Register(register) => Location::Register(*register),
+            ConditionSlot::Stack(offset) => Location::Stack {
+                base,
+                offset: *offset,
+            },
+        };
+        frame.conditions.insert(location, *value);
+    }
+
+    //Types are matched by name if the table has one for them, and by address otherwise.
+    //Null types without a name are catch (...), which the library matches itself.
+    let matches = |catch_type: &Relocation| {
+        let name = relocation_names.get(&catch_type.offset);
+        match (&simulate_options.thrown_type, name) {
+            (Some(ThrownType::Name(thrown)), Some(name)) => thrown == name,
+            (Some(ThrownType::Address(thrown)), _) => {
+                catch_type.address != 0 && *thrown == catch_type.address
             }
+            _ => false,
+        }
+    };
+    let trace = data
+        .simulate_throw_matching(simulate_options.pc, &frame, &relocation_names, matches)
+        .map_err(|e| format!("Error: {e}"))?;
+    print!("{}", trace.to_string_with_relocations(&relocation_names));
+    Ok(())
+}
 
-            let bytes: &[u8] =
-            if data_size == 4 {
-                &line_val.to_be_bytes()
-            } else {
-                let u16_val: u16 = line_val as u16;
-                &u16_val.to_be_bytes()
-            };
-            table_bytes.extend_from_slice(bytes);
+fn print_usage() {
+    eprintln!("Usage: cwextab-bin [options] <file>");
+    eprintln!("       cwextab-bin [options] --hex <bytes>");
+    eprintln!("       cwextab-bin validate [options] <file>...");
+    eprintln!("       cwextab-bin simulate --pc <n> [options] <file>");
+    eprintln!("       cwextab-bin diff [options] <file> <file>");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --format text|json|yaml|source  Output format (default: text)");
+    eprintln!("  --input asm|binary|hex|xxd      Input format (default: asm)");
+    eprintln!("                                  (asm reads .word as 2 bytes, as on PowerPC)");
+    eprintln!("  --offset <n>                    Offset of the table in the input");
+    eprintln!("  --length <n>                    Size of the table in the input");
+    eprintln!("  --map <file>                    CodeWarrior linker map to name symbols from");
+    eprintln!("  --symbols <file>                decomp-toolkit symbols.txt to name symbols from");
+    eprintln!();
+    eprintln!("Simulate options:");
+    eprintln!("  --pc <n>                        PC the exception is thrown at");
+    eprintln!("  --thrown-type <n|symbol>        Type thrown (default: only catch (...) matches)");
+    eprintln!("  --cond <rN|offset>=<0|1>        Value of a condition flag (default: 1)");
+    eprintln!();
+    eprintln!("Use - as the file to read from stdin.");
+}
+
+/// Removes the given option and its value from the arguments, returning the value.
+/// If the option is given more than once, the last value is used.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let mut value: Option<String> = None;
+    while let Some(index) = args.iter().position(|arg| arg == name) {
+        args.remove(index);
+        if index >= args.len() {
+            return Err(format!("Error: Missing value for {name}"));
+        }
+        value = Some(args.remove(index));
+    }
+    Ok(value)
+}
+
+/// Removes every occurrence of the given option and its value from the arguments,
+/// returning the values in order.
+fn take_options(args: &mut Vec<String>, name: &str) -> Result<Vec<String>, String> {
+    let mut values: Vec<String> = vec![];
+    while let Some(index) = args.iter().position(|arg| arg == name) {
+        args.remove(index);
+        if index >= args.len() {
+            return Err(format!("Error: Missing value for {name}"));
         }
+        values.push(args.remove(index));
+    }
+    Ok(values)
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Options given on the command line.
+struct Options {
+    format: Format,
+    input: InputOptions,
+    hex: Option<String>,
+    symbols: Option<SymbolMap>,
+    simulate: Option<SimulateOptions>,
+}
 
-        test_decode(&table_bytes, func_names);
+/// Reads the symbols from the given CodeWarrior map or decomp-toolkit symbols.txt file.
+fn read_symbols(path: &str, is_cw_map: bool) -> Result<SymbolMap, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Error: Failed to open file \"{path}\": {e}"))?;
+    if is_cw_map {
+        Ok(SymbolMap::from_cw_map(&text))
     } else {
-        println!("Usage: cwextab-bin <file>");
+        SymbolMap::from_symbols_txt(&text).map_err(|e| format!("Error: {path}: {e}"))
+    }
+}
+
+fn parse_args(args: &mut Vec<String>) -> Result<Options, String> {
+    let format = match take_option(args, "--format")? {
+        Some(name) => Format::from_name(&name)
+            .ok_or("Error: Invalid format, must be text, json, yaml or source")?,
+        None => Format::Text,
+    };
+    let input_format = match take_option(args, "--input")? {
+        Some(name) => InputFormat::from_name(&name)
+            .ok_or("Error: Invalid input format, must be asm, binary, hex or xxd")?,
+        None => InputFormat::Asm,
+    };
+    let offset = match take_option(args, "--offset")? {
+        Some(value) => parse_number(&value).ok_or("Error: Invalid offset")?,
+        None => 0,
+    };
+    let length = match take_option(args, "--length")? {
+        Some(value) => Some(parse_number(&value).ok_or("Error: Invalid length")?),
+        None => None,
+    };
+    let hex = take_option(args, "--hex")?;
+    let symbols = match (take_option(args, "--map")?, take_option(args, "--symbols")?) {
+        (Some(_), Some(_)) => {
+            return Err(String::from(
+                "Error: Only one of --map and --symbols can be given",
+            ))
+        }
+        (Some(path), None) => Some(read_symbols(&path, true)?),
+        (None, Some(path)) => Some(read_symbols(&path, false)?),
+        (None, None) => None,
+    };
+
+    let pc = match take_option(args, "--pc")? {
+        Some(value) => Some(parse_number(&value).ok_or("Error: Invalid PC")?),
+        None => None,
+    };
+    let thrown_type = take_option(args, "--thrown-type")?.map(|value| match parse_number(&value) {
+        Some(address) => ThrownType::Address(address),
+        None => ThrownType::Name(value),
+    });
+    let mut conditions: Vec<(ConditionSlot, bool)> = vec![];
+    for value in take_options(args, "--cond")? {
+        let invalid = || format!("Error: Invalid condition \"{value}\", must be <rN|offset>=<0|1>");
+        let (slot, flag) = value.split_once('=').ok_or_else(invalid)?;
+        let slot = match slot.strip_prefix('r') {
+            Some(register) => ConditionSlot::Register(register.parse().map_err(|_| invalid())?),
+            None => ConditionSlot::Stack(parse_number(slot).ok_or_else(invalid)?),
+        };
+        let flag = match flag {
+            "0" => false,
+            "1" => true,
+            _ => return Err(invalid()),
+        };
+        conditions.push((slot, flag));
+    }
+    let simulate = pc.map(|pc| SimulateOptions {
+        pc,
+        thrown_type,
+        conditions,
+    });
+
+    let input = InputOptions {
+        format: input_format,
+        offset,
+        length,
+    };
+    Ok(Options {
+        format,
+        input,
+        hex,
+        symbols,
+        simulate,
+    })
+}
+
+/// Decodes and prints the table in the given input, in the range given by the options.
+fn decode_input(input: Result<TableInput, String>, options: &Options) -> Result<(), String> {
+    let (table_bytes, relocation_names) = options.input.select_range(input?)?;
+    test_decode(
+        &table_bytes,
+        relocation_names,
+        options.format,
+        options.symbols.as_ref(),
+    )
+}
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    //Parse the options
+    let options = match parse_args(&mut args) {
+        Ok(val) => val,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match args.first().map(String::as_str) {
+        Some("validate") if args.len() > 1 => match validate(&args[1..], &options.input) {
+            true => Ok(()),
+            false => return ExitCode::FAILURE,
+        },
+        Some("diff") if args.len() == 3 => {
+            match diff(&args[1], &args[2], &options.input, options.symbols.as_ref()) {
+                Ok(true) => Ok(()),
+                Ok(false) => return ExitCode::FAILURE,
+                Err(e) => Err(e),
+            }
+        }
+        Some("simulate") if args.len() == 2 => match &options.simulate {
+            Some(simulate_options) => simulate(
+                &args[1],
+                &options.input,
+                options.symbols.as_ref(),
+                simulate_options,
+            ),
+            None => Err(String::from(
+                "Error: simulate needs the PC to throw at, given with --pc",
+            )),
+        },
+        //Subcommands with the wrong number of files
+        Some("validate" | "diff" | "simulate") => {
+            print_usage();
+            return ExitCode::FAILURE;
+        }
+        //Only one table is decoded at a time
+        Some(_) if args.len() > 1 || options.hex.is_some() => {
+            print_usage();
+            return ExitCode::FAILURE;
+        }
+        Some(path) => decode_input(input::read_table(path, options.input.format), &options),
+        None => match &options.hex {
+            Some(hex) => {
+                let input = input::parse_hex(hex).map(|data| (data, HashMap::new()));
+                decode_input(input, &options)
+            }
+            None => {
+                print_usage();
+                Ok(())
+            }
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        return ExitCode::FAILURE;
     }
+    ExitCode::SUCCESS
 }
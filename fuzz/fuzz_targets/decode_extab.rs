@@ -0,0 +1,31 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use cwextab::*;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    //Decoding must never panic, whatever the input is
+    let table = match decode_extab(data) {
+        Ok(val) => val,
+        Err(_) => return,
+    };
+
+    //Every decoded action must have enough data for its type
+    for action in &table.exception_actions {
+        action
+            .try_get_exaction_data()
+            .expect("Decoded action is missing data");
+    }
+
+    //Decoded tables must encode back to the original bytes
+    let bytes = encode_extab(&table).expect("Failed to encode decoded table");
+    assert_eq!(bytes, data);
+
+    //The text form of the table must parse back to the same bytes
+    let text = table.to_text(&HashMap::new());
+    let (parsed, _) = parse_text(&text).expect("Failed to parse table text");
+    let bytes = encode_extab(&parsed).expect("Failed to encode parsed table");
+    assert_eq!(bytes, data);
+});
@@ -0,0 +1,96 @@
+//! Compares decoding a table into owned data against iterating over a borrowed view.
+//!
+//! Run with `cargo bench -p cwextab`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use cwextab::{decode_extab, ExceptionTable};
+
+/// Allocator counting the number of allocations made.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: u32 = 10_000;
+const NUM_ACTIONS: u32 = 256;
+
+/// Builds a table with a PC action per action, each destroying a local.
+fn build_table() -> Vec<u8> {
+    let mut data: Vec<u8> = vec![];
+    data.extend_from_slice(&0x0008u16.to_be_bytes());
+    data.extend_from_slice(&0u16.to_be_bytes());
+
+    let actions_offset = 4 + NUM_ACTIONS * 8 + 4;
+    for i in 0..NUM_ACTIONS {
+        data.extend_from_slice(&(0x100 + i * 0x10).to_be_bytes());
+        data.extend_from_slice(&4u16.to_be_bytes());
+        data.extend_from_slice(&((actions_offset + i * 8) as u16).to_be_bytes());
+    }
+    data.extend_from_slice(&0u32.to_be_bytes());
+
+    for i in 0..NUM_ACTIONS {
+        data.push(0x82); //DestroyLocal with the end bit set
+        data.push(0);
+        data.extend_from_slice(&((0x8 + i * 4) as u16).to_be_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes());
+    }
+    data
+}
+
+fn run(name: &str, data: &[u8], f: fn(&[u8]) -> usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    //Keep a total of the results, so the decoding isn't optimized out
+    let mut total: usize = 0;
+    for _ in 0..ITERATIONS {
+        total = total.wrapping_add(f(data));
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{name}: {:?} per table, {} allocations per table (total {total})",
+        elapsed / ITERATIONS,
+        allocations / ITERATIONS as usize
+    );
+}
+
+fn decode_owned(data: &[u8]) -> usize {
+    let table = decode_extab(data).unwrap();
+    table
+        .exception_actions
+        .iter()
+        .map(|action| action.try_get_exaction_data().unwrap().data_size() as usize)
+        .sum()
+}
+
+fn decode_view(data: &[u8]) -> usize {
+    let table = ExceptionTable::parse(data).unwrap();
+    table
+        .actions()
+        .map(|action| action.unwrap().try_get_exaction_data().unwrap().data_size() as usize)
+        .sum()
+}
+
+fn main() {
+    let data = build_table();
+    println!("Table with {NUM_ACTIONS} actions, {} bytes", data.len());
+    run("decode_extab", &data, decode_owned);
+    run("ExceptionTable", &data, decode_view);
+}
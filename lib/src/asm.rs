@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::{encode_extab, ExceptionTableData, ExtabEncodeError};
+
+/// Struct for the extabindex entry written along with a table.
+#[derive(Debug, Clone)]
+pub struct AsmIndexEntry {
+    /// Name of the entry's symbol. decomp-toolkit names these `@eti_<address>`.
+    pub symbol: String,
+    pub function_symbol: String,
+    pub function_size: u32,
+}
+
+/// Options for `ExceptionTableData::to_asm`.
+#[derive(Debug, Clone)]
+pub struct AsmOptions {
+    /// Name of the table's symbol. decomp-toolkit names these `@etb_<address>`.
+    pub table_symbol: String,
+    /// The extabindex entry pointing to the table. No entry is written if `None`.
+    pub index_entry: Option<AsmIndexEntry>,
+    /// Whether to write a label before every action, named after the table symbol
+    /// and the action offset.
+    pub action_labels: bool,
+}
+
+impl AsmOptions {
+    pub fn new(table_symbol: &str) -> Self {
+        Self {
+            table_symbol: String::from(table_symbol),
+            index_entry: None,
+            action_labels: false,
+        }
+    }
+}
+
+/// Returns the symbol name as written in assembly, quoting it if it has characters
+/// which aren't allowed in plain symbol names.
+fn asm_symbol(name: &str) -> String {
+    let is_plain = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$');
+    if is_plain {
+        String::from(name)
+    } else {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Returns a relocation name as written in assembly. Names of relocations with an
+/// addend end with it (such as `sym+0x10`), which is kept outside of the quotes.
+fn asm_reference(name: &str) -> String {
+    let addend_start = name.rfind(['+', '-']).filter(|&index| {
+        let addend = &name[index + 1..];
+        index > 0
+            && addend.len() > 2
+            && (addend.starts_with("0x") || addend.starts_with("0X"))
+            && addend[2..].chars().all(|c| c.is_ascii_hexdigit())
+    });
+    match addend_start {
+        Some(index) => format!("{}{}", asm_symbol(&name[..index]), &name[index..]),
+        None => asm_symbol(name),
+    }
+}
+
+/// Writes the start of a local object, as done by decomp-toolkit.
+fn write_obj_start(out: &mut String, symbol: &str) {
+    let _ = writeln!(out, ".obj {symbol}, local");
+    let _ = writeln!(out, ".hidden {symbol}");
+}
+
+impl ExceptionTableData {
+    /// Converts the table into assembly in the dialect used by decomp-toolkit projects,
+    /// with an `extab` section holding the table and, if set in the options, an
+    /// `extabindex` section holding its index entry.
+    ///
+    /// The table is written as `.4byte` and `.2byte` directives. Symbols from the given
+    /// relocation names, which are keyed by their offset in the table, are written in
+    /// place of the values they are relocated into.
+    pub fn to_asm(
+        &self,
+        relocation_names: &HashMap<u32, String>,
+        options: &AsmOptions,
+    ) -> Result<String, ExtabEncodeError> {
+        let bytes = encode_extab(self)?;
+        let table_symbol = asm_symbol(&options.table_symbol);
+        let mut out = String::new();
+
+        out += ".section extab, \"a\"\n\n";
+        write_obj_start(&mut out, &table_symbol);
+
+        //Split the data into words, or halfwords where a word would cover the start
+        //of a symbol reference or an action
+        let starts_label = |offset: u32| -> bool {
+            options.action_labels && self.action_index_at_offset(offset).is_some()
+        };
+        let splits = |offset: u32| -> bool {
+            relocation_names.contains_key(&offset) || starts_label(offset)
+        };
+
+        let length = bytes.len() as u32;
+        let mut offset: u32 = 0;
+        while offset < length {
+            if starts_label(offset) {
+                let label = format!("{}_action_{offset:X}", options.table_symbol);
+                let _ = writeln!(out, "{}:", asm_symbol(&label));
+            }
+
+            let remaining = length - offset;
+            let index = offset as usize;
+            if let Some(name) = relocation_names.get(&offset) {
+                let _ = writeln!(out, "\t.4byte {}", asm_reference(name));
+                offset += 4;
+            } else if remaining >= 4 && !(1..4).any(|i| splits(offset + i)) {
+                let value = u32::from_be_bytes(bytes[index..index + 4].try_into().unwrap());
+                let _ = writeln!(out, "\t.4byte 0x{value:08X}");
+                offset += 4;
+            } else if remaining >= 2 && !splits(offset + 1) {
+                let value = u16::from_be_bytes([bytes[index], bytes[index + 1]]);
+                let _ = writeln!(out, "\t.2byte 0x{value:04X}");
+                offset += 2;
+            } else {
+                let _ = writeln!(out, "\t.byte 0x{:02X}", bytes[index]);
+                offset += 1;
+            }
+        }
+
+        let _ = writeln!(out, ".endobj {table_symbol}");
+
+        if let Some(entry) = &options.index_entry {
+            let symbol = asm_symbol(&entry.symbol);
+            out += "\n.section extabindex, \"a\"\n\n";
+            write_obj_start(&mut out, &symbol);
+            let _ = writeln!(out, "\t.4byte {}", asm_symbol(&entry.function_symbol));
+            let _ = writeln!(out, "\t.4byte 0x{:08X}", entry.function_size);
+            let _ = writeln!(out, "\t.4byte {table_symbol}");
+            let _ = writeln!(out, ".endobj {symbol}");
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_extab;
+
+    #[test]
+    fn addend_kept_outside_quotes() {
+        assert_eq!(asm_reference("__dt__3FooFv"), "__dt__3FooFv");
+        assert_eq!(asm_reference("@1234+0x10"), "\"@1234\"+0x10");
+        assert_eq!(asm_reference("sym-0x8"), "sym-0x8");
+        assert_eq!(asm_reference("+0x10"), "\"+0x10\"");
+    }
+
+    #[test]
+    fn relocation_with_addend() {
+        //Range 0x10-0x20 going to a single destroy_local action
+        let bytes = [
+            0x18, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x04, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x82, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let table = decode_extab(&bytes).unwrap();
+        let names = HashMap::from([(0x14, String::from("@stringBase0+0x10"))]);
+        let asm = table.to_asm(&names, &AsmOptions::new("@etb_0")).unwrap();
+        assert!(asm.contains("\t.4byte \"@stringBase0\"+0x10\n"), "{asm}");
+    }
+}
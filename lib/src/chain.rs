@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::{ExAction, ExActionData, ExceptionAction, ExceptionTableData, PCAction};
+
+#[derive(Error, Debug)]
+pub enum ActionChainError {
+    #[error("Action offset 0x{0:X} does not point to the start of an action")]
+    InvalidActionOffset(u32),
+    #[error("Action chain loops back to the action at offset 0x{0:X}")]
+    Cycle(u32),
+    #[error("Action at offset 0x{0:X} is the last action but has no end bit")]
+    MissingEndBit(u32),
+    #[error("Invalid data for action at offset 0x{0:X}")]
+    InvalidActionData(u32),
+}
+
+impl ExceptionTableData {
+    /// Returns the index of the action starting at the given table offset, if any.
+    pub fn action_index_at_offset(&self, offset: u32) -> Option<usize> {
+        self.exception_actions
+            .binary_search_by_key(&offset, |action| action.action_offset)
+            .ok()
+    }
+
+    /// Returns the PC action whose range contains the given PC, if any.
+    pub fn find_pc_action(&self, pc: u32) -> Option<&PCAction> {
+        self.pc_actions
+            .iter()
+            .find(|pcaction| pcaction.start_pc <= pc && pc < pcaction.end_pc)
+    }
+
+    /// Returns the actions the runtime goes through when unwinding from the action at
+    /// the given table offset, in order. The chain ends at the first action with the end
+    /// bit set or at a null action, and follows branches to their targets (the branch
+    /// actions themselves are included).
+    ///
+    /// An offset of zero gives an empty chain, as it is used by PC actions without any
+    /// actions to run.
+    pub fn action_chain(
+        &self,
+        action_offset: u32,
+    ) -> Result<Vec<&ExceptionAction>, ActionChainError> {
+        let mut chain: Vec<&ExceptionAction> = vec![];
+        if action_offset == 0 {
+            return Ok(chain);
+        }
+
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut offset = action_offset;
+
+        loop {
+            let index = self
+                .action_index_at_offset(offset)
+                .ok_or(ActionChainError::InvalidActionOffset(offset))?;
+            if !visited.insert(offset) {
+                return Err(ActionChainError::Cycle(offset));
+            }
+
+            let action = &self.exception_actions[index];
+            chain.push(action);
+
+            if let ExAction::EndOfList = action.action_type {
+                break;
+            }
+            if action.has_end_bit {
+                break;
+            }
+
+            offset = match action.action_type {
+                ExAction::Branch => match action.try_get_exaction_data() {
+                    Ok(ExActionData::Branch { target_offset }) => target_offset as u32,
+                    _ => return Err(ActionChainError::InvalidActionData(offset)),
+                },
+                _ => match self.exception_actions.get(index + 1) {
+                    Some(next_action) => next_action.action_offset,
+                    None => return Err(ActionChainError::MissingEndBit(offset)),
+                },
+            };
+        }
+
+        Ok(chain)
+    }
+
+    /// Returns the actions the runtime goes through for the given PC action's range.
+    pub fn pc_action_chain(
+        &self,
+        pcaction: &PCAction,
+    ) -> Result<Vec<&ExceptionAction>, ActionChainError> {
+        self.action_chain(pcaction.action_offset)
+    }
+
+    /// Returns the actions the runtime goes through when an exception is thrown at the
+    /// given PC. PCs outside of every range give an empty chain.
+    pub fn action_chain_at_pc(&self, pc: u32) -> Result<Vec<&ExceptionAction>, ActionChainError> {
+        match self.find_pc_action(pc) {
+            Some(pcaction) => self.pc_action_chain(pcaction),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decode_extab, ActionChainError, ExAction};
+
+    /// Table whose first PC range runs through a branch to the end of the list, and
+    /// whose second PC range starts at a pair of branches to each other.
+    #[rustfmt::skip]
+    const BRANCH_TABLE: [u8; 40] = [
+        0x00, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x04, 0x00, 0x18, //PC range
+        0x00, 0x00, 0x00, 0x20, 0x00, 0x04, 0x00, 0x1C, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x01, 0x00, 0x00, 0x24, //Branch to the end of the list
+        0x01, 0x00, 0x00, 0x20, //Branch to the next branch
+        0x01, 0x00, 0x00, 0x1C, //Branch back to the previous branch
+        0x80, 0x00, 0x00, 0x00, //End of list, padding
+    ];
+
+    #[test]
+    fn follows_branches() {
+        let table = decode_extab(&BRANCH_TABLE).unwrap();
+        let chain = table.action_chain_at_pc(0x10).unwrap();
+        let offsets: Vec<u32> = chain.iter().map(|action| action.action_offset).collect();
+        assert_eq!(offsets, [0x18, 0x24]);
+        assert!(matches!(chain[1].action_type, ExAction::EndOfList));
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let table = decode_extab(&BRANCH_TABLE).unwrap();
+        assert!(matches!(
+            table.action_chain_at_pc(0x20),
+            Err(ActionChainError::Cycle(0x1C))
+        ));
+    }
+
+    #[test]
+    fn rejects_offsets_inside_actions() {
+        let table = decode_extab(&BRANCH_TABLE).unwrap();
+        assert!(matches!(
+            table.action_chain(0x1E),
+            Err(ActionChainError::InvalidActionOffset(0x1E))
+        ));
+    }
+
+    #[test]
+    fn empty_chain_outside_of_ranges() {
+        let table = decode_extab(&BRANCH_TABLE).unwrap();
+        assert!(table.action_chain_at_pc(0x100).unwrap().is_empty());
+        assert!(table.action_chain(0).unwrap().is_empty());
+    }
+}
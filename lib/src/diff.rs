@@ -0,0 +1,688 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{
+    ActionOperands, ExAction, ExceptionAction, ExceptionTableData, PCAction, Relocation,
+    UNKNOWN_FLAG_BITS,
+};
+
+/// Enum for the differences found by `ExceptionTableData::diff`. Actions are given by
+/// their offset in each table.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TableDifference {
+    /// A header flag or field has a different value.
+    FlagChanged { flag: String, left: u32, right: u32 },
+    /// The PC range is only in the right table.
+    PCRangeAdded { start_pc: u32, end_pc: u32 },
+    /// The PC range is only in the left table.
+    PCRangeRemoved { start_pc: u32, end_pc: u32 },
+    /// The PC range covers different instructions.
+    PCRangeMoved {
+        left_start_pc: u32,
+        left_end_pc: u32,
+        right_start_pc: u32,
+        right_end_pc: u32,
+    },
+    /// The PC range starts its action chain at actions which don't correspond.
+    PCRangeActionChanged {
+        start_pc: u32,
+        left_action_offset: u32,
+        right_action_offset: u32,
+    },
+    /// The action is only in the right table.
+    ActionAdded {
+        right_offset: u32,
+        action_type: ExAction,
+    },
+    /// The action is only in the left table.
+    ActionRemoved {
+        left_offset: u32,
+        action_type: ExAction,
+    },
+    /// The action has a different type.
+    ActionTypeChanged {
+        left_offset: u32,
+        right_offset: u32,
+        left: ExAction,
+        right: ExAction,
+    },
+    /// An operand of the action has a different value.
+    OperandChanged {
+        left_offset: u32,
+        right_offset: u32,
+        operand: String,
+        left: String,
+        right: String,
+    },
+    /// The branch goes to actions which don't correspond.
+    BranchTargetChanged {
+        left_offset: u32,
+        right_offset: u32,
+        left_target: u32,
+        right_target: u32,
+    },
+    /// The action has the end bit set in only one of the tables.
+    EndBitChanged {
+        left_offset: u32,
+        right_offset: u32,
+        left: bool,
+        right: bool,
+    },
+    /// The action references a different dtor or type.
+    SymbolChanged {
+        left_offset: u32,
+        right_offset: u32,
+        left: String,
+        right: String,
+    },
+}
+
+impl fmt::Display for TableDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableDifference::FlagChanged { flag, left, right } => {
+                write!(f, "Flag {flag} changed from {left} to {right}")
+            }
+            TableDifference::PCRangeAdded { start_pc, end_pc } => {
+                write!(f, "PC range {start_pc:08X}-{end_pc:08X} added")
+            }
+            TableDifference::PCRangeRemoved { start_pc, end_pc } => {
+                write!(f, "PC range {start_pc:08X}-{end_pc:08X} removed")
+            }
+            TableDifference::PCRangeMoved {
+                left_start_pc,
+                left_end_pc,
+                right_start_pc,
+                right_end_pc,
+            } => write!(
+                f,
+                "PC range {left_start_pc:08X}-{left_end_pc:08X} changed to {right_start_pc:08X}-{right_end_pc:08X}"
+            ),
+            TableDifference::PCRangeActionChanged {
+                start_pc,
+                left_action_offset,
+                right_action_offset,
+            } => write!(
+                f,
+                "PC range starting at {start_pc:08X} goes to action {left_action_offset:06X} on the left but {right_action_offset:06X} on the right"
+            ),
+            TableDifference::ActionAdded {
+                right_offset,
+                action_type,
+            } => write!(
+                f,
+                "Action {right_offset:06X} ({}) added",
+                action_type.convert_to_string()
+            ),
+            TableDifference::ActionRemoved {
+                left_offset,
+                action_type,
+            } => write!(
+                f,
+                "Action {left_offset:06X} ({}) removed",
+                action_type.convert_to_string()
+            ),
+            TableDifference::ActionTypeChanged {
+                left_offset,
+                right_offset,
+                left,
+                right,
+            } => write!(
+                f,
+                "Action {left_offset:06X}/{right_offset:06X} changed from {} to {}",
+                left.convert_to_string(),
+                right.convert_to_string()
+            ),
+            TableDifference::OperandChanged {
+                left_offset,
+                right_offset,
+                operand,
+                left,
+                right,
+            } => write!(
+                f,
+                "Action {left_offset:06X}/{right_offset:06X}: {operand} changed from {left} to {right}"
+            ),
+            TableDifference::BranchTargetChanged {
+                left_offset,
+                right_offset,
+                left_target,
+                right_target,
+            } => write!(
+                f,
+                "Action {left_offset:06X}/{right_offset:06X}: branch goes to {left_target:06X} on the left but {right_target:06X} on the right"
+            ),
+            TableDifference::EndBitChanged {
+                left_offset,
+                right_offset,
+                left,
+                right,
+            } => {
+                let end_bit = |set: &bool| if *set { "set" } else { "not set" };
+                write!(
+                    f,
+                    "Action {left_offset:06X}/{right_offset:06X}: end bit {} on the left but {} on the right",
+                    end_bit(left),
+                    end_bit(right)
+                )
+            }
+            TableDifference::SymbolChanged {
+                left_offset,
+                right_offset,
+                left,
+                right,
+            } => write!(
+                f,
+                "Action {left_offset:06X}/{right_offset:06X}: references {left} on the left but {right} on the right"
+            ),
+        }
+    }
+}
+
+/// Aligns the items of two lists in order, matching the items which maximize the total
+/// score of the matches. A score of zero means the items can't be matched. Unmatched
+/// items between two matches are paired up in order, and the rest are given on their
+/// own.
+fn align<T, F>(left: &[T], right: &[T], score: F) -> Vec<(Option<usize>, Option<usize>)>
+where
+    F: Fn(&T, &T) -> u32,
+{
+    //best[i][j] is the best total score for the items from left[i] and right[j] on
+    let mut best = vec![vec![0u32; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            let mut value = best[i + 1][j].max(best[i][j + 1]);
+            let item_score = score(&left[i], &right[j]);
+            if item_score > 0 {
+                value = value.max(best[i + 1][j + 1] + item_score);
+            }
+            best[i][j] = value;
+        }
+    }
+
+    let mut pairs: Vec<(Option<usize>, Option<usize>)> = vec![];
+    let mut gap_left: Vec<usize> = vec![];
+    let mut gap_right: Vec<usize> = vec![];
+    fn flush_gap(
+        pairs: &mut Vec<(Option<usize>, Option<usize>)>,
+        gap_left: &mut Vec<usize>,
+        gap_right: &mut Vec<usize>,
+    ) {
+        for k in 0..gap_left.len().max(gap_right.len()) {
+            pairs.push((gap_left.get(k).copied(), gap_right.get(k).copied()));
+        }
+        gap_left.clear();
+        gap_right.clear();
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        let item_score = match i < left.len() && j < right.len() {
+            true => score(&left[i], &right[j]),
+            false => 0,
+        };
+        if item_score > 0 && best[i][j] == best[i + 1][j + 1] + item_score {
+            flush_gap(&mut pairs, &mut gap_left, &mut gap_right);
+            pairs.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j >= right.len() || (i < left.len() && best[i + 1][j] >= best[i][j + 1]) {
+            gap_left.push(i);
+            i += 1;
+        } else {
+            gap_right.push(j);
+            j += 1;
+        }
+    }
+    flush_gap(&mut pairs, &mut gap_left, &mut gap_right);
+
+    pairs
+}
+
+/// Returns the operands of an action which are compared, leaving out the relocated
+/// values and branch targets.
+fn operand_fields(operands: &ActionOperands) -> Vec<(&'static str, String)> {
+    match operands {
+        ActionOperands::EndOfList | ActionOperands::Branch { .. } | ActionOperands::Terminate => {
+            vec![]
+        }
+        ActionOperands::DestroyLocal { local, .. } => vec![("local", local.to_string())],
+        ActionOperands::DestroyLocalCond {
+            condition, local, ..
+        } => vec![
+            ("condition", condition.to_string()),
+            ("local", local.to_string()),
+        ],
+        ActionOperands::DestroyLocalPointer { pointer, .. }
+        | ActionOperands::DeletePointer { pointer, .. } => vec![("pointer", pointer.to_string())],
+        ActionOperands::DestroyLocalArray {
+            array,
+            elements,
+            element_size,
+            ..
+        } => vec![
+            ("array", array.to_string()),
+            ("elements", elements.to_string()),
+            ("element size", element_size.to_string()),
+        ],
+        ActionOperands::DestroyBase { member, .. }
+        | ActionOperands::DestroyMember { member, .. } => {
+            vec![("member", member.to_string())]
+        }
+        ActionOperands::DestroyMemberCond {
+            condition, member, ..
+        } => vec![
+            ("condition", condition.to_string()),
+            ("member", member.to_string()),
+        ],
+        ActionOperands::DestroyMemberArray {
+            member,
+            elements,
+            element_size,
+            ..
+        } => vec![
+            ("member", member.to_string()),
+            ("elements", elements.to_string()),
+            ("element size", element_size.to_string()),
+        ],
+        ActionOperands::DeletePointerCond {
+            condition, pointer, ..
+        } => vec![
+            ("condition", condition.to_string()),
+            ("pointer", pointer.to_string()),
+        ],
+        ActionOperands::CatchBlock {
+            catch_pc_offset,
+            catch_info,
+            ..
+        } => vec![
+            ("handler PC", format!("{catch_pc_offset:08X}")),
+            ("catch info", catch_info.to_string()),
+        ],
+        ActionOperands::CatchBlock32 {
+            catch_pc_offset,
+            catch_info,
+            ..
+        } => vec![
+            ("handler PC", format!("{catch_pc_offset:08X}")),
+            ("catch info", catch_info.to_string()),
+        ],
+        ActionOperands::ActiveCatchBlock { catch_info } => {
+            vec![("catch info", catch_info.to_string())]
+        }
+        ActionOperands::Specification {
+            pc_offset,
+            catch_info,
+            spec,
+        } => vec![
+            ("handler PC", format!("{pc_offset:08X}")),
+            ("catch info", catch_info.to_string()),
+            ("types", spec.len().to_string()),
+        ],
+    }
+}
+
+/// Action of one of the tables, along with what is compared.
+struct DiffAction<'a> {
+    action: &'a ExceptionAction,
+    operands: Option<ActionOperands>,
+    fields: Vec<(&'static str, String)>,
+    //Names of the referenced symbols. Null catch types without a name are `catch (...)`,
+    //and other references without a name are only known by address and aren't compared
+    symbols: Vec<Option<String>>,
+}
+
+impl<'a> DiffAction<'a> {
+    fn new(
+        table: &ExceptionTableData,
+        action: &'a ExceptionAction,
+        references: &[Relocation],
+        names: &HashMap<u32, String>,
+    ) -> Self {
+        let operands = action
+            .try_get_exaction_data()
+            .ok()
+            .map(|data| ActionOperands::from_data(data, action.action_param, table.frame_base()));
+        let fields = operands.as_ref().map(operand_fields).unwrap_or_default();
+        let is_catch = matches!(
+            operands,
+            Some(ActionOperands::CatchBlock { .. }) | Some(ActionOperands::CatchBlock32 { .. })
+        );
+        let start = action.action_offset;
+        let end = start + 2 + action.bytes.len() as u32;
+        let symbols = references
+            .iter()
+            .filter(|reference| start <= reference.offset && reference.offset < end)
+            .map(|reference| match names.get(&reference.offset) {
+                Some(name) => Some(name.clone()),
+                None if is_catch && reference.address == 0 => Some(String::from("...")),
+                None => None,
+            })
+            .collect();
+        Self {
+            action,
+            operands,
+            fields,
+            symbols,
+        }
+    }
+
+    /// Actions of the same type can be aligned, preferring the ones with the same
+    /// operands.
+    fn score(&self, other: &DiffAction) -> u32 {
+        if self.action.action_type.to_int() != other.action.action_type.to_int() {
+            0
+        } else if self.fields != other.fields {
+            1
+        } else if self.symbols != other.symbols {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+fn diff_actions<'a>(
+    table: &'a ExceptionTableData,
+    names: &HashMap<u32, String>,
+) -> Vec<DiffAction<'a>> {
+    let references = table.symbol_references();
+    table
+        .exception_actions
+        .iter()
+        .map(|action| DiffAction::new(table, action, &references, names))
+        .collect()
+}
+
+impl ExceptionTableData {
+    /// Compares the table with another one, returning the differences in their
+    /// structure: header flags, PC ranges, and the type, operands, branches and
+    /// referenced symbols of their actions.
+    ///
+    /// The actions of the tables are aligned, so that an added or removed action is
+    /// reported once rather than as a change to every action after it. Relocated values
+    /// are compared by the names given for each table, keyed by their offset in the
+    /// table, and differences between their addresses are ignored.
+    pub fn diff(
+        &self,
+        names: &HashMap<u32, String>,
+        other: &ExceptionTableData,
+        other_names: &HashMap<u32, String>,
+    ) -> Vec<TableDifference> {
+        let mut differences: Vec<TableDifference> = vec![];
+
+        //Compare the header
+        let flags = |table: &ExceptionTableData| {
+            [
+                ("has_elf_vector", table.has_elf_vector as u32),
+                ("large_frame", table.large_frame as u32),
+                ("has_frame_pointer", table.has_frame_pointer as u32),
+                ("saved_cr", table.saved_cr as u32),
+                ("fpr_save_range", table.fpr_save_range),
+                ("gpr_save_range", table.gpr_save_range),
+                ("unknown_bits", (table.flag_val & UNKNOWN_FLAG_BITS) as u32),
+                ("et_field", table.et_field as u32),
+            ]
+        };
+        for ((flag, left), (_, right)) in flags(self).into_iter().zip(flags(other)) {
+            if left != right {
+                differences.push(TableDifference::FlagChanged {
+                    flag: String::from(flag),
+                    left,
+                    right,
+                });
+            }
+        }
+
+        //Align the actions, which the PC ranges and branches are compared through
+        let left_actions = diff_actions(self, names);
+        let right_actions = diff_actions(other, other_names);
+        let action_pairs = align(&left_actions, &right_actions, DiffAction::score);
+        let mut right_index_of: HashMap<usize, usize> = HashMap::new();
+        for pair in &action_pairs {
+            if let (Some(i), Some(j)) = pair {
+                right_index_of.insert(*i, *j);
+            }
+        }
+        //Returns whether the actions at the given offsets were aligned with each other
+        let corresponds = |left_offset: u32, right_offset: u32| {
+            let left_index = self.action_index_at_offset(left_offset);
+            let right_index = other.action_index_at_offset(right_offset);
+            match (left_index, right_index) {
+                (Some(i), Some(j)) => right_index_of.get(&i) == Some(&j),
+                _ => left_offset == right_offset,
+            }
+        };
+
+        //Compare the PC ranges
+        //Ranges which overlap can be aligned, preferring the ones which cover the same PCs
+        let range_pairs = align(&self.pc_actions, &other.pc_actions, |a: &PCAction, b| {
+            if a.start_pc == b.start_pc && a.end_pc == b.end_pc {
+                2
+            } else if a.start_pc < b.end_pc && b.start_pc < a.end_pc {
+                1
+            } else {
+                0
+            }
+        });
+        for pair in range_pairs {
+            match pair {
+                (Some(i), Some(j)) => {
+                    let (left, right) = (&self.pc_actions[i], &other.pc_actions[j]);
+                    if left.start_pc != right.start_pc || left.end_pc != right.end_pc {
+                        differences.push(TableDifference::PCRangeMoved {
+                            left_start_pc: left.start_pc,
+                            left_end_pc: left.end_pc,
+                            right_start_pc: right.start_pc,
+                            right_end_pc: right.end_pc,
+                        });
+                    }
+                    if !corresponds(left.action_offset, right.action_offset) {
+                        differences.push(TableDifference::PCRangeActionChanged {
+                            start_pc: right.start_pc,
+                            left_action_offset: left.action_offset,
+                            right_action_offset: right.action_offset,
+                        });
+                    }
+                }
+                (Some(i), None) => differences.push(TableDifference::PCRangeRemoved {
+                    start_pc: self.pc_actions[i].start_pc,
+                    end_pc: self.pc_actions[i].end_pc,
+                }),
+                (None, Some(j)) => differences.push(TableDifference::PCRangeAdded {
+                    start_pc: other.pc_actions[j].start_pc,
+                    end_pc: other.pc_actions[j].end_pc,
+                }),
+                (None, None) => {}
+            }
+        }
+
+        //Compare the actions
+        for pair in action_pairs {
+            let (left, right) = match pair {
+                (Some(i), Some(j)) => (&left_actions[i], &right_actions[j]),
+                (Some(i), None) => {
+                    let action = left_actions[i].action;
+                    differences.push(TableDifference::ActionRemoved {
+                        left_offset: action.action_offset,
+                        action_type: action.action_type,
+                    });
+                    continue;
+                }
+                (None, Some(j)) => {
+                    let action = right_actions[j].action;
+                    differences.push(TableDifference::ActionAdded {
+                        right_offset: action.action_offset,
+                        action_type: action.action_type,
+                    });
+                    continue;
+                }
+                (None, None) => continue,
+            };
+            let left_offset = left.action.action_offset;
+            let right_offset = right.action.action_offset;
+
+            if left.action.action_type.to_int() != right.action.action_type.to_int() {
+                differences.push(TableDifference::ActionTypeChanged {
+                    left_offset,
+                    right_offset,
+                    left: left.action.action_type,
+                    right: right.action.action_type,
+                });
+                continue;
+            }
+
+            for ((operand, left_value), (_, right_value)) in left.fields.iter().zip(&right.fields) {
+                if left_value != right_value {
+                    differences.push(TableDifference::OperandChanged {
+                        left_offset,
+                        right_offset,
+                        operand: String::from(*operand),
+                        left: left_value.clone(),
+                        right: right_value.clone(),
+                    });
+                }
+            }
+
+            if let (
+                Some(ActionOperands::Branch {
+                    target_offset: left_target,
+                }),
+                Some(ActionOperands::Branch {
+                    target_offset: right_target,
+                }),
+            ) = (&left.operands, &right.operands)
+            {
+                if !corresponds(*left_target as u32, *right_target as u32) {
+                    differences.push(TableDifference::BranchTargetChanged {
+                        left_offset,
+                        right_offset,
+                        left_target: *left_target as u32,
+                        right_target: *right_target as u32,
+                    });
+                }
+            }
+
+            if left.action.has_end_bit != right.action.has_end_bit {
+                differences.push(TableDifference::EndBitChanged {
+                    left_offset,
+                    right_offset,
+                    left: left.action.has_end_bit,
+                    right: right.action.has_end_bit,
+                });
+            }
+
+            for (left_symbol, right_symbol) in left.symbols.iter().zip(&right.symbols) {
+                if let (Some(left_symbol), Some(right_symbol)) = (left_symbol, right_symbol) {
+                    if left_symbol != right_symbol {
+                        differences.push(TableDifference::SymbolChanged {
+                            left_offset,
+                            right_offset,
+                            left: left_symbol.clone(),
+                            right: right_symbol.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{align, TableDifference};
+    use crate::decode_extab;
+    use crate::fixtures::DESTROY_LOCALS_TABLE;
+
+    /// Scores equal numbers highest, and numbers with the same parity lower.
+    fn score(left: &u32, right: &u32) -> u32 {
+        match (left, right) {
+            _ if left == right => 2,
+            _ if left % 2 == right % 2 => 1,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn align_equal_lists() {
+        assert_eq!(
+            align(&[1, 2, 3], &[1, 2, 3], score),
+            [(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2))]
+        );
+        assert_eq!(align::<u32, _>(&[], &[], score), []);
+    }
+
+    #[test]
+    fn align_insertions_and_removals() {
+        assert_eq!(
+            align(&[1, 3], &[1, 2, 3], score),
+            [(Some(0), Some(0)), (None, Some(1)), (Some(1), Some(2))]
+        );
+        assert_eq!(
+            align(&[1, 2, 3], &[3], score),
+            [(Some(0), None), (Some(1), None), (Some(2), Some(0))]
+        );
+    }
+
+    #[test]
+    fn align_prefers_higher_scores() {
+        //5 could be matched with 3 by parity, but matching it with the equal item scores
+        //more in total
+        assert_eq!(
+            align(&[3, 5], &[5], score),
+            [(Some(0), None), (Some(1), Some(0))]
+        );
+    }
+
+    #[test]
+    fn align_pairs_unmatched_items() {
+        //Items which can't be matched are paired up in order between matches
+        assert_eq!(
+            align(&[1, 2, 4, 7], &[1, 3, 7], score),
+            [
+                (Some(0), Some(0)),
+                (Some(1), Some(1)),
+                (Some(2), None),
+                (Some(3), Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_tables() {
+        let names = HashMap::new();
+        let left = decode_extab(&DESTROY_LOCALS_TABLE).unwrap();
+        assert!(left.diff(&names, &left, &names).is_empty());
+
+        //Drop the first action, so both ranges start at the second one
+        let mut bytes = DESTROY_LOCALS_TABLE.to_vec();
+        bytes.drain(0x18..0x20);
+        bytes[11] = 0x18;
+        bytes[19] = 0x18;
+        let right = decode_extab(&bytes).unwrap();
+        let differences = left.diff(&names, &right, &names);
+        //The second range still runs the same action, so only the first one changed
+        assert_eq!(differences.len(), 2);
+        assert!(matches!(
+            differences[0],
+            TableDifference::PCRangeActionChanged {
+                start_pc: 0x20,
+                left_action_offset: 0x18,
+                right_action_offset: 0x18,
+                ..
+            }
+        ));
+        assert!(matches!(
+            differences[1],
+            TableDifference::ActionRemoved {
+                left_offset: 0x18,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::section::split_tables;
+use crate::{
+    decode_extab, mem_utils, parse_extabindex, ExceptionTableData, ExtabDecodeError,
+    ExtabIndexEntry, EXTABINDEX_ENTRY_SIZE,
+};
+
+const DOL_HEADER_SIZE: u32 = 0x100;
+const TEXT_SECTION_COUNT: u32 = 7;
+const DATA_SECTION_COUNT: u32 = 11;
+
+/// Size of an `_eti_init_info` record (index start, index end, code start, code size).
+const ETI_INIT_INFO_SIZE: u32 = 16;
+
+#[derive(Error, Debug)]
+pub enum DolExtabError {
+    #[error("DOL file should at least be 256 bytes long. Given file is {0} bytes long.")]
+    FileTooSmall(u32),
+    #[error("DOL section at header offset 0x{0:X} is outside of the file")]
+    InvalidSection(u32),
+    #[error("Address 0x{0:08X} is not mapped by any DOL section")]
+    UnmappedAddress(u32),
+    #[error("_eti_init_info record at 0x{0:08X} has an invalid extabindex range")]
+    InvalidEtiInitInfo(u32),
+    #[error("extabindex entry at 0x{0:08X} does not point into a DOL section")]
+    InvalidTablePointer(u32),
+    #[error("Failed to decode the exception table of the function at 0x{0:08X}: {1}")]
+    Decode(u32, ExtabDecodeError),
+    #[error("Function at 0x{0:08X} has more than one extabindex entry")]
+    DuplicateFunction(u32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DolSectionKind {
+    Text,
+    Data,
+}
+
+/// Struct for a section of a DOL file, which is loaded at the given address.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DolSection {
+    pub kind: DolSectionKind,
+    pub index: u32, //Index among the sections of the same kind
+    pub file_offset: u32,
+    pub address: u32,
+    pub size: u32,
+}
+
+impl DolSection {
+    /// Returns the address right after the end of the section.
+    pub fn end(&self) -> u32 {
+        self.address.wrapping_add(self.size)
+    }
+
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.address && address - self.address < self.size
+    }
+}
+
+/// Struct for a DOL executable, with its sections mapped by address.
+#[derive(Debug, Clone)]
+pub struct Dol<'a> {
+    data: &'a [u8],
+    pub sections: Vec<DolSection>, //Sections with data, in header order
+    pub bss_address: u32,
+    pub bss_size: u32,
+    pub entry_point: u32,
+}
+
+impl<'a> Dol<'a> {
+    /// Parses the header of a DOL file. Empty sections are skipped.
+    pub fn parse(data: &'a [u8]) -> Result<Self, DolExtabError> {
+        if (data.len() as u32) < DOL_HEADER_SIZE {
+            return Err(DolExtabError::FileTooSmall(data.len() as u32));
+        }
+        //The header is checked to be large enough, so these reads can't fail
+        let header =
+            |offset: u32| mem_utils::read_uint32(data, &mut (offset as i32), false).unwrap_or(0);
+
+        let mut sections: Vec<DolSection> = vec![];
+        let kinds = [
+            (DolSectionKind::Text, 0, TEXT_SECTION_COUNT),
+            (DolSectionKind::Data, TEXT_SECTION_COUNT, DATA_SECTION_COUNT),
+        ];
+        for (kind, first, count) in kinds {
+            for index in 0..count {
+                let slot = (first + index) * 4;
+                let file_offset = header(slot);
+                let address = header(0x48 + slot);
+                let size = header(0x90 + slot);
+                if size == 0 {
+                    continue;
+                }
+                let in_file = file_offset
+                    .checked_add(size)
+                    .map_or(false, |end| end as usize <= data.len());
+                if !in_file || address.checked_add(size).is_none() {
+                    return Err(DolExtabError::InvalidSection(slot));
+                }
+                sections.push(DolSection {
+                    kind,
+                    index,
+                    file_offset,
+                    address,
+                    size,
+                });
+            }
+        }
+
+        Ok(Self {
+            data,
+            sections,
+            bss_address: header(0xD8),
+            bss_size: header(0xDC),
+            entry_point: header(0xE0),
+        })
+    }
+
+    /// Returns the section which the given address is loaded into.
+    pub fn section_at(&self, address: u32) -> Option<&DolSection> {
+        self.sections
+            .iter()
+            .find(|section| section.contains(address))
+    }
+
+    /// Returns the data of the given section.
+    pub fn section_data(&self, section: &DolSection) -> &'a [u8] {
+        &self.data[section.file_offset as usize..(section.file_offset + section.size) as usize]
+    }
+
+    /// Returns the data loaded at the given address, if it is all in one section.
+    pub fn read(&self, address: u32, size: u32) -> Option<&'a [u8]> {
+        let section = self.section_at(address)?;
+        let offset = address - section.address;
+        if size > section.size - offset {
+            return None;
+        }
+        Some(&self.section_data(section)[offset as usize..(offset + size) as usize])
+    }
+
+    pub fn read_u32(&self, address: u32) -> Option<u32> {
+        mem_utils::read_uint32(self.read(address, 4)?, &mut 0, false)
+    }
+}
+
+/// Struct for an `_eti_init_info` record, which the CodeWarrior runtime uses to find
+/// the extabindex entries of a range of code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EtiInitInfo {
+    pub eti_start: u32, //Address of the first extabindex entry
+    pub eti_end: u32,   //Address right after the last extabindex entry
+    pub code_start: u32,
+    pub code_size: u32,
+}
+
+/// Reads the `_eti_init_info` records at the given address, up to the record with a
+/// null start address which ends the list.
+pub fn read_eti_init_info(dol: &Dol, address: u32) -> Result<Vec<EtiInitInfo>, DolExtabError> {
+    let mut records: Vec<EtiInitInfo> = vec![];
+
+    let mut record_address = address;
+    loop {
+        let read = |offset: u32| {
+            let field_address = record_address.wrapping_add(offset);
+            dol.read_u32(field_address)
+                .ok_or(DolExtabError::UnmappedAddress(field_address))
+        };
+        let record = EtiInitInfo {
+            eti_start: read(0)?,
+            eti_end: read(4)?,
+            code_start: read(8)?,
+            code_size: read(12)?,
+        };
+        if record.eti_start == 0 {
+            break;
+        }
+        if record.eti_end < record.eti_start
+            || dol
+                .read(record.eti_start, record.eti_end - record.eti_start)
+                .is_none()
+        {
+            return Err(DolExtabError::InvalidEtiInitInfo(record_address));
+        }
+        records.push(record);
+        record_address = record_address.wrapping_add(ETI_INIT_INFO_SIZE);
+    }
+
+    Ok(records)
+}
+
+/// Checks whether the given record looks like a valid `_eti_init_info` record, with
+/// index entries for functions in its code range.
+fn is_eti_init_info(dol: &Dol, record: &EtiInitInfo) -> bool {
+    if record.eti_start % 4 != 0
+        || record.eti_end <= record.eti_start
+        || record.code_size == 0
+        || record.code_start % 4 != 0
+    {
+        return false;
+    }
+    let code_in_text = matches!(
+        dol.section_at(record.code_start),
+        Some(section) if section.kind == DolSectionKind::Text
+            && record.code_size <= section.end() - record.code_start
+    );
+    if !code_in_text {
+        return false;
+    }
+
+    let index = match dol.read(record.eti_start, record.eti_end - record.eti_start) {
+        Some(index) => index,
+        None => return false,
+    };
+    let entries = match parse_extabindex(index) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    let code_end = record.code_start + record.code_size;
+    entries.iter().all(|entry| {
+        (record.code_start..code_end).contains(&entry.function_address)
+            && entry.function_size <= code_end - entry.function_address
+            && entry.extab_address % 4 == 0
+            && dol.section_at(entry.extab_address).is_some()
+    })
+}
+
+/// Scans the sections of the DOL for the `_eti_init_info` records, returning their
+/// address if they are found.
+///
+/// A list of records is accepted if every record points to a range of extabindex
+/// entries whose functions are in the record's code range, and whose table pointers
+/// point into a section.
+pub fn find_eti_init_info(dol: &Dol) -> Option<u32> {
+    for section in &dol.sections {
+        let data = dol.section_data(section);
+        for offset in (0..section.size.saturating_sub(ETI_INIT_INFO_SIZE - 1)).step_by(4) {
+            let read = |field: u32| {
+                mem_utils::read_uint32(data, &mut ((offset + field) as i32), false).unwrap_or(0)
+            };
+            let record = EtiInitInfo {
+                eti_start: read(0),
+                eti_end: read(4),
+                code_start: read(8),
+                code_size: read(12),
+            };
+            if !is_eti_init_info(dol, &record) {
+                continue;
+            }
+
+            //The rest of the list must be valid too, up to the null record
+            let address = section.address + offset;
+            let valid_list = match read_eti_init_info(dol, address) {
+                Ok(records) => records.iter().all(|record| is_eti_init_info(dol, record)),
+                Err(_) => false,
+            };
+            if valid_list {
+                return Some(address);
+            }
+        }
+    }
+
+    None
+}
+
+/// Struct containing the decoded exception table of a function in a DOL file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DolFunctionExtab {
+    pub function_address: u32,
+    pub function_size: u32,
+    pub extab_address: u32, //Address of the table
+    pub data: ExceptionTableData,
+}
+
+/// Struct for the exception tables decoded from a DOL file.
+#[derive(Debug, Default)]
+pub struct DolExtab {
+    pub tables: BTreeMap<u32, DolFunctionExtab>, //Keyed by function address
+    //Errors for the extabindex entries which were skipped, as their table could not be
+    //found or decoded, or their function already had a table
+    pub errors: Vec<DolExtabError>,
+}
+
+/// Decodes the exception tables of every function in the given DOL file.
+///
+/// The extabindex entries are found through the `_eti_init_info` records at the given
+/// address, or by scanning the DOL for them if no address is given. Files without
+/// the records give no tables. Entries whose table can't be decoded are skipped and
+/// reported along with the tables, so that one bad table doesn't hide the others.
+///
+/// Tables are split as with `decode_extab_section`: each one runs until the start of
+/// the next one. The linker places the extabindex entries right after the tables, so
+/// the last table of a section ends where the entries start, or at the end of the
+/// section.
+pub fn decode_dol(data: &[u8], eti_init_info: Option<u32>) -> Result<DolExtab, DolExtabError> {
+    let dol = Dol::parse(data)?;
+    let records = match eti_init_info.or_else(|| find_eti_init_info(&dol)) {
+        Some(address) => read_eti_init_info(&dol, address)?,
+        None => return Ok(DolExtab::default()),
+    };
+    let mut result = DolExtab::default();
+
+    //Parse the index entries of every record, grouped by the section of their tables
+    let mut sections: BTreeMap<u32, (&DolSection, Vec<ExtabIndexEntry>)> = BTreeMap::new();
+    for record in &records {
+        let size = record.eti_end - record.eti_start;
+        //The range is checked to be mapped when reading the records
+        let index = dol.read(record.eti_start, size).unwrap_or_default();
+        let record_entries = parse_extabindex(index)
+            .map_err(|_| DolExtabError::InvalidEtiInitInfo(record.eti_start))?;
+        for (i, entry) in record_entries.into_iter().enumerate() {
+            let entry_address = record.eti_start + (i as u32) * EXTABINDEX_ENTRY_SIZE;
+            match dol.section_at(entry.extab_address) {
+                Some(section) => {
+                    let (_, entries) = sections
+                        .entry(section.address)
+                        .or_insert_with(|| (section, vec![]));
+                    entries.push(entry);
+                }
+                None => result
+                    .errors
+                    .push(DolExtabError::InvalidTablePointer(entry_address)),
+            }
+        }
+    }
+
+    for (section, entries) in sections.values() {
+        let table_addresses = entries.iter().map(|entry| entry.extab_address);
+        let (start, last) = match (table_addresses.clone().min(), table_addresses.max()) {
+            (Some(start), Some(last)) => (start, last),
+            _ => continue,
+        };
+        let end = records
+            .iter()
+            .map(|record| record.eti_start)
+            .filter(|eti_start| *eti_start > last && *eti_start <= section.end())
+            .min()
+            .unwrap_or(section.end());
+        //Both ends are in the section
+        let extab = dol.read(start, end - start).unwrap_or_default();
+        let table_bytes = split_tables(extab, entries.iter().map(|e| e.extab_address - start));
+
+        for entry in entries {
+            if result.tables.contains_key(&entry.function_address) {
+                let error = DolExtabError::DuplicateFunction(entry.function_address);
+                result.errors.push(error);
+                continue;
+            }
+            let data = match decode_extab(table_bytes[&(entry.extab_address - start)]) {
+                Ok(data) => data,
+                Err(e) => {
+                    let error = DolExtabError::Decode(entry.function_address, e);
+                    result.errors.push(error);
+                    continue;
+                }
+            };
+            let table = DolFunctionExtab {
+                function_address: entry.function_address,
+                function_size: entry.function_size,
+                extab_address: entry.extab_address,
+                data,
+            };
+            result.tables.insert(entry.function_address, table);
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_dol, find_eti_init_info, Dol, DolExtabError, DolSectionKind};
+    use crate::fixtures::{DESTROY_LOCAL_TABLE, EMPTY_TABLE};
+
+    const TEXT_ADDRESS: u32 = 0x80003100;
+    const DATA_ADDRESS: u32 = 0x80004000;
+    const ETI_INIT_INFO_ADDRESS: u32 = DATA_ADDRESS + 0x38;
+
+    /// Extabindex entries of the two functions, and the `_eti_init_info` records pointing
+    /// to them.
+    #[rustfmt::skip]
+    const INDEX_AND_RECORDS: [u8; 0x38] = [
+        0x80, 0x00, 0x31, 0x00, 0x00, 0x00, 0x00, 0x10, 0x80, 0x00, 0x40, 0x00, //extabindex
+        0x80, 0x00, 0x31, 0x10, 0x00, 0x00, 0x00, 0x10, 0x80, 0x00, 0x40, 0x08,
+        0x80, 0x00, 0x40, 0x20, 0x80, 0x00, 0x40, 0x38, //_eti_init_info
+        0x80, 0x00, 0x31, 0x00, 0x00, 0x00, 0x00, 0x20,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //Null record
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// Returns the data section, holding the tables of the two functions followed by
+    /// their index entries and records.
+    fn data_section() -> Vec<u8> {
+        [&EMPTY_TABLE[..], &DESTROY_LOCAL_TABLE, &INDEX_AND_RECORDS].concat()
+    }
+
+    /// Builds a DOL with a text section for the functions and the data section above.
+    fn build_dol() -> Vec<u8> {
+        let data_section = data_section();
+        let mut data = vec![0u8; 0x100];
+        let mut set_header = |offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        //Text section 0
+        set_header(0x00, 0x100);
+        set_header(0x48, TEXT_ADDRESS);
+        set_header(0x90, 0x20);
+        //Data section 0
+        set_header(0x1C, 0x120);
+        set_header(0x64, DATA_ADDRESS);
+        set_header(0xAC, data_section.len() as u32);
+        set_header(0xE0, TEXT_ADDRESS);
+
+        data.extend_from_slice(&[0x60, 0x00, 0x00, 0x00].repeat(8));
+        data.extend_from_slice(&data_section);
+        data
+    }
+
+    #[test]
+    fn parse_sections() {
+        let data = build_dol();
+        let dol = Dol::parse(&data).unwrap();
+        assert_eq!(dol.sections.len(), 2);
+        assert_eq!(dol.sections[0].kind, DolSectionKind::Text);
+        assert_eq!(dol.sections[1].kind, DolSectionKind::Data);
+        assert_eq!(dol.sections[1].file_offset, 0x120);
+        assert_eq!(dol.entry_point, TEXT_ADDRESS);
+
+        assert_eq!(dol.read_u32(TEXT_ADDRESS + 0x1C), Some(0x60000000));
+        assert_eq!(dol.read_u32(DATA_ADDRESS + 0x20), Some(TEXT_ADDRESS));
+        //Reads can't run past the end of a section
+        assert_eq!(dol.read(TEXT_ADDRESS + 0x1C, 8), None);
+        assert_eq!(dol.read_u32(DATA_ADDRESS - 4), None);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(matches!(
+            Dol::parse(&[0; 0x80]),
+            Err(DolExtabError::FileTooSmall(0x80))
+        ));
+
+        let mut data = build_dol();
+        //Data section running past the end of the file
+        data[0xAC..0xB0].copy_from_slice(&0x100u32.to_be_bytes());
+        assert!(matches!(
+            Dol::parse(&data),
+            Err(DolExtabError::InvalidSection(0x1C))
+        ));
+    }
+
+    #[test]
+    fn find_records() {
+        let data = build_dol();
+        let dol = Dol::parse(&data).unwrap();
+        assert_eq!(find_eti_init_info(&dol), Some(ETI_INIT_INFO_ADDRESS));
+    }
+
+    #[test]
+    fn decode_tables() {
+        let data = build_dol();
+        for eti_init_info in [None, Some(ETI_INIT_INFO_ADDRESS)] {
+            let result = decode_dol(&data, eti_init_info).unwrap();
+            assert!(result.errors.is_empty());
+            let tables = result.tables;
+            let functions: Vec<(u32, u32)> = tables
+                .values()
+                .map(|table| (table.function_address, table.extab_address))
+                .collect();
+            assert_eq!(
+                functions,
+                [
+                    (TEXT_ADDRESS, DATA_ADDRESS),
+                    (TEXT_ADDRESS + 0x10, DATA_ADDRESS + 8)
+                ]
+            );
+
+            //The last table ends where the extabindex entries start
+            let table = &tables[&(TEXT_ADDRESS + 0x10)].data;
+            assert_eq!(table.pc_actions.len(), 1);
+            assert_eq!(table.pc_actions[0].end_pc, 0xC);
+            assert_eq!(table.exception_actions.len(), 1);
+        }
+    }
+
+    #[test]
+    fn decode_errors_are_reported_per_function() {
+        let mut data = build_dol();
+        //Give the second table an invalid action type
+        data[0x120 + 0x18] = 0xFF;
+        let result = decode_dol(&data, Some(ETI_INIT_INFO_ADDRESS)).unwrap();
+        assert_eq!(result.tables.len(), 1);
+        assert!(result.tables.contains_key(&TEXT_ADDRESS));
+        assert!(matches!(
+            result.errors[..],
+            [DolExtabError::Decode(address, _)] if address == TEXT_ADDRESS + 0x10
+        ));
+    }
+
+    #[test]
+    fn duplicate_functions() {
+        let mut data = build_dol();
+        //Point the second entry at the first function
+        data[0x120 + 0x2C..0x120 + 0x30].copy_from_slice(&TEXT_ADDRESS.to_be_bytes());
+        let result = decode_dol(&data, Some(ETI_INIT_INFO_ADDRESS)).unwrap();
+        assert_eq!(result.tables[&TEXT_ADDRESS].extab_address, DATA_ADDRESS);
+        assert!(matches!(
+            result.errors[..],
+            [DolExtabError::DuplicateFunction(TEXT_ADDRESS)]
+        ));
+    }
+
+    #[test]
+    fn decode_without_records() {
+        let mut data = build_dol();
+        //Clear the code range of the records, so that they aren't found
+        data[0x120 + 0x40..0x120 + 0x48].fill(0);
+        assert!(decode_dol(&data, None).unwrap().tables.is_empty());
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::HashMap;
+
+use object::{
+    File, Object, ObjectSection, ObjectSymbol, RelocationTarget, Section, SectionIndex, SymbolKind,
+};
+use thiserror::Error;
+
+use crate::section::split_tables;
+use crate::{
+    decode_extab, mem_utils, parse_extabindex, ExceptionTableData, ExtabDecodeError,
+    ExtabIndexEntry, ExtabIndexError, EXTABINDEX_ENTRY_SIZE,
+};
+
+const EXTAB_SECTION_NAME: &str = "extab";
+const EXTABINDEX_SECTION_NAME: &str = "extabindex";
+
+#[derive(Error, Debug)]
+pub enum ElfExtabError {
+    #[error("Failed to parse ELF file: {0}")]
+    Object(#[from] object::Error),
+    #[error("ELF file has an {0} section but no {1} section")]
+    MissingSection(&'static str, &'static str),
+    #[error("Invalid extabindex section: {0}")]
+    Index(#[from] ExtabIndexError),
+    #[error("extabindex entry at offset 0x{0:X} has an invalid function address")]
+    InvalidFunctionAddress(u32),
+    #[error("extabindex entry at offset 0x{0:X} does not point into the extab section")]
+    InvalidTablePointer(u32),
+    #[error("Failed to decode the exception table of {0}: {1}")]
+    Decode(String, ExtabDecodeError),
+}
+
+/// Struct containing the decoded exception table of a function in an ELF file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElfFunctionExtab {
+    pub name: String,
+    //Values from the function's extabindex entry. For relocatable files, the
+    //function address is relative to the start of its section.
+    pub function_address: u32,
+    pub function_size: u32,
+    pub extab_offset: u32, //Offset of the table in the extab section
+    pub data: ExceptionTableData,
+    //Names of the symbols referenced by the table, keyed by their offset in the table.
+    //These come from the extab relocations, or from the symbol table for executables.
+    pub relocation_names: HashMap<u32, String>,
+}
+
+/// Decodes the exception tables of every function in the given ELF file (relocatable
+/// or executable), by walking its extabindex section.
+///
+/// Returns the tables in the order of their extabindex entries. Function names are not
+/// unique (local symbols in different units can share a name), so tables are not keyed
+/// by them. Files without extab and extabindex sections give an empty list.
+pub fn decode_elf(data: &[u8]) -> Result<Vec<ElfFunctionExtab>, ElfExtabError> {
+    let file = File::parse(data)?;
+
+    let (extab, extabindex) = match (
+        file.section_by_name(EXTAB_SECTION_NAME),
+        file.section_by_name(EXTABINDEX_SECTION_NAME),
+    ) {
+        (Some(extab), Some(extabindex)) => (extab, extabindex),
+        (None, None) => return Ok(vec![]),
+        (Some(_), None) => {
+            return Err(ElfExtabError::MissingSection(
+                EXTAB_SECTION_NAME,
+                EXTABINDEX_SECTION_NAME,
+            ))
+        }
+        (None, Some(_)) => {
+            return Err(ElfExtabError::MissingSection(
+                EXTABINDEX_SECTION_NAME,
+                EXTAB_SECTION_NAME,
+            ))
+        }
+    };
+
+    let extab_data = extab.data()?;
+    let index_data = extabindex.data()?;
+    //The function and table addresses of relocatable files are resolved below, and only
+    //the function size is stored directly
+    let index_entries = parse_extabindex(index_data)?;
+
+    let symbol_names = SymbolNames::new(&file);
+    let index_relocations = SectionRelocations::new(&file, &extabindex);
+    let extab_relocations = SectionRelocations::new(&file, &extab);
+
+    //Parse the index entries
+    let mut entries: Vec<(String, ExtabIndexEntry)> = vec![];
+    for (i, index_entry) in index_entries.into_iter().enumerate() {
+        let entry_offset = (i as u32) * EXTABINDEX_ENTRY_SIZE;
+        let invalid_function = || ElfExtabError::InvalidFunctionAddress(entry_offset);
+        let (function_section, function_address) = index_relocations
+            .read_address(index_data, entry_offset)
+            .ok_or_else(invalid_function)?;
+        let function_size = index_entry.function_size;
+        let name = match symbol_names.find(&file, function_section, function_address) {
+            Some(name) => name,
+            None => format!("fn_{function_address:08X}"),
+        };
+
+        let invalid_pointer = || ElfExtabError::InvalidTablePointer(entry_offset);
+        let (table_section, table_address) = index_relocations
+            .read_address(index_data, entry_offset + 8)
+            .ok_or_else(invalid_pointer)?;
+        if matches!(table_section, Some(index) if index != extab.index()) {
+            return Err(invalid_pointer());
+        }
+        let entry = ExtabIndexEntry {
+            function_address,
+            function_size,
+            extab_address: table_address,
+        };
+        entry
+            .extab_offset(extab.address() as u32, extab_data.len() as u32)
+            .ok_or_else(invalid_pointer)?;
+
+        entries.push((name, entry));
+    }
+
+    let extab_offset = |entry: &ExtabIndexEntry| entry.extab_address - extab.address() as u32;
+    let table_bytes = split_tables(
+        extab_data,
+        entries.iter().map(|(_, entry)| extab_offset(entry)),
+    );
+
+    let mut tables: Vec<ElfFunctionExtab> = vec![];
+    for (name, entry) in entries {
+        let extab_offset = extab_offset(&entry);
+        let table_bytes = table_bytes[&extab_offset];
+        let end_offset = extab_offset + table_bytes.len() as u32;
+        let data = match decode_extab(table_bytes) {
+            Ok(val) => val,
+            Err(e) => return Err(ElfExtabError::Decode(name, e)),
+        };
+
+        let relocation_names: HashMap<u32, String> = if extab_relocations.is_empty() {
+            //Executables have no relocations, so look up the addresses referenced by every
+            //action instead
+            data.exception_actions
+                .iter()
+                .flat_map(|action| action.reference_offsets())
+                .filter_map(|reloc| {
+                    let name = symbol_names.find(&file, None, reloc.address)?;
+                    Some((reloc.offset, name))
+                })
+                .collect()
+        } else {
+            extab_relocations
+                .relocations
+                .iter()
+                .filter(|(offset, _)| (extab_offset..end_offset).contains(offset))
+                .filter_map(|(offset, target)| {
+                    let name = match &target.name {
+                        Some(name) => name.clone(),
+                        None => symbol_names.find(&file, target.section, target.address)?,
+                    };
+                    Some((offset - extab_offset, name))
+                })
+                .collect()
+        };
+
+        let table = ElfFunctionExtab {
+            name,
+            function_address: entry.function_address,
+            function_size: entry.function_size,
+            extab_offset,
+            data,
+            relocation_names,
+        };
+        tables.push(table);
+    }
+
+    Ok(tables)
+}
+
+fn read_uint32(data: &[u8], offset: u32) -> u32 {
+    //The index size is checked to be a multiple of the entry size beforehand
+    mem_utils::read_uint32(data, &mut (offset as i32), false).unwrap_or(0)
+}
+
+/// Target of a relocation, with the addend applied.
+struct ResolvedRelocation {
+    section: Option<SectionIndex>,
+    address: u32,
+    name: Option<String>, //Name of the target symbol, unless it is a section symbol
+}
+
+/// Relocations of a section, keyed by their offset.
+struct SectionRelocations {
+    relocations: HashMap<u32, ResolvedRelocation>,
+}
+
+impl SectionRelocations {
+    fn new(file: &File, section: &Section) -> Self {
+        let mut relocations = HashMap::new();
+
+        for (offset, relocation) in section.relocations() {
+            let (target_section, target_address, target_name) = match relocation.target() {
+                RelocationTarget::Symbol(index) => match file.symbol_by_index(index) {
+                    Ok(symbol) => {
+                        let name = match symbol.name() {
+                            Ok(name)
+                                if !name.is_empty() && symbol.kind() != SymbolKind::Section =>
+                            {
+                                Some(name.to_string())
+                            }
+                            _ => None,
+                        };
+                        (symbol.section_index(), symbol.address(), name)
+                    }
+                    Err(_e) => {
+                        log_warn!("Skipping relocation at offset {offset:#X}: {_e}");
+                        continue;
+                    }
+                },
+                RelocationTarget::Section(index) => match file.section_by_index(index) {
+                    Ok(section) => (Some(index), section.address(), None),
+                    Err(_e) => {
+                        log_warn!("Skipping relocation at offset {offset:#X}: {_e}");
+                        continue;
+                    }
+                },
+                RelocationTarget::Absolute => (None, 0, None),
+                _ => {
+                    log_warn!("Skipping relocation at offset {offset:#X} with unsupported target");
+                    continue;
+                }
+            };
+
+            let mut addend = relocation.addend() as i32;
+            if relocation.has_implicit_addend() {
+                let implicit_addend = mem_utils::read_uint32(
+                    section.data().unwrap_or_default(),
+                    &mut (offset as i32),
+                    false,
+                );
+                addend = addend.wrapping_add(implicit_addend.unwrap_or(0) as i32);
+            }
+
+            let name = match target_name {
+                Some(name) if addend != 0 => Some(format!("{name}+{addend:#X}")),
+                name => name,
+            };
+            let resolved = ResolvedRelocation {
+                section: target_section,
+                address: (target_address as u32).wrapping_add(addend as u32),
+                name,
+            };
+            relocations.insert(offset as u32, resolved);
+        }
+
+        Self { relocations }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.relocations.is_empty()
+    }
+
+    /// Reads the address at the given offset of the section data, resolving it through
+    /// the relocation at that offset if there is one.
+    ///
+    /// Returns the section the address belongs to (if known) along with the address.
+    fn read_address(&self, data: &[u8], offset: u32) -> Option<(Option<SectionIndex>, u32)> {
+        match self.relocations.get(&offset) {
+            Some(target) => Some((target.section, target.address)),
+            None if self.is_empty() => Some((None, read_uint32(data, offset))),
+            //Unrelocated values in a relocatable file cannot be resolved
+            None => None,
+        }
+    }
+}
+
+/// Function and object symbol names, keyed by section and address.
+struct SymbolNames {
+    names: HashMap<(Option<SectionIndex>, u32), String>,
+}
+
+impl SymbolNames {
+    fn new(file: &File) -> Self {
+        let mut names = HashMap::new();
+
+        for symbol in file.symbols() {
+            if !matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data) {
+                continue;
+            }
+            let name = match symbol.name() {
+                Ok(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+            let key = (symbol.section_index(), symbol.address() as u32);
+            //Prefer global symbols over local ones at the same address
+            if symbol.is_global() || !names.contains_key(&key) {
+                names.insert(key, name.to_string());
+            }
+        }
+
+        Self { names }
+    }
+
+    /// Returns the name of the symbol at the given address, if there is one.
+    fn find(&self, file: &File, section: Option<SectionIndex>, address: u32) -> Option<String> {
+        //Executables have no relocations, so find the section from the address
+        let section = section.or_else(|| {
+            file.sections()
+                .find(|section| {
+                    let start = section.address();
+                    (start..start + section.size()).contains(&(address as u64))
+                })
+                .map(|section| section.index())
+        });
+
+        self.names.get(&(section, address)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::write::elf::{FileHeader, SectionHeader, Sym, Writer};
+    use object::write::{Object, Relocation, StandardSection, Symbol, SymbolSection};
+    use object::{
+        elf, Architecture, BinaryFormat, Endianness, RelocationFlags, SectionKind, SymbolFlags,
+        SymbolKind, SymbolScope,
+    };
+
+    use super::{decode_elf, ElfExtabError, ElfFunctionExtab};
+    use crate::fixtures::DESTROY_LOCAL_TABLE;
+    use crate::{mem_utils, ExtabIndexError};
+
+    const TEXT_ADDRESS: u32 = 0x80003100;
+    const DATA_ADDRESS: u32 = 0x80004000;
+    const EXTAB_ADDRESS: u32 = 0x80005000;
+    const EXTABINDEX_ADDRESS: u32 = 0x80005100;
+    const DTOR_ADDRESS: u32 = TEXT_ADDRESS + 0x20;
+
+    /// Table whose only PC range, 0x8-0x10, catches Baz.
+    #[rustfmt::skip]
+    const CATCH_TABLE: [u8; 28] = [
+        0x10, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x8C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //Catch block, catch type
+        0x00, 0x0C, 0x00, 0x08, //Handler PC, catch info
+    ];
+
+    /// Offset of the catch table in the extab section.
+    const CATCH_TABLE_OFFSET: u32 = DESTROY_LOCAL_TABLE.len() as u32;
+
+    /// Offset of the dtor of the destroy local table, and of the catch type of the
+    /// catch table, in their tables.
+    const REFERENCE_OFFSET: u32 = 0x14;
+
+    /// Returns the extab section, with the given dtor and catch type addresses.
+    fn extab_section(dtor_address: u32, catch_type: u32) -> Vec<u8> {
+        let mut extab = [&DESTROY_LOCAL_TABLE[..], &CATCH_TABLE].concat();
+        let mut patch = |offset: u32, value: u32| {
+            let offset = offset as usize;
+            extab[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        patch(REFERENCE_OFFSET, dtor_address);
+        patch(CATCH_TABLE_OFFSET + REFERENCE_OFFSET, catch_type);
+        extab
+    }
+
+    /// Returns an extabindex section with entries for two functions of size 0x10,
+    /// using the given function and table addresses.
+    fn extabindex_section(functions: [u32; 2], tables: [u32; 2]) -> Vec<u8> {
+        let mut extabindex: Vec<u8> = vec![];
+        for (function, table) in functions.into_iter().zip(tables) {
+            mem_utils::write_uint32(&mut extabindex, function);
+            mem_utils::write_uint32(&mut extabindex, 0x10);
+            mem_utils::write_uint32(&mut extabindex, table);
+        }
+        extabindex
+    }
+
+    /// Builds a relocatable object with the functions foo and bar, the first one destroying
+    /// a local with an external dtor and the second one catching an external type.
+    fn build_relocatable(extabindex_size: usize) -> Vec<u8> {
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let text = obj.section_id(StandardSection::Text);
+        obj.append_section_data(text, &[0x60, 0x00, 0x00, 0x00].repeat(8), 4);
+        let extab = obj.add_section(vec![], b"extab".to_vec(), SectionKind::ReadOnlyData);
+        obj.append_section_data(extab, &extab_section(0, 0), 4);
+        let extabindex = obj.add_section(vec![], b"extabindex".to_vec(), SectionKind::ReadOnlyData);
+        let mut index_data = extabindex_section([0, 0], [0, 0]);
+        index_data.truncate(extabindex_size);
+        obj.append_section_data(extabindex, &index_data, 4);
+
+        let mut add_symbol = |name: &str, value: u32, section: SymbolSection| {
+            obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: value as u64,
+                size: 0x10,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section,
+                flags: SymbolFlags::None,
+            })
+        };
+        let foo = add_symbol("foo", 0, SymbolSection::Section(text));
+        let bar = add_symbol("bar", 0x10, SymbolSection::Section(text));
+        let dtor = add_symbol("__dt__3FooFv", 0, SymbolSection::Undefined);
+        let catch_type = add_symbol("__RTTI__3Baz", 0, SymbolSection::Undefined);
+        let extab_symbol = obj.section_symbol(extab);
+
+        let relocations = [
+            (extab, REFERENCE_OFFSET, dtor, 0),
+            (extab, CATCH_TABLE_OFFSET + REFERENCE_OFFSET, catch_type, 0),
+            (extabindex, 0, foo, 0),
+            (extabindex, 8, extab_symbol, 0),
+            (extabindex, 12, bar, 0),
+            (extabindex, 20, extab_symbol, CATCH_TABLE_OFFSET),
+        ];
+        for (section, offset, symbol, addend) in relocations {
+            let relocation = Relocation {
+                offset: offset as u64,
+                symbol,
+                addend: addend as i64,
+                flags: RelocationFlags::Elf {
+                    r_type: elf::R_PPC_ADDR32,
+                },
+            };
+            obj.add_relocation(section, relocation).unwrap();
+        }
+
+        obj.write().unwrap()
+    }
+
+    /// Builds an executable with the same functions as `build_relocatable`, along with
+    /// the dtor and the type info of the caught type.
+    fn build_executable() -> Vec<u8> {
+        let text = [0x60, 0x00, 0x00, 0x00].repeat(12);
+        let data = [0; 8];
+        let extab = extab_section(DTOR_ADDRESS, DATA_ADDRESS);
+        let extabindex = extabindex_section(
+            [TEXT_ADDRESS, TEXT_ADDRESS + 0x10],
+            [EXTAB_ADDRESS, EXTAB_ADDRESS + CATCH_TABLE_OFFSET],
+        );
+        let sections = [
+            (&b".text"[..], &text[..], TEXT_ADDRESS, elf::SHF_EXECINSTR),
+            (b".data", &data, DATA_ADDRESS, elf::SHF_WRITE),
+            (b"extab", &extab, EXTAB_ADDRESS, 0),
+            (b"extabindex", &extabindex, EXTABINDEX_ADDRESS, 0),
+        ];
+
+        let mut buffer: Vec<u8> = vec![];
+        let mut writer = Writer::new(Endianness::Big, false, &mut buffer);
+        writer.reserve_file_header();
+
+        writer.reserve_null_section_index();
+        let mut section_headers = vec![];
+        for (name, bytes, address, flags) in sections {
+            let name = writer.add_section_name(name);
+            let index = writer.reserve_section_index();
+            let offset = writer.reserve(bytes.len(), 4);
+            section_headers.push((index, name, bytes, address, flags, offset));
+        }
+        let text_index = section_headers[0].0;
+        let data_index = section_headers[1].0;
+
+        writer.reserve_null_symbol_index();
+        let symbols = [
+            ("foo", text_index, TEXT_ADDRESS, elf::STT_FUNC),
+            ("bar", text_index, TEXT_ADDRESS + 0x10, elf::STT_FUNC),
+            ("__dt__3FooFv", text_index, DTOR_ADDRESS, elf::STT_FUNC),
+            ("__RTTI__3Baz", data_index, DATA_ADDRESS, elf::STT_OBJECT),
+        ]
+        .map(|(name, section, address, kind)| {
+            writer.reserve_symbol_index(Some(section));
+            (writer.add_string(name.as_bytes()), section, address, kind)
+        });
+        writer.reserve_symtab_section_index();
+        writer.reserve_symtab();
+        writer.reserve_strtab_section_index();
+        writer.reserve_strtab();
+        writer.reserve_shstrtab_section_index();
+        writer.reserve_shstrtab();
+        writer.reserve_section_headers();
+
+        writer
+            .write_file_header(&FileHeader {
+                os_abi: elf::ELFOSABI_SYSV,
+                abi_version: 0,
+                e_type: elf::ET_EXEC,
+                e_machine: elf::EM_PPC,
+                e_entry: TEXT_ADDRESS as u64,
+                e_flags: 0,
+            })
+            .unwrap();
+        for (_, _, bytes, _, _, _) in &section_headers {
+            writer.write_align(4);
+            writer.write(bytes);
+        }
+        writer.write_null_symbol();
+        for (name, section, address, kind) in symbols {
+            writer.write_symbol(&Sym {
+                name: Some(name),
+                section: Some(section),
+                st_info: (elf::STB_GLOBAL << 4) | kind,
+                st_other: elf::STV_DEFAULT,
+                st_shndx: 0,
+                st_value: address as u64,
+                st_size: 0x10,
+            });
+        }
+        writer.write_strtab();
+        writer.write_shstrtab();
+
+        writer.write_null_section_header();
+        for (_, name, bytes, address, flags, offset) in section_headers {
+            writer.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type: elf::SHT_PROGBITS,
+                sh_flags: (elf::SHF_ALLOC | flags) as u64,
+                sh_addr: address as u64,
+                sh_offset: offset as u64,
+                sh_size: bytes.len() as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        writer.write_symtab_section_header(1);
+        writer.write_strtab_section_header();
+        writer.write_shstrtab_section_header();
+
+        buffer
+    }
+
+    fn reference_name(table: &ElfFunctionExtab) -> Option<&str> {
+        table
+            .relocation_names
+            .get(&REFERENCE_OFFSET)
+            .map(String::as_str)
+    }
+
+    #[test]
+    fn relocatable() {
+        let tables = decode_elf(&build_relocatable(24)).unwrap();
+        assert_eq!(tables.len(), 2);
+
+        let (foo, bar) = (&tables[0], &tables[1]);
+        assert_eq!(foo.name, "foo");
+        assert_eq!(foo.function_address, 0);
+        assert_eq!(foo.function_size, 0x10);
+        assert_eq!(foo.extab_offset, 0);
+        assert_eq!(foo.data.exception_actions.len(), 1);
+        assert_eq!(reference_name(foo), Some("__dt__3FooFv"));
+        assert_eq!(foo.relocation_names.len(), 1);
+
+        assert_eq!(bar.name, "bar");
+        assert_eq!(bar.function_address, 0x10);
+        assert_eq!(bar.extab_offset, CATCH_TABLE_OFFSET);
+        assert_eq!(bar.data.exception_actions.len(), 1);
+        assert_eq!(reference_name(bar), Some("__RTTI__3Baz"));
+        assert_eq!(bar.relocation_names.len(), 1);
+    }
+
+    #[test]
+    fn executable() {
+        let tables = decode_elf(&build_executable()).unwrap();
+        assert_eq!(tables.len(), 2);
+
+        let (foo, bar) = (&tables[0], &tables[1]);
+        assert_eq!(foo.name, "foo");
+        assert_eq!(foo.function_address, TEXT_ADDRESS);
+        assert_eq!(foo.extab_offset, 0);
+        assert_eq!(reference_name(foo), Some("__dt__3FooFv"));
+
+        //Catch types aren't dtor references, but they are still named
+        assert_eq!(bar.name, "bar");
+        assert_eq!(bar.function_address, TEXT_ADDRESS + 0x10);
+        assert_eq!(bar.extab_offset, CATCH_TABLE_OFFSET);
+        assert_eq!(reference_name(bar), Some("__RTTI__3Baz"));
+    }
+
+    #[test]
+    fn invalid_index_size() {
+        let result = decode_elf(&build_relocatable(20));
+        assert!(matches!(
+            result,
+            Err(ElfExtabError::Index(ExtabIndexError::InvalidIndexSize(20)))
+        ));
+    }
+}
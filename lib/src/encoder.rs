@@ -0,0 +1,240 @@
+use crate::{mem_utils, ExActionData, ExceptionTableData, ExtabEncodeError};
+
+pub(crate) struct ExtabEncoder {
+    data: Vec<u8>,
+}
+
+impl ExtabEncoder {
+    pub(crate) fn new() -> Self {
+        Self { data: vec![] }
+    }
+
+    pub(crate) fn encode_exception_table(
+        mut self,
+        extab_data: &ExceptionTableData,
+    ) -> Result<Vec<u8>, ExtabEncodeError> {
+        if extab_data.fpr_save_range > 0b11111 {
+            return Err(ExtabEncodeError::InvalidFprSaveRange(
+                extab_data.fpr_save_range,
+            ));
+        }
+        if extab_data.gpr_save_range > 0b11111 {
+            return Err(ExtabEncodeError::InvalidGprSaveRange(
+                extab_data.gpr_save_range,
+            ));
+        }
+
+        //Write the header flag value and the et field
+        mem_utils::write_uint16(&mut self.data, extab_data.calculate_flag_val());
+        mem_utils::write_uint16(&mut self.data, extab_data.et_field);
+
+        //Write the range entries, followed by the terminator (32 bit zero value)
+        for pcaction in &extab_data.pc_actions {
+            //A zero start pc would be read back as the terminator
+            if pcaction.start_pc == 0 {
+                return Err(ExtabEncodeError::ZeroStartPC);
+            }
+            let range_size = match pcaction.end_pc.checked_sub(pcaction.start_pc) {
+                Some(size) if size % 4 == 0 && size / 4 <= 0xFFFF => size / 4, //range size is encoded as size >> 2
+                _ => {
+                    return Err(ExtabEncodeError::InvalidPCRange(
+                        pcaction.start_pc,
+                        pcaction.end_pc,
+                    ))
+                }
+            };
+            if pcaction.action_offset > 0xFFFF {
+                return Err(ExtabEncodeError::ActionOffsetTooLarge(
+                    pcaction.action_offset,
+                ));
+            }
+
+            mem_utils::write_uint32(&mut self.data, pcaction.start_pc);
+            mem_utils::write_uint16(&mut self.data, range_size as u16);
+            mem_utils::write_uint16(&mut self.data, pcaction.action_offset as u16);
+        }
+
+        mem_utils::write_uint32(&mut self.data, 0);
+
+        //Write the action entries
+        for exaction in &extab_data.exception_actions {
+            //The PC actions and branches refer to actions by offset, so each one has to
+            //be written where it says it is
+            let offset = self.data.len() as u32;
+            if exaction.action_offset != offset {
+                return Err(ExtabEncodeError::ActionOffsetMismatch(
+                    offset,
+                    exaction.action_offset,
+                ));
+            }
+            //The decoder finds the next action from the size of the data
+            let data_size = ExActionData::from_bytes(exaction.action_type, &exaction.bytes)
+                .map(|data| data.data_size());
+            if data_size != Some(exaction.bytes.len() as u32) {
+                return Err(ExtabEncodeError::InvalidActionData(
+                    offset,
+                    exaction.bytes.len() as u32,
+                    exaction.action_type,
+                ));
+            }
+
+            let mut action_type_byte = exaction.action_type.to_int() as u8;
+            if exaction.has_end_bit {
+                action_type_byte |= 0x80;
+            }
+            mem_utils::write_byte(&mut self.data, action_type_byte);
+            mem_utils::write_byte(&mut self.data, exaction.action_param);
+            self.data.extend_from_slice(&exaction.bytes);
+        }
+
+        Ok(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{DESTROY_LOCALS_TABLE, DESTROY_LOCAL_TABLE, EMPTY_TABLE};
+    use crate::{decode_extab, encode_extab, ExAction, ExActionData, ExtabEncodeError};
+
+    /// Table with a throw specification allowing two types.
+    #[rustfmt::skip]
+    const SPECIFICATION_TABLE: [u8; 36] = [
+        0x08, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x24, 0x00, 0x05, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x8F, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x58, 0x00, 0x00, 0x00, 0x10, //Specification
+        0x80, 0x00, 0x12, 0x34, 0x80, 0x00, 0x56, 0x78, //Types
+    ];
+
+    /// Table with a catch block using 32 bit offsets.
+    #[rustfmt::skip]
+    const CATCH_BLOCK32_TABLE: [u8; 32] = [
+        0x18, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x40, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //Catch block, catch type
+        0x00, 0x00, 0x01, 0x2C, 0x00, 0x00, 0x00, 0x20, //Handler PC, catch info
+    ];
+
+    /// Returns a table padded with zeros to the alignment of the section.
+    fn padded_table() -> Vec<u8> {
+        [&DESTROY_LOCAL_TABLE[..], &[0; 8]].concat()
+    }
+
+    fn assert_round_trip(bytes: &[u8]) {
+        let table = decode_extab(bytes).unwrap();
+        assert_eq!(encode_extab(&table).unwrap(), bytes);
+    }
+
+    #[test]
+    fn empty_table_round_trip() {
+        let table = decode_extab(&EMPTY_TABLE).unwrap();
+        assert_eq!(table.gpr_save_range, 3);
+        assert!(table.large_frame);
+        assert!(table.pc_actions.is_empty());
+        assert!(table.exception_actions.is_empty());
+        assert_round_trip(&EMPTY_TABLE);
+    }
+
+    #[test]
+    fn specification_round_trip() {
+        let table = decode_extab(&SPECIFICATION_TABLE).unwrap();
+        match table.exception_actions[0].try_get_exaction_data().unwrap() {
+            ExActionData::Specification {
+                specs,
+                pc_offset,
+                spec,
+                ..
+            } => {
+                assert_eq!(specs, 2);
+                assert_eq!(pc_offset, 0x58);
+                assert_eq!(spec, vec![0x80001234, 0x80005678]);
+            }
+            data => panic!("unexpected action data {data:?}"),
+        }
+        assert_round_trip(&SPECIFICATION_TABLE);
+    }
+
+    #[test]
+    fn catch_block32_round_trip() {
+        let table = decode_extab(&CATCH_BLOCK32_TABLE).unwrap();
+        let action = &table.exception_actions[0];
+        assert!(matches!(action.action_type, ExAction::CatchBlock32));
+        assert!(action.has_end_bit);
+        match action.try_get_exaction_data().unwrap() {
+            ExActionData::CatchBlock32 {
+                catch_pc_offset,
+                cinfo_ref,
+                ..
+            } => {
+                assert_eq!(catch_pc_offset, 0x12C);
+                assert_eq!(cinfo_ref, 0x20);
+            }
+            data => panic!("unexpected action data {data:?}"),
+        }
+        assert_round_trip(&CATCH_BLOCK32_TABLE);
+    }
+
+    #[test]
+    fn padded_table_round_trip() {
+        let table = decode_extab(&padded_table()).unwrap();
+        //The padding is read as null actions
+        assert_eq!(table.exception_actions.len(), 5);
+        assert!(table.exception_actions[1..]
+            .iter()
+            .all(|action| matches!(action.action_type, ExAction::EndOfList)));
+        assert_round_trip(&padded_table());
+    }
+
+    #[test]
+    fn destroy_local_round_trip() {
+        assert_round_trip(&DESTROY_LOCAL_TABLE);
+        assert_round_trip(&DESTROY_LOCALS_TABLE);
+    }
+
+    #[test]
+    fn zero_start_pc_is_rejected() {
+        let mut table = decode_extab(&DESTROY_LOCAL_TABLE).unwrap();
+        table.pc_actions[0].start_pc = 0;
+        assert!(matches!(
+            encode_extab(&table),
+            Err(ExtabEncodeError::ZeroStartPC)
+        ));
+    }
+
+    #[test]
+    fn mismatched_action_data_is_rejected() {
+        let mut table = decode_extab(&DESTROY_LOCAL_TABLE).unwrap();
+        table.exception_actions[0].bytes.pop();
+        assert!(matches!(
+            encode_extab(&table),
+            Err(ExtabEncodeError::InvalidActionData(
+                0x10,
+                5,
+                ExAction::DestroyLocal
+            ))
+        ));
+
+        //Specifications also hold the types given by their count
+        let mut table = decode_extab(&SPECIFICATION_TABLE).unwrap();
+        table.exception_actions[0].bytes.truncate(14);
+        assert!(matches!(
+            encode_extab(&table),
+            Err(ExtabEncodeError::InvalidActionData(
+                0x10,
+                14,
+                ExAction::Specification
+            ))
+        ));
+    }
+
+    #[test]
+    fn misplaced_action_is_rejected() {
+        let mut table = decode_extab(&DESTROY_LOCALS_TABLE).unwrap();
+        table.exception_actions[1].action_offset = 0x24;
+        assert!(matches!(
+            encode_extab(&table),
+            Err(ExtabEncodeError::ActionOffsetMismatch(0x20, 0x24))
+        ));
+    }
+}
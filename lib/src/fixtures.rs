@@ -0,0 +1,30 @@
+//! Tables shared by the tests of the decoders, loaders and table tools.
+
+/// Table without PC ranges, which the compiler still emits for functions that have
+/// nothing to unwind. Its flags give a large frame saving r29-r31.
+#[rustfmt::skip]
+pub(crate) const EMPTY_TABLE: [u8; 8] = [
+    0x18, 0x08, 0x00, 0x00, //Flags
+    0x00, 0x00, 0x00, 0x00, //Terminator
+];
+
+/// Table whose only PC range, 0x4-0xC, destroys a local at 0x8(r1).
+#[rustfmt::skip]
+pub(crate) const DESTROY_LOCAL_TABLE: [u8; 24] = [
+    0x10, 0x08, 0x00, 0x00, //Flags
+    0x00, 0x00, 0x00, 0x04, 0x00, 0x02, 0x00, 0x10, //PC range
+    0x00, 0x00, 0x00, 0x00, //Terminator
+    0x82, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, //Destroy local
+];
+
+/// Table whose PC range 0x20-0x40 destroys the locals at 0x8(r1) and 0xC(r1), and whose
+/// PC range 0x40-0x50 only destroys the second one.
+#[rustfmt::skip]
+pub(crate) const DESTROY_LOCALS_TABLE: [u8; 40] = [
+    0x10, 0x08, 0x00, 0x00, //Flags
+    0x00, 0x00, 0x00, 0x20, 0x00, 0x08, 0x00, 0x18, //PC range
+    0x00, 0x00, 0x00, 0x40, 0x00, 0x04, 0x00, 0x20, //PC range
+    0x00, 0x00, 0x00, 0x00, //Terminator
+    0x02, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, //Destroy local
+    0x82, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x00, //Destroy local
+];
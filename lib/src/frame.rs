@@ -0,0 +1,1188 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::{ExceptionTableData, SymbolResolver};
+
+/// First non-volatile general purpose and floating point register.
+const FIRST_NONVOLATILE_REGISTER: u32 = 14;
+/// Stack pointer register.
+const STACK_POINTER: u32 = 1;
+
+/// Way the non-volatile registers of a function can be saved in its prologue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SaveStyle {
+    /// One store per register (`stw`, or `stfd` and `psq_st`).
+    Individual,
+    /// A single `stmw` for every general purpose register.
+    Multiple,
+    /// A call to a runtime save function (`_savegpr_N` or `_savefpr_N`).
+    Call,
+}
+
+/// Frame of a function as described by the header flags of its table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameDescription {
+    /// General purpose registers saved in the prologue, from the first saved one to r31.
+    pub saved_gprs: Vec<u32>,
+    /// Floating point registers saved in the prologue, from the first saved one to f31.
+    pub saved_fprs: Vec<u32>,
+    /// Ways the general purpose registers can be saved. Which one is used depends on the
+    /// compiler options, and isn't recorded in the table.
+    pub gpr_save_styles: Vec<SaveStyle>,
+    /// Ways the floating point registers can be saved.
+    pub fpr_save_styles: Vec<SaveStyle>,
+    /// Whether the condition register is saved to the stack.
+    pub saves_cr: bool,
+    /// Whether a frame pointer is set up with `mr`, which stack locations are relative to.
+    pub has_frame_pointer: bool,
+    /// Whether the frame is too large for `stwu`, and is allocated with `stwux`.
+    pub large_frame: bool,
+}
+
+impl FrameDescription {
+    /// Returns the offset of the CR save word from the top of the frame (the stack
+    /// pointer of the caller), if CR is saved. The word is right below the GPR save
+    /// area, which is itself below the FPR save area. FPRs take 8 bytes each, or 16 when
+    /// they are also saved as paired singles.
+    pub fn cr_save_offset(&self, paired_singles: bool) -> Option<i32> {
+        if !self.saves_cr {
+            return None;
+        }
+        let fpr_size = if paired_singles { 16 } else { 8 };
+        let save_area_size =
+            fpr_size * self.saved_fprs.len() as i32 + 4 * self.saved_gprs.len() as i32;
+        Some(-save_area_size - 4)
+    }
+}
+
+/// Returns the registers saved for a save range, which counts down from register 31.
+fn saved_registers(save_range: u32) -> Vec<u32> {
+    (32 - save_range.min(32)..32).collect()
+}
+
+impl fmt::Display for FrameDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = vec![];
+        if let Some(first) = self.saved_gprs.first() {
+            parts.push(format!("saves r{first}-r31"));
+        }
+        if let Some(first) = self.saved_fprs.first() {
+            parts.push(format!("saves f{first}-f31"));
+        }
+        if self.saves_cr {
+            parts.push(String::from("saves CR"));
+        }
+        if self.has_frame_pointer {
+            parts.push(String::from("uses a frame pointer"));
+        }
+        if self.large_frame {
+            parts.push(String::from("large frame"));
+        }
+        if parts.is_empty() {
+            write!(f, "no saved registers")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Prologue instruction which affects the frame of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrologueInstruction {
+    /// `stwu r1, -size(r1)`
+    AllocateFrame { size: u32 },
+    /// `stwux r1, r1, rN`
+    AllocateFrameIndexed { register: u32 },
+    /// `mflr rN`
+    MoveFromLr { register: u32 },
+    /// `mfcr rN`
+    MoveFromCr { register: u32 },
+    /// `mr rD, rS`
+    MoveRegister { dest: u32, source: u32 },
+    /// `stw rS, offset(rA)`
+    StoreWord {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `stmw rS, offset(rA)`
+    StoreMultiple {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `stfd fS, offset(rA)`
+    StoreDouble {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `psq_st fS, offset(rA), W, I`
+    StorePairedSingle {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `bl target`. Calls decoded from instruction words only have a target name if a
+    /// resolver is given.
+    Call { target: Option<String> },
+    /// Any other instruction.
+    Other,
+}
+
+/// Parses a register operand, such as `r31` or `f31` with the given prefix.
+fn parse_register(text: &str, prefix: char) -> Option<u32> {
+    let text = text.trim();
+    if prefix == 'r' && text == "sp" {
+        return Some(STACK_POINTER);
+    }
+    let register: u32 = text.strip_prefix(prefix)?.parse().ok()?;
+    (register < 32).then_some(register)
+}
+
+/// Parses a signed decimal or hexadecimal immediate.
+fn parse_immediate(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a memory operand of the form `offset(rA)`.
+fn parse_memory_operand(text: &str) -> Option<(i16, u32)> {
+    let (offset, base) = text.trim().strip_suffix(')')?.split_once('(')?;
+    let offset = if offset.trim().is_empty() {
+        0
+    } else {
+        i16::try_from(parse_immediate(offset)?).ok()?
+    };
+    Some((offset, parse_register(base, 'r')?))
+}
+
+/// Splits a line of assembly into its mnemonic and operands, removing any comment.
+fn split_instruction(line: &str) -> (&str, Vec<&str>) {
+    let line = match line.split_once(['#', ';']) {
+        Some((code, _)) => code,
+        None => line,
+    };
+    let line = match line.split_once("/*") {
+        Some((code, _)) => code,
+        None => line,
+    };
+    let (mnemonic, operands) = match line.trim().split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, operands),
+        None => (line.trim(), ""),
+    };
+    (mnemonic, operands.split(',').map(str::trim).collect())
+}
+
+/// Decodes the instruction word as a `bl`, returning the name of its target if the
+/// resolver has one.
+fn decode_call(
+    word: u32,
+    address: u32,
+    resolver: Option<&dyn SymbolResolver>,
+) -> Option<Option<String>> {
+    //bl, without the absolute address bit
+    if word >> 26 != 18 || word & 3 != 1 {
+        return None;
+    }
+    //The target is a signed 24-bit word offset from the instruction
+    let displacement = ((word & 0x03FF_FFFC) << 6) as i32 >> 6;
+    let target = address.wrapping_add(displacement as u32);
+    Some(
+        resolver
+            .and_then(|resolver| resolver.resolve(target))
+            .map(String::from),
+    )
+}
+
+/// Returns the sign extended 12-bit offset of a paired single load or store.
+fn paired_single_offset(word: u32) -> i16 {
+    ((word << 20) as i32 >> 20) as i16
+}
+
+impl PrologueInstruction {
+    /// Decodes the instruction word at the given address. Call targets are named using
+    /// the resolver, if one is given.
+    pub fn decode(word: u32, address: u32, resolver: Option<&dyn SymbolResolver>) -> Self {
+        let opcode = word >> 26;
+        let rs = (word >> 21) & 0x1F;
+        let ra = (word >> 16) & 0x1F;
+        let rb = (word >> 11) & 0x1F;
+        let offset = word as u16 as i16;
+
+        if let Some(target) = decode_call(word, address, resolver) {
+            return PrologueInstruction::Call { target };
+        }
+        match opcode {
+            37 if rs == STACK_POINTER && ra == STACK_POINTER && offset < 0 => {
+                PrologueInstruction::AllocateFrame {
+                    size: -(offset as i32) as u32,
+                }
+            }
+            36 => PrologueInstruction::StoreWord {
+                register: rs,
+                base: ra,
+                offset,
+            },
+            47 => PrologueInstruction::StoreMultiple {
+                register: rs,
+                base: ra,
+                offset,
+            },
+            54 => PrologueInstruction::StoreDouble {
+                register: rs,
+                base: ra,
+                offset,
+            },
+            //The offset of paired single stores is only 12 bits
+            60 => PrologueInstruction::StorePairedSingle {
+                register: rs,
+                base: ra,
+                offset: paired_single_offset(word),
+            },
+            31 => match (word >> 1) & 0x3FF {
+                183 if rs == STACK_POINTER && ra == STACK_POINTER => {
+                    PrologueInstruction::AllocateFrameIndexed { register: rb }
+                }
+                //The two halves of the SPR number are swapped, and LR is SPR 8
+                339 if ra == 8 && rb == 0 => PrologueInstruction::MoveFromLr { register: rs },
+                19 => PrologueInstruction::MoveFromCr { register: rs },
+                444 if rs == rb => PrologueInstruction::MoveRegister {
+                    dest: ra,
+                    source: rs,
+                },
+                _ => PrologueInstruction::Other,
+            },
+            _ => PrologueInstruction::Other,
+        }
+    }
+
+    /// Parses a line of assembly, such as `stmw r27, 0xc(r1)` or `bl _savegpr_27`.
+    /// Comments and instructions which don't affect the frame are parsed as `Other`.
+    pub fn parse(line: &str) -> Self {
+        let (mnemonic, operands) = split_instruction(line);
+        Self::parse_operands(mnemonic, &operands).unwrap_or(PrologueInstruction::Other)
+    }
+
+    fn parse_operands(mnemonic: &str, operands: &[&str]) -> Option<Self> {
+        let instruction = match (mnemonic, operands) {
+            ("stwu", [rs, memory]) => {
+                let (offset, base) = parse_memory_operand(memory)?;
+                if parse_register(rs, 'r')? != STACK_POINTER || base != STACK_POINTER || offset >= 0
+                {
+                    return None;
+                }
+                PrologueInstruction::AllocateFrame {
+                    size: -(offset as i32) as u32,
+                }
+            }
+            ("stwux", [rs, ra, rb]) => {
+                if parse_register(rs, 'r')? != STACK_POINTER
+                    || parse_register(ra, 'r')? != STACK_POINTER
+                {
+                    return None;
+                }
+                PrologueInstruction::AllocateFrameIndexed {
+                    register: parse_register(rb, 'r')?,
+                }
+            }
+            ("mflr", [rd]) => PrologueInstruction::MoveFromLr {
+                register: parse_register(rd, 'r')?,
+            },
+            ("mfcr", [rd]) => PrologueInstruction::MoveFromCr {
+                register: parse_register(rd, 'r')?,
+            },
+            ("mr", [rd, rs]) => PrologueInstruction::MoveRegister {
+                dest: parse_register(rd, 'r')?,
+                source: parse_register(rs, 'r')?,
+            },
+            ("stw", [rs, memory]) | ("stmw", [rs, memory]) => {
+                let register = parse_register(rs, 'r')?;
+                let (offset, base) = parse_memory_operand(memory)?;
+                if mnemonic == "stw" {
+                    PrologueInstruction::StoreWord {
+                        register,
+                        base,
+                        offset,
+                    }
+                } else {
+                    PrologueInstruction::StoreMultiple {
+                        register,
+                        base,
+                        offset,
+                    }
+                }
+            }
+            ("stfd", [fs, memory]) => {
+                let (offset, base) = parse_memory_operand(memory)?;
+                PrologueInstruction::StoreDouble {
+                    register: parse_register(fs, 'f')?,
+                    base,
+                    offset,
+                }
+            }
+            ("psq_st", [fs, memory, _w, _i]) => {
+                let (offset, base) = parse_memory_operand(memory)?;
+                PrologueInstruction::StorePairedSingle {
+                    register: parse_register(fs, 'f')?,
+                    base,
+                    offset,
+                }
+            }
+            ("bl", [target]) => PrologueInstruction::Call {
+                target: Some(String::from(*target)),
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+}
+
+/// Epilogue instruction which affects the frame of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpilogueInstruction {
+    /// `addi r1, r1, size`. Large frames are freed by loading the back chain with
+    /// `lwz r1, 0(r1)` instead.
+    DeallocateFrame { size: u32 },
+    /// `mtlr rS`
+    MoveToLr { register: u32 },
+    /// `mtcrf mask, rS`, or `mtcr rS`
+    MoveToCr { register: u32 },
+    /// `lwz rD, offset(rA)`
+    LoadWord {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `lmw rD, offset(rA)`
+    LoadMultiple {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `lfd fD, offset(rA)`
+    LoadDouble {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `psq_l fD, offset(rA), W, I`
+    LoadPairedSingle {
+        register: u32,
+        base: u32,
+        offset: i16,
+    },
+    /// `bl target`. Calls decoded from instruction words only have a target name if a
+    /// resolver is given.
+    Call { target: Option<String> },
+    /// `blr`
+    Return,
+    /// Any other instruction.
+    Other,
+}
+
+impl EpilogueInstruction {
+    /// Decodes the instruction word at the given address. Call targets are named using
+    /// the resolver, if one is given.
+    pub fn decode(word: u32, address: u32, resolver: Option<&dyn SymbolResolver>) -> Self {
+        let opcode = word >> 26;
+        let rd = (word >> 21) & 0x1F;
+        let ra = (word >> 16) & 0x1F;
+        let rb = (word >> 11) & 0x1F;
+        let offset = word as u16 as i16;
+
+        if word == 0x4E800020 {
+            return EpilogueInstruction::Return;
+        }
+        if let Some(target) = decode_call(word, address, resolver) {
+            return EpilogueInstruction::Call { target };
+        }
+        match opcode {
+            14 if rd == STACK_POINTER && ra == STACK_POINTER && offset > 0 => {
+                EpilogueInstruction::DeallocateFrame {
+                    size: offset as u32,
+                }
+            }
+            32 => EpilogueInstruction::LoadWord {
+                register: rd,
+                base: ra,
+                offset,
+            },
+            46 => EpilogueInstruction::LoadMultiple {
+                register: rd,
+                base: ra,
+                offset,
+            },
+            50 => EpilogueInstruction::LoadDouble {
+                register: rd,
+                base: ra,
+                offset,
+            },
+            56 => EpilogueInstruction::LoadPairedSingle {
+                register: rd,
+                base: ra,
+                offset: paired_single_offset(word),
+            },
+            31 => match (word >> 1) & 0x3FF {
+                467 if ra == 8 && rb == 0 => EpilogueInstruction::MoveToLr { register: rd },
+                144 => EpilogueInstruction::MoveToCr { register: rd },
+                _ => EpilogueInstruction::Other,
+            },
+            _ => EpilogueInstruction::Other,
+        }
+    }
+
+    /// Parses a line of assembly, such as `lmw r27, 0xc(r1)` or `bl _restgpr_27`.
+    /// Comments and instructions which don't affect the frame are parsed as `Other`.
+    pub fn parse(line: &str) -> Self {
+        let (mnemonic, operands) = split_instruction(line);
+        Self::parse_operands(mnemonic, &operands).unwrap_or(EpilogueInstruction::Other)
+    }
+
+    fn parse_operands(mnemonic: &str, operands: &[&str]) -> Option<Self> {
+        let memory = |rd: &str, prefix: char, memory: &str| {
+            let (offset, base) = parse_memory_operand(memory)?;
+            Some((parse_register(rd, prefix)?, base, offset))
+        };
+
+        let instruction = match (mnemonic, operands) {
+            ("addi", [rd, ra, size]) => {
+                if parse_register(rd, 'r')? != STACK_POINTER
+                    || parse_register(ra, 'r')? != STACK_POINTER
+                {
+                    return None;
+                }
+                match parse_immediate(size)? {
+                    size @ 1..=0x7FFF => EpilogueInstruction::DeallocateFrame { size: size as u32 },
+                    _ => return None,
+                }
+            }
+            ("mtlr", [rs]) => EpilogueInstruction::MoveToLr {
+                register: parse_register(rs, 'r')?,
+            },
+            ("mtcrf", [_, rs]) | ("mtcr", [rs]) => EpilogueInstruction::MoveToCr {
+                register: parse_register(rs, 'r')?,
+            },
+            ("lwz", [rd, address]) => {
+                let (register, base, offset) = memory(rd, 'r', address)?;
+                EpilogueInstruction::LoadWord {
+                    register,
+                    base,
+                    offset,
+                }
+            }
+            ("lmw", [rd, address]) => {
+                let (register, base, offset) = memory(rd, 'r', address)?;
+                EpilogueInstruction::LoadMultiple {
+                    register,
+                    base,
+                    offset,
+                }
+            }
+            ("lfd", [fd, address]) => {
+                let (register, base, offset) = memory(fd, 'f', address)?;
+                EpilogueInstruction::LoadDouble {
+                    register,
+                    base,
+                    offset,
+                }
+            }
+            ("psq_l", [fd, address, _w, _i]) => {
+                let (register, base, offset) = memory(fd, 'f', address)?;
+                EpilogueInstruction::LoadPairedSingle {
+                    register,
+                    base,
+                    offset,
+                }
+            }
+            ("bl", [target]) => EpilogueInstruction::Call {
+                target: Some(String::from(*target)),
+            },
+            ("blr", [""]) => EpilogueInstruction::Return,
+            _ => return None,
+        };
+        Some(instruction)
+    }
+}
+
+/// Enum for the differences between a prologue or epilogue and the frame described by
+/// the table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameMismatch {
+    /// The general purpose register should be saved, but isn't.
+    MissingGprSave(u32),
+    /// The general purpose register is saved, but the table doesn't say so.
+    UnexpectedGprSave(u32),
+    /// The floating point register should be saved, but isn't.
+    MissingFprSave(u32),
+    /// The floating point register is saved, but the table doesn't say so.
+    UnexpectedFprSave(u32),
+    /// The condition register should be saved, but isn't.
+    MissingCrSave,
+    /// The condition register is saved, but the table doesn't say so.
+    UnexpectedCrSave,
+    /// The condition register is saved at a different offset from the top of the frame
+    /// than the CR save word (see `FrameDescription::cr_save_offset`).
+    MisplacedCrSave { expected: i32, actual: i32 },
+    /// The general purpose register should be restored, but isn't.
+    MissingGprRestore(u32),
+    /// The general purpose register is restored, but the table doesn't save it.
+    UnexpectedGprRestore(u32),
+    /// The floating point register should be restored, but isn't.
+    MissingFprRestore(u32),
+    /// The floating point register is restored, but the table doesn't save it.
+    UnexpectedFprRestore(u32),
+    /// The condition register should be restored, but isn't.
+    MissingCrRestore,
+    /// The condition register is restored, but the table doesn't save it.
+    UnexpectedCrRestore,
+    /// The table has a frame pointer, but the prologue doesn't set one up.
+    MissingFramePointer,
+    /// The prologue sets up a frame pointer in the given register, but the table
+    /// doesn't have one.
+    UnexpectedFramePointer(u32),
+    /// The table has a large frame, but the prologue allocates it with `stwu`.
+    MissingLargeFrame,
+    /// The prologue allocates the frame with `stwux`, but the table doesn't have a
+    /// large frame.
+    UnexpectedLargeFrame,
+}
+
+impl fmt::Display for FrameMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameMismatch::MissingGprSave(register) => {
+                write!(f, "r{register} should be saved but is not")
+            }
+            FrameMismatch::UnexpectedGprSave(register) => {
+                write!(f, "r{register} is saved but outside the GPR save range")
+            }
+            FrameMismatch::MissingFprSave(register) => {
+                write!(f, "f{register} should be saved but is not")
+            }
+            FrameMismatch::UnexpectedFprSave(register) => {
+                write!(f, "f{register} is saved but outside the FPR save range")
+            }
+            FrameMismatch::MissingCrSave => write!(f, "CR should be saved but is not"),
+            FrameMismatch::UnexpectedCrSave => {
+                write!(f, "CR is saved but the table does not have saved_cr set")
+            }
+            FrameMismatch::MisplacedCrSave { expected, actual } => write!(
+                f,
+                "CR is saved at {} from the top of the frame instead of {}",
+                signed_hex(*actual),
+                signed_hex(*expected)
+            ),
+            FrameMismatch::MissingGprRestore(register) => {
+                write!(f, "r{register} should be restored but is not")
+            }
+            FrameMismatch::UnexpectedGprRestore(register) => {
+                write!(f, "r{register} is restored but outside the GPR save range")
+            }
+            FrameMismatch::MissingFprRestore(register) => {
+                write!(f, "f{register} should be restored but is not")
+            }
+            FrameMismatch::UnexpectedFprRestore(register) => {
+                write!(f, "f{register} is restored but outside the FPR save range")
+            }
+            FrameMismatch::MissingCrRestore => write!(f, "CR should be restored but is not"),
+            FrameMismatch::UnexpectedCrRestore => {
+                write!(f, "CR is restored but the table does not have saved_cr set")
+            }
+            FrameMismatch::MissingFramePointer => {
+                write!(
+                    f,
+                    "Table has a frame pointer but the prologue does not set one up"
+                )
+            }
+            FrameMismatch::UnexpectedFramePointer(register) => write!(
+                f,
+                "Prologue sets up r{register} as a frame pointer but the table does not have one"
+            ),
+            FrameMismatch::MissingLargeFrame => {
+                write!(
+                    f,
+                    "Table has a large frame but the prologue allocates it with stwu"
+                )
+            }
+            FrameMismatch::UnexpectedLargeFrame => write!(
+                f,
+                "Prologue allocates the frame with stwux but the table does not have a large frame"
+            ),
+        }
+    }
+}
+
+fn signed_hex(value: i32) -> String {
+    if value < 0 {
+        format!("-{:#X}", -(value as i64))
+    } else {
+        format!("{value:#X}")
+    }
+}
+
+/// Returns the first register saved or restored by a call to a runtime function with
+/// the given name prefix, such as `_savegpr_27` or `_restgpr_27`.
+fn save_call_register(target: &str, prefix: &str) -> Option<u32> {
+    let register: u32 = target.strip_prefix(prefix)?.parse().ok()?;
+    (FIRST_NONVOLATILE_REGISTER..32)
+        .contains(&register)
+        .then_some(register)
+}
+
+/// Compares the registers saved or restored by the function with the expected ones.
+/// Missing registers aren't reported after a call without a target name, as it may be
+/// to a runtime save or restore function.
+fn compare_registers(
+    mismatches: &mut Vec<FrameMismatch>,
+    expected: &[u32],
+    found: &BTreeSet<u32>,
+    unnamed_call: bool,
+    missing: fn(u32) -> FrameMismatch,
+    unexpected: fn(u32) -> FrameMismatch,
+) {
+    let expected: BTreeSet<u32> = expected.iter().copied().collect();
+    if !unnamed_call {
+        mismatches.extend(expected.difference(found).copied().map(missing));
+    }
+    mismatches.extend(found.difference(&expected).copied().map(unexpected));
+}
+
+impl ExceptionTableData {
+    /// Returns the frame of the function as described by the header flags.
+    pub fn frame_description(&self) -> FrameDescription {
+        let saved_gprs = saved_registers(self.gpr_save_range);
+        let saved_fprs = saved_registers(self.fpr_save_range);
+        let gpr_save_styles = match saved_gprs.len() {
+            0 => vec![],
+            1 => vec![SaveStyle::Individual],
+            _ => vec![SaveStyle::Individual, SaveStyle::Multiple, SaveStyle::Call],
+        };
+        let fpr_save_styles = match saved_fprs.len() {
+            0 => vec![],
+            1 => vec![SaveStyle::Individual],
+            _ => vec![SaveStyle::Individual, SaveStyle::Call],
+        };
+
+        FrameDescription {
+            saved_gprs,
+            saved_fprs,
+            gpr_save_styles,
+            fpr_save_styles,
+            saves_cr: self.saved_cr,
+            has_frame_pointer: self.has_frame_pointer,
+            large_frame: self.large_frame,
+        }
+    }
+
+    /// Checks the prologue of the function against the frame described by the header
+    /// flags, returning every inconsistency found.
+    ///
+    /// Stores of the registers which LR and CR were moved to are treated as LR and CR
+    /// saves, and any other store of a non-volatile register as a register save. A
+    /// frame pointer is set up by moving r1 to a non-volatile register. Calls to
+    /// `_savegpr_N` and `_savefpr_N` are recognized by their target name, so missing
+    /// register saves aren't reported once a call without a target name is found, as it
+    /// may be one of them.
+    ///
+    /// CR saves relative to r1 are also checked against the CR save word, unless the
+    /// frame is allocated with `stwux` before them, as its size is then unknown.
+    pub fn check_prologue(&self, prologue: &[PrologueInstruction]) -> Vec<FrameMismatch> {
+        let description = self.frame_description();
+
+        let mut lr_register: Option<u32> = None;
+        let mut cr_register: Option<u32> = None;
+        let mut saved_gprs: BTreeSet<u32> = BTreeSet::new();
+        let mut saved_fprs: BTreeSet<u32> = BTreeSet::new();
+        let mut saves_cr = false;
+        //Offset of the CR save from the top of the frame, if it is known
+        let mut cr_save_offset: Option<i32> = None;
+        let mut frame_pointer: Option<u32> = None;
+        let mut large_frame = false;
+        let mut paired_singles = false;
+        let mut unnamed_call = false;
+        //Size of the frame once it is allocated, if it is known
+        let mut frame_size: Option<Option<u32>> = None;
+
+        for instruction in prologue {
+            match instruction {
+                PrologueInstruction::AllocateFrame { size } => frame_size = Some(Some(*size)),
+                PrologueInstruction::AllocateFrameIndexed { .. } => {
+                    large_frame = true;
+                    frame_size = Some(None);
+                }
+                PrologueInstruction::MoveFromLr { register } => lr_register = Some(*register),
+                PrologueInstruction::MoveFromCr { register } => cr_register = Some(*register),
+                PrologueInstruction::MoveRegister { dest, source }
+                    if *source == STACK_POINTER && *dest >= FIRST_NONVOLATILE_REGISTER =>
+                {
+                    frame_pointer = Some(*dest);
+                }
+                PrologueInstruction::StoreWord {
+                    register,
+                    base,
+                    offset,
+                } => {
+                    if Some(*register) == lr_register {
+                        continue;
+                    }
+                    if Some(*register) == cr_register {
+                        saves_cr = true;
+                        //Before the frame is allocated, r1 is the top of the frame
+                        cr_save_offset = match (*base, frame_size) {
+                            (STACK_POINTER, None) => Some(*offset as i32),
+                            (STACK_POINTER, Some(Some(size))) => Some(*offset as i32 - size as i32),
+                            _ => None,
+                        };
+                    } else if *register >= FIRST_NONVOLATILE_REGISTER {
+                        saved_gprs.insert(*register);
+                    }
+                }
+                PrologueInstruction::StoreMultiple { register, .. } => {
+                    saved_gprs.extend(*register..32);
+                }
+                PrologueInstruction::StoreDouble { register, .. }
+                | PrologueInstruction::StorePairedSingle { register, .. }
+                    if *register >= FIRST_NONVOLATILE_REGISTER =>
+                {
+                    saved_fprs.insert(*register);
+                    if matches!(instruction, PrologueInstruction::StorePairedSingle { .. }) {
+                        paired_singles = true;
+                    }
+                }
+                PrologueInstruction::Call {
+                    target: Some(target),
+                } => {
+                    if let Some(register) = save_call_register(target, "_savegpr_") {
+                        saved_gprs.extend(register..32);
+                    } else if let Some(register) = save_call_register(target, "_savefpr_") {
+                        saved_fprs.extend(register..32);
+                    }
+                }
+                PrologueInstruction::Call { target: None } => unnamed_call = true,
+                _ => {}
+            }
+        }
+
+        let mut mismatches: Vec<FrameMismatch> = vec![];
+        compare_registers(
+            &mut mismatches,
+            &description.saved_gprs,
+            &saved_gprs,
+            unnamed_call,
+            FrameMismatch::MissingGprSave,
+            FrameMismatch::UnexpectedGprSave,
+        );
+        compare_registers(
+            &mut mismatches,
+            &description.saved_fprs,
+            &saved_fprs,
+            unnamed_call,
+            FrameMismatch::MissingFprSave,
+            FrameMismatch::UnexpectedFprSave,
+        );
+
+        match (description.saves_cr, saves_cr) {
+            (true, false) => mismatches.push(FrameMismatch::MissingCrSave),
+            (false, true) => mismatches.push(FrameMismatch::UnexpectedCrSave),
+            _ => {}
+        }
+        if let (Some(expected), Some(actual)) =
+            (description.cr_save_offset(paired_singles), cr_save_offset)
+        {
+            if expected != actual {
+                mismatches.push(FrameMismatch::MisplacedCrSave { expected, actual });
+            }
+        }
+        match (description.has_frame_pointer, frame_pointer) {
+            (true, None) => mismatches.push(FrameMismatch::MissingFramePointer),
+            (false, Some(register)) => {
+                mismatches.push(FrameMismatch::UnexpectedFramePointer(register))
+            }
+            _ => {}
+        }
+        match (description.large_frame, large_frame) {
+            (true, false) => mismatches.push(FrameMismatch::MissingLargeFrame),
+            (false, true) => mismatches.push(FrameMismatch::UnexpectedLargeFrame),
+            _ => {}
+        }
+
+        mismatches
+    }
+
+    /// Checks the epilogue of the function against the frame described by the header
+    /// flags, returning the registers which are restored differently from how the
+    /// prologue saves them.
+    ///
+    /// Loads of volatile registers which are then moved to CR are treated as CR
+    /// restores, and any other load of a non-volatile register as a register restore.
+    /// As for `check_prologue`, calls to `_restgpr_N` and `_restfpr_N` are recognized by
+    /// their target name.
+    pub fn check_epilogue(&self, epilogue: &[EpilogueInstruction]) -> Vec<FrameMismatch> {
+        let description = self.frame_description();
+
+        let mut loaded_registers: BTreeSet<u32> = BTreeSet::new();
+        let mut restored_gprs: BTreeSet<u32> = BTreeSet::new();
+        let mut restored_fprs: BTreeSet<u32> = BTreeSet::new();
+        let mut restores_cr = false;
+        let mut unnamed_call = false;
+
+        for instruction in epilogue {
+            match instruction {
+                EpilogueInstruction::LoadWord { register, .. } => {
+                    if *register >= FIRST_NONVOLATILE_REGISTER {
+                        restored_gprs.insert(*register);
+                    } else {
+                        loaded_registers.insert(*register);
+                    }
+                }
+                EpilogueInstruction::MoveToCr { register } => {
+                    restores_cr |= loaded_registers.contains(register);
+                }
+                EpilogueInstruction::LoadMultiple { register, .. } => {
+                    restored_gprs.extend(*register..32);
+                }
+                EpilogueInstruction::LoadDouble { register, .. }
+                | EpilogueInstruction::LoadPairedSingle { register, .. }
+                    if *register >= FIRST_NONVOLATILE_REGISTER =>
+                {
+                    restored_fprs.insert(*register);
+                }
+                EpilogueInstruction::Call {
+                    target: Some(target),
+                } => {
+                    if let Some(register) = save_call_register(target, "_restgpr_") {
+                        restored_gprs.extend(register..32);
+                    } else if let Some(register) = save_call_register(target, "_restfpr_") {
+                        restored_fprs.extend(register..32);
+                    }
+                }
+                EpilogueInstruction::Call { target: None } => unnamed_call = true,
+                _ => {}
+            }
+        }
+
+        let mut mismatches: Vec<FrameMismatch> = vec![];
+        compare_registers(
+            &mut mismatches,
+            &description.saved_gprs,
+            &restored_gprs,
+            unnamed_call,
+            FrameMismatch::MissingGprRestore,
+            FrameMismatch::UnexpectedGprRestore,
+        );
+        compare_registers(
+            &mut mismatches,
+            &description.saved_fprs,
+            &restored_fprs,
+            unnamed_call,
+            FrameMismatch::MissingFprRestore,
+            FrameMismatch::UnexpectedFprRestore,
+        );
+        match (description.saves_cr, restores_cr) {
+            (true, false) => mismatches.push(FrameMismatch::MissingCrRestore),
+            (false, true) => mismatches.push(FrameMismatch::UnexpectedCrRestore),
+            _ => {}
+        }
+
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{EpilogueInstruction, FrameMismatch, PrologueInstruction};
+    use crate::text::parse_text;
+    use crate::ExceptionTableData;
+
+    const FUNCTION_ADDRESS: u32 = 0x80003100;
+
+    fn table(flags: &str) -> ExceptionTableData {
+        parse_text(&format!(".flags {flags}\n")).unwrap().0
+    }
+
+    fn decode(words: &[u32], resolver: Option<&HashMap<u32, String>>) -> Vec<PrologueInstruction> {
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let address = FUNCTION_ADDRESS + (i as u32) * 4;
+                PrologueInstruction::decode(*word, address, resolver.map(|r| r as _))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decode_paired_single_stores() {
+        assert_eq!(
+            PrologueInstruction::decode(0xF3E10018, FUNCTION_ADDRESS, None),
+            PrologueInstruction::StorePairedSingle {
+                register: 31,
+                base: 1,
+                offset: 0x18,
+            }
+        );
+        assert_eq!(
+            PrologueInstruction::decode(0xF3C18FF8, FUNCTION_ADDRESS, None),
+            PrologueInstruction::StorePairedSingle {
+                register: 30,
+                base: 1,
+                offset: -8,
+            }
+        );
+        assert_eq!(
+            PrologueInstruction::parse("psq_st f31, 0x18(r1), 0, 0"),
+            PrologueInstruction::StorePairedSingle {
+                register: 31,
+                base: 1,
+                offset: 0x18,
+            }
+        );
+    }
+
+    #[test]
+    fn paired_single_fpr_saves() {
+        let table = table("fpr_save_range=2");
+        //stwu r1, -0x30(r1); stfd f31, 0x28(r1); psq_st f31, 0x30(r1), 0, 0;
+        //stfd f30, 0x18(r1); psq_st f30, 0x20(r1), 0, 0
+        let prologue = decode(
+            &[0x9421FFD0, 0xDBE10028, 0xF3E10030, 0xDBC10018, 0xF3C10020],
+            None,
+        );
+        assert!(table.check_prologue(&prologue).is_empty());
+
+        //psq_st only
+        let prologue = decode(&[0x9421FFD0, 0xF3E10030], None);
+        assert_eq!(
+            table.check_prologue(&prologue),
+            vec![FrameMismatch::MissingFprSave(30)]
+        );
+    }
+
+    #[test]
+    fn resolved_save_calls() {
+        let table = table("gpr_save_range=3");
+        //stwu r1, -0x20(r1); bl _savegpr_29 at 0x80003000
+        let words = [0x9421FFE0, 0x4BFFFEFD];
+        let names = HashMap::from([(0x80003000, String::from("_savegpr_29"))]);
+        let prologue = decode(&words, Some(&names));
+        assert_eq!(
+            prologue[1],
+            PrologueInstruction::Call {
+                target: Some(String::from("_savegpr_29")),
+            }
+        );
+        assert!(table.check_prologue(&prologue).is_empty());
+
+        //Calls to other functions don't save anything
+        let names = HashMap::from([(0x80003000, String::from("memcpy"))]);
+        let prologue = decode(&words, Some(&names));
+        assert_eq!(
+            table.check_prologue(&prologue),
+            vec![
+                FrameMismatch::MissingGprSave(29),
+                FrameMismatch::MissingGprSave(30),
+                FrameMismatch::MissingGprSave(31),
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolved_calls_may_save_registers() {
+        let table = table("fpr_save_range=2, gpr_save_range=3");
+        //stwu r1, -0x40(r1); bl 0x80003000; stw r14, 0x8(r1)
+        let prologue = decode(&[0x9421FFC0, 0x4BFFFEFD, 0x91C10008], None);
+        assert_eq!(prologue[1], PrologueInstruction::Call { target: None });
+        //Only the unexpected saves are reported
+        assert_eq!(
+            table.check_prologue(&prologue),
+            vec![FrameMismatch::UnexpectedGprSave(14)]
+        );
+    }
+
+    fn decode_epilogue(words: &[u32]) -> Vec<EpilogueInstruction> {
+        words
+            .iter()
+            .map(|word| EpilogueInstruction::decode(*word, FUNCTION_ADDRESS, None))
+            .collect()
+    }
+
+    fn parse_prologue(asm: &str) -> Vec<PrologueInstruction> {
+        asm.lines().map(PrologueInstruction::parse).collect()
+    }
+
+    fn parse_epilogue(asm: &str) -> Vec<EpilogueInstruction> {
+        asm.lines().map(EpilogueInstruction::parse).collect()
+    }
+
+    #[test]
+    fn cr_save_slot() {
+        //The CR save word is right below r30-r31
+        let table = table("saved_cr, gpr_save_range=2");
+        let prologue = "
+stwu r1, -0x20(r1)
+mflr r0
+stw r0, 0x24(r1)
+mfcr r12
+stw r31, 0x1c(r1)
+stw r30, 0x18(r1)
+stw r12, 0x14(r1)
+";
+        assert!(table.check_prologue(&parse_prologue(prologue)).is_empty());
+
+        let misplaced = prologue.replace("stw r12, 0x14(r1)", "stw r12, 0x10(r1)");
+        let mismatches = table.check_prologue(&parse_prologue(&misplaced));
+        assert_eq!(
+            mismatches,
+            vec![FrameMismatch::MisplacedCrSave {
+                expected: -0xC,
+                actual: -0x10,
+            }]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "CR is saved at -0x10 from the top of the frame instead of -0xC"
+        );
+
+        //Before the frame is allocated, the offset is from the top of the frame
+        let before_allocation = "
+mfcr r12
+stw r31, -0x4(r1)
+stw r30, -0x8(r1)
+stw r12, -0xc(r1)
+stwu r1, -0x20(r1)
+";
+        assert!(table
+            .check_prologue(&parse_prologue(before_allocation))
+            .is_empty());
+    }
+
+    #[test]
+    fn paired_single_cr_save_slot() {
+        //FPRs saved as paired singles take 16 bytes each
+        let table = table("saved_cr, fpr_save_range=1, gpr_save_range=1");
+        let prologue = parse_prologue(
+            "
+stwu r1, -0x30(r1)
+mfcr r12
+stfd f31, 0x20(r1)
+psq_st f31, 0x28(r1), 0, 0
+stw r31, 0x1c(r1)
+stw r12, 0x18(r1)
+",
+        );
+        assert!(table.check_prologue(&prologue).is_empty());
+    }
+
+    #[test]
+    fn decode_epilogue_words() {
+        //psq_l f31, 0x48(r1), 0, 0; lfd f31, 0x40(r1); lmw r29, 0x2c(r1);
+        //lwz r12, 0x28(r1); mtcrf 0xff, r12; lwz r0, 0x54(r1); mtlr r0;
+        //addi r1, r1, 0x50; blr
+        let words = [
+            0xE3E10048, 0xCBE10040, 0xBBA1002C, 0x81810028, 0x7D8FF120, 0x80010054, 0x7C0803A6,
+            0x38210050, 0x4E800020,
+        ];
+        let decoded = decode_epilogue(&words);
+        let parsed = parse_epilogue(EPILOGUE);
+        assert_eq!(decoded, parsed[1..]);
+        assert_eq!(
+            decoded[0],
+            EpilogueInstruction::LoadPairedSingle {
+                register: 31,
+                base: 1,
+                offset: 0x48,
+            }
+        );
+        assert_eq!(decoded[4], EpilogueInstruction::MoveToCr { register: 12 });
+        assert_eq!(decoded[6], EpilogueInstruction::MoveToLr { register: 0 });
+        assert_eq!(
+            decoded[7],
+            EpilogueInstruction::DeallocateFrame { size: 0x50 }
+        );
+        assert_eq!(decoded[8], EpilogueInstruction::Return);
+    }
+
+    const EPILOGUE: &str = "
+psq_l f31, 0x48(r1), 0, 0
+lfd f31, 0x40(r1)
+lmw r29, 0x2c(r1)
+lwz r12, 0x28(r1)
+mtcrf 0xff, r12
+lwz r0, 0x54(r1)
+mtlr r0
+addi r1, r1, 0x50
+blr
+";
+
+    #[test]
+    fn epilogue_restores() {
+        let table = table("saved_cr, fpr_save_range=1, gpr_save_range=3");
+        assert!(table.check_epilogue(&parse_epilogue(EPILOGUE)).is_empty());
+
+        let epilogue = EPILOGUE
+            .replace("lmw r29", "lmw r28")
+            .replace("lfd f31, 0x40(r1)", "lfd f30, 0x40(r1)")
+            .replace("mtcrf 0xff, r12", "");
+        assert_eq!(
+            table.check_epilogue(&parse_epilogue(&epilogue)),
+            vec![
+                FrameMismatch::UnexpectedGprRestore(28),
+                FrameMismatch::UnexpectedFprRestore(30),
+                FrameMismatch::MissingCrRestore,
+            ]
+        );
+    }
+
+    #[test]
+    fn lr_restore_is_not_cr_restore() {
+        //LR is loaded into a volatile register too, but only loads moved to CR restore it
+        let table = table("gpr_save_range=3");
+        let epilogue = EPILOGUE
+            .replace("psq_l f31, 0x48(r1), 0, 0", "")
+            .replace("lfd f31, 0x40(r1)", "")
+            .replace("lwz r12, 0x28(r1)", "")
+            .replace("mtcrf 0xff, r12", "");
+        assert!(table.check_epilogue(&parse_epilogue(&epilogue)).is_empty());
+        let epilogue = epilogue.replace("mtlr r0", "mtcr r0");
+        assert_eq!(
+            table.check_epilogue(&parse_epilogue(&epilogue)),
+            vec![FrameMismatch::UnexpectedCrRestore]
+        );
+    }
+
+    #[test]
+    fn epilogue_restore_calls() {
+        let table = table("fpr_save_range=2, gpr_save_range=3");
+        let epilogue = "
+addi r11, r1, 0x40
+bl _restfpr_30
+bl _restgpr_29
+lwz r0, 0x44(r1)
+mtlr r0
+addi r1, r1, 0x40
+blr
+";
+        assert!(table.check_epilogue(&parse_epilogue(epilogue)).is_empty());
+
+        let epilogue = epilogue.replace("_restgpr_29", "_restgpr_30");
+        assert_eq!(
+            table.check_epilogue(&parse_epilogue(&epilogue)),
+            vec![FrameMismatch::MissingGprRestore(29)]
+        );
+
+        //Unnamed calls may be to the restore functions
+        let epilogue = decode_epilogue(&[0x4BFFFEFD, 0x4E800020]);
+        assert!(table.check_epilogue(&epilogue).is_empty());
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::{decode_extab, find_table_size, mem_utils, Severity};
+
+/// Size of an extabindex entry (function address, function size, extab pointer).
+pub const EXTABINDEX_ENTRY_SIZE: u32 = 12;
+
+#[derive(Error, Debug)]
+pub enum ExtabIndexError {
+    #[error("extabindex section size 0x{0:X} is not a multiple of 12")]
+    InvalidIndexSize(u32),
+}
+
+/// Struct for an extabindex entry, which points the runtime to the exception table
+/// of a function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtabIndexEntry {
+    pub function_address: u32,
+    pub function_size: u32,
+    pub extab_address: u32, //Address of the function's table in the extab section
+}
+
+impl ExtabIndexEntry {
+    /// Returns the address right after the end of the function.
+    pub fn function_end(&self) -> u32 {
+        self.function_address.wrapping_add(self.function_size)
+    }
+
+    /// Returns the offset of the entry's table in an extab section at the given address,
+    /// or `None` if the table is outside of the section.
+    pub fn extab_offset(&self, extab_address: u32, extab_size: u32) -> Option<u32> {
+        self.extab_address
+            .checked_sub(extab_address)
+            .filter(|offset| *offset < extab_size)
+    }
+}
+
+/// Parses the entries of an extabindex section.
+pub fn parse_extabindex(data: &[u8]) -> Result<Vec<ExtabIndexEntry>, ExtabIndexError> {
+    let index_size = data.len() as u32;
+    if index_size % EXTABINDEX_ENTRY_SIZE != 0 {
+        return Err(ExtabIndexError::InvalidIndexSize(index_size));
+    }
+
+    let mut entries: Vec<ExtabIndexEntry> = vec![];
+    let mut offset: i32 = 0;
+    //The size is checked to be a multiple of the entry size, so the reads can't fail
+    let mut read = || mem_utils::read_uint32(data, &mut offset, true).unwrap_or(0);
+    for _ in 0..index_size / EXTABINDEX_ENTRY_SIZE {
+        entries.push(ExtabIndexEntry {
+            function_address: read(),
+            function_size: read(),
+            extab_address: read(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Enum for the problems found by `validate_extabindex`. Entries are given by their
+/// offset in the extabindex section, and tables by their offset in the extab section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IndexDiagnosticKind {
+    /// The entry's function starts before the previous entry's function.
+    UnsortedEntry {
+        entry_offset: u32,
+        function_address: u32,
+        previous_function_address: u32,
+    },
+    /// The entry's function overlaps the previous entry's function.
+    OverlappingEntry {
+        entry_offset: u32,
+        function_address: u32,
+        previous_function_end: u32,
+    },
+    /// The entry's table pointer is outside of the extab section.
+    InvalidTablePointer {
+        entry_offset: u32,
+        extab_address: u32,
+    },
+    /// The entry's table pointer doesn't point to the start of a table.
+    MisalignedTablePointer {
+        entry_offset: u32,
+        table_offset: u32,
+    },
+    /// The entry's table could not be decoded.
+    InvalidTable {
+        entry_offset: u32,
+        table_offset: u32,
+    },
+    /// A PC range of the entry's table ends after the end of the function.
+    PCRangeOutsideFunction {
+        entry_offset: u32,
+        end_pc: u32,
+        function_size: u32,
+    },
+    /// The table is pointed to by more than one entry.
+    DuplicateTableReference { table_offset: u32, references: u32 },
+    /// The table is not pointed to by any entry.
+    UnreferencedTable { table_offset: u32 },
+}
+
+impl fmt::Display for IndexDiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexDiagnosticKind::UnsortedEntry {
+                entry_offset,
+                function_address,
+                previous_function_address,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: function at {function_address:08X} comes after the function at {previous_function_address:08X}"
+            ),
+            IndexDiagnosticKind::OverlappingEntry {
+                entry_offset,
+                function_address,
+                previous_function_end,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: function at {function_address:08X} overlaps the previous function ending at {previous_function_end:08X}"
+            ),
+            IndexDiagnosticKind::InvalidTablePointer {
+                entry_offset,
+                extab_address,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: table pointer {extab_address:08X} is outside of the extab section"
+            ),
+            IndexDiagnosticKind::MisalignedTablePointer {
+                entry_offset,
+                table_offset,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: table pointer to {table_offset:06X} does not point to the start of a table"
+            ),
+            IndexDiagnosticKind::InvalidTable {
+                entry_offset,
+                table_offset,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: table at {table_offset:06X} could not be decoded"
+            ),
+            IndexDiagnosticKind::PCRangeOutsideFunction {
+                entry_offset,
+                end_pc,
+                function_size,
+            } => write!(
+                f,
+                "Entry at {entry_offset:06X}: PC range ending at {end_pc:08X} is outside of the function of size {function_size:08X}"
+            ),
+            IndexDiagnosticKind::DuplicateTableReference {
+                table_offset,
+                references,
+            } => write!(
+                f,
+                "Table at {table_offset:06X} is referenced by {references} entries"
+            ),
+            IndexDiagnosticKind::UnreferencedTable { table_offset } => {
+                write!(f, "Table at {table_offset:06X} is not referenced by any entry")
+            }
+        }
+    }
+}
+
+/// Struct for a problem found in an extabindex section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexDiagnostic {
+    pub severity: Severity,
+    pub kind: IndexDiagnosticKind,
+}
+
+impl fmt::Display for IndexDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.kind)
+    }
+}
+
+/// Checks the entries of an extabindex section against each other and against the
+/// extab section at the given address, returning a diagnostic for every problem found.
+///
+/// The table boundaries are found from the table actions (see `find_table_size`), so
+/// that tables which no entry points to can be found. If the boundaries can't be found
+/// past some point, tables after it are only checked through the entries.
+pub fn validate_extabindex(
+    entries: &[ExtabIndexEntry],
+    extab: &[u8],
+    extab_address: u32,
+) -> Vec<IndexDiagnostic> {
+    let mut diagnostics: Vec<IndexDiagnostic> = vec![];
+    let mut push = |severity: Severity, kind: IndexDiagnosticKind| {
+        diagnostics.push(IndexDiagnostic { severity, kind });
+    };
+    let extab_size = extab.len() as u32;
+
+    //Check the order of the entries
+    for (i, pair) in entries.windows(2).enumerate() {
+        let (previous, entry) = (&pair[0], &pair[1]);
+        let entry_offset = (i as u32 + 1) * EXTABINDEX_ENTRY_SIZE;
+        if entry.function_address < previous.function_address {
+            let kind = IndexDiagnosticKind::UnsortedEntry {
+                entry_offset,
+                function_address: entry.function_address,
+                previous_function_address: previous.function_address,
+            };
+            push(Severity::Warning, kind);
+        } else if entry.function_address < previous.function_end() {
+            let kind = IndexDiagnosticKind::OverlappingEntry {
+                entry_offset,
+                function_address: entry.function_address,
+                previous_function_end: previous.function_end(),
+            };
+            push(Severity::Error, kind);
+        }
+    }
+
+    //Find the table boundaries from the tables themselves, as far as possible
+    let mut table_starts: BTreeSet<u32> = BTreeSet::new();
+    let mut scanned_end: u32 = 0;
+    while scanned_end < extab_size {
+        let remaining = &extab[scanned_end as usize..];
+        if remaining.iter().all(|byte| *byte == 0) {
+            scanned_end = extab_size;
+            break;
+        }
+        match find_table_size(remaining) {
+            Ok(size) => {
+                table_starts.insert(scanned_end);
+                scanned_end += size;
+            }
+            Err(_) => break,
+        }
+    }
+
+    //Count the references to each table
+    let mut references: HashMap<u32, u32> = HashMap::new();
+    let mut entry_tables: Vec<(u32, &ExtabIndexEntry, u32)> = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let entry_offset = (i as u32) * EXTABINDEX_ENTRY_SIZE;
+        let table_offset = match entry.extab_offset(extab_address, extab_size) {
+            Some(offset) => offset,
+            None => {
+                let kind = IndexDiagnosticKind::InvalidTablePointer {
+                    entry_offset,
+                    extab_address: entry.extab_address,
+                };
+                push(Severity::Error, kind);
+                continue;
+            }
+        };
+        if table_offset < scanned_end && !table_starts.contains(&table_offset) {
+            let kind = IndexDiagnosticKind::MisalignedTablePointer {
+                entry_offset,
+                table_offset,
+            };
+            push(Severity::Error, kind);
+            continue;
+        }
+
+        *references.entry(table_offset).or_insert(0) += 1;
+        entry_tables.push((entry_offset, entry, table_offset));
+    }
+
+    //Tables past the scanned part of the section are only known through the entries
+    table_starts.extend(references.keys().copied());
+
+    //Check the PC ranges of each table against its function
+    for (entry_offset, entry, table_offset) in entry_tables {
+        let table_end = table_starts
+            .range(table_offset + 1..)
+            .next()
+            .copied()
+            .unwrap_or(extab_size);
+        let data = match decode_extab(&extab[table_offset as usize..table_end as usize]) {
+            Ok(data) => data,
+            Err(_) => {
+                let kind = IndexDiagnosticKind::InvalidTable {
+                    entry_offset,
+                    table_offset,
+                };
+                push(Severity::Error, kind);
+                continue;
+            }
+        };
+        for pcaction in &data.pc_actions {
+            if pcaction.end_pc > entry.function_size {
+                let kind = IndexDiagnosticKind::PCRangeOutsideFunction {
+                    entry_offset,
+                    end_pc: pcaction.end_pc,
+                    function_size: entry.function_size,
+                };
+                push(Severity::Error, kind);
+            }
+        }
+    }
+
+    //Check that every table is referenced exactly once
+    for table_offset in table_starts {
+        match references.get(&table_offset).copied().unwrap_or(0) {
+            0 => push(
+                Severity::Warning,
+                IndexDiagnosticKind::UnreferencedTable { table_offset },
+            ),
+            1 => {}
+            count => push(
+                Severity::Error,
+                IndexDiagnosticKind::DuplicateTableReference {
+                    table_offset,
+                    references: count,
+                },
+            ),
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_extabindex, validate_extabindex, ExtabIndexEntry, ExtabIndexError, IndexDiagnostic,
+        IndexDiagnosticKind,
+    };
+    use crate::fixtures::{DESTROY_LOCALS_TABLE, DESTROY_LOCAL_TABLE};
+    use crate::Severity;
+
+    const EXTAB_ADDRESS: u32 = 0x80005000;
+
+    /// Returns an extab section with a table for each of the functions of `entries`.
+    fn extab_section() -> Vec<u8> {
+        [&DESTROY_LOCAL_TABLE[..], &DESTROY_LOCALS_TABLE].concat()
+    }
+
+    /// Returns the entries of two functions following each other, pointing to the
+    /// tables of `extab_section` in order.
+    fn entries() -> Vec<ExtabIndexEntry> {
+        vec![
+            ExtabIndexEntry {
+                function_address: 0x80003100,
+                function_size: 0x10,
+                extab_address: EXTAB_ADDRESS,
+            },
+            ExtabIndexEntry {
+                function_address: 0x80003110,
+                function_size: 0x60,
+                extab_address: EXTAB_ADDRESS + DESTROY_LOCAL_TABLE.len() as u32,
+            },
+        ]
+    }
+
+    fn validate(entries: &[ExtabIndexEntry]) -> Vec<IndexDiagnostic> {
+        validate_extabindex(entries, &extab_section(), EXTAB_ADDRESS)
+    }
+
+    fn diagnostic(severity: Severity, kind: IndexDiagnosticKind) -> IndexDiagnostic {
+        IndexDiagnostic { severity, kind }
+    }
+
+    #[rustfmt::skip]
+    const EXTABINDEX_SECTION: [u8; 24] = [
+        0x80, 0x00, 0x31, 0x00, 0x00, 0x00, 0x00, 0x10, 0x80, 0x00, 0x50, 0x00, //Entry
+        0x80, 0x00, 0x31, 0x10, 0x00, 0x00, 0x00, 0x60, 0x80, 0x00, 0x50, 0x18, //Entry
+    ];
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_extabindex(&EXTABINDEX_SECTION).unwrap(), entries());
+        assert!(parse_extabindex(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_index_size() {
+        for size in [4, 20, 25] {
+            let data = [EXTABINDEX_SECTION.to_vec(), vec![0; 12]].concat();
+            assert!(matches!(
+                parse_extabindex(&data[..size]),
+                Err(ExtabIndexError::InvalidIndexSize(index_size)) if index_size == size as u32
+            ));
+        }
+    }
+
+    #[test]
+    fn valid_index() {
+        assert!(validate(&entries()).is_empty());
+    }
+
+    #[test]
+    fn unsorted_entry() {
+        let mut entries = entries();
+        entries.swap(0, 1);
+        //The tables are still referenced once each, just in a different order
+        assert_eq!(
+            validate(&entries),
+            vec![diagnostic(
+                Severity::Warning,
+                IndexDiagnosticKind::UnsortedEntry {
+                    entry_offset: 12,
+                    function_address: 0x80003100,
+                    previous_function_address: 0x80003110,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn overlapping_entry() {
+        let mut entries = entries();
+        entries[1].function_address = 0x80003108;
+        assert_eq!(
+            validate(&entries),
+            vec![diagnostic(
+                Severity::Error,
+                IndexDiagnosticKind::OverlappingEntry {
+                    entry_offset: 12,
+                    function_address: 0x80003108,
+                    previous_function_end: 0x80003110,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_table_pointer() {
+        let mut entries = entries();
+        entries[1].extab_address = EXTAB_ADDRESS + extab_section().len() as u32;
+        assert_eq!(
+            validate(&entries),
+            vec![
+                diagnostic(
+                    Severity::Error,
+                    IndexDiagnosticKind::InvalidTablePointer {
+                        entry_offset: 12,
+                        extab_address: 0x80005040,
+                    }
+                ),
+                diagnostic(
+                    Severity::Warning,
+                    IndexDiagnosticKind::UnreferencedTable { table_offset: 0x18 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn misaligned_table_pointer() {
+        let mut entries = entries();
+        entries[1].extab_address += 4;
+        assert_eq!(
+            validate(&entries),
+            vec![
+                diagnostic(
+                    Severity::Error,
+                    IndexDiagnosticKind::MisalignedTablePointer {
+                        entry_offset: 12,
+                        table_offset: 0x1C,
+                    }
+                ),
+                diagnostic(
+                    Severity::Warning,
+                    IndexDiagnosticKind::UnreferencedTable { table_offset: 0x18 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_table() {
+        //An unknown action type stops the scan of the tables, so the second table is
+        //only known through its entry
+        let mut extab = extab_section();
+        extab[DESTROY_LOCAL_TABLE.len() + 0x18] = 0x7F;
+        assert_eq!(
+            validate_extabindex(&entries(), &extab, EXTAB_ADDRESS),
+            vec![diagnostic(
+                Severity::Error,
+                IndexDiagnosticKind::InvalidTable {
+                    entry_offset: 12,
+                    table_offset: 0x18,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn pc_range_outside_function() {
+        let mut entries = entries();
+        entries[1].function_size = 0x40;
+        assert_eq!(
+            validate(&entries),
+            vec![diagnostic(
+                Severity::Error,
+                IndexDiagnosticKind::PCRangeOutsideFunction {
+                    entry_offset: 12,
+                    end_pc: 0x50,
+                    function_size: 0x40,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicate_table_reference() {
+        let mut entries = entries();
+        entries[1].extab_address = EXTAB_ADDRESS;
+        assert_eq!(
+            validate(&entries),
+            vec![
+                diagnostic(
+                    Severity::Error,
+                    IndexDiagnosticKind::DuplicateTableReference {
+                        table_offset: 0,
+                        references: 2,
+                    }
+                ),
+                diagnostic(
+                    Severity::Warning,
+                    IndexDiagnosticKind::UnreferencedTable { table_offset: 0x18 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreferenced_table() {
+        assert_eq!(
+            validate(&entries()[..1]),
+            vec![diagnostic(
+                Severity::Warning,
+                IndexDiagnosticKind::UnreferencedTable { table_offset: 0x18 }
+            )]
+        );
+    }
+}
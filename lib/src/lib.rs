@@ -1,6 +1,74 @@
+use std::collections::HashMap;
+
 use thiserror::Error;
 
+/// Logs an error through the `log` crate if the `log` feature is enabled. The library
+/// never prints anything itself.
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::error!($($arg)*);
+    };
+}
+
+/// Logs a warning through the `log` crate if the `log` feature is enabled.
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+    };
+}
+
+mod asm;
+mod chain;
+mod diff;
+pub mod dol;
+pub mod elf;
+mod encoder;
+#[cfg(test)]
+mod fixtures;
+mod frame;
+mod index;
 mod mem_utils;
+mod operands;
+pub mod rel;
+mod scope;
+mod section;
+mod simulate;
+mod symbols;
+pub mod text;
+mod typeinfo;
+mod validate;
+mod view;
+
+pub use asm::{AsmIndexEntry, AsmOptions};
+pub use chain::ActionChainError;
+pub use diff::TableDifference;
+pub use frame::{
+    EpilogueInstruction, FrameDescription, FrameMismatch, PrologueInstruction, SaveStyle,
+};
+pub use index::{
+    parse_extabindex, validate_extabindex, ExtabIndexEntry, ExtabIndexError, IndexDiagnostic,
+    IndexDiagnosticKind, EXTABINDEX_ENTRY_SIZE,
+};
+pub use operands::{ActionOperands, FrameBase, Location, MemberLocation};
+pub use scope::{ScopeNode, ScopeTree};
+pub use section::{decode_extab_section, find_table_size, ExtabSectionError, SectionTable};
+pub use simulate::{FrameModel, UnwindObject, UnwindOutcome, UnwindStep, UnwindTrace};
+pub use symbols::{SymbolMap, SymbolMapError, SymbolResolver};
+pub use text::{parse_text, TextParseError};
+pub use typeinfo::{
+    HandlerMatch, HandlerSelection, MemoryReader, TypeBase, TypeHierarchy, TypeInfo, TypeInfoError,
+};
+pub use validate::{Diagnostic, DiagnosticKind, Severity};
+pub use view::{ActionIter, ExceptionActionRef, ExceptionTable, PCActionIter};
+
+/// Size of the table header (flag value and et field).
+pub(crate) const HEADER_SIZE: u32 = 4;
+/// Size of a PC action range entry.
+pub(crate) const PC_ACTION_SIZE: u32 = 8;
+/// Mask of the flag bits which don't have a name.
+pub(crate) const UNKNOWN_FLAG_BITS: u16 = 0b101;
 
 #[derive(Error, Debug)]
 pub enum ExtabDecodeError {
@@ -10,12 +78,43 @@ pub enum ExtabDecodeError {
     InvalidActionValue(u32, u32),
     #[error("Table is 8 bytes long but terminator is not zero.")]
     InvalidSmallTableTerminator,
+    #[error("Truncated PC action range entry at offset 0x{0:X}")]
+    TruncatedRangeEntry(u32),
+    #[error("PC action range entry at offset 0x{0:X} overflows the address space")]
+    InvalidPCRange(u32),
+    #[error("Truncated action entry header at offset 0x{0:X}")]
+    TruncatedActionHeader(u32),
+    #[error("Truncated {0:?} action payload at offset 0x{1:X}")]
+    TruncatedActionPayload(ExAction, u32),
     #[error("Internal error")]
     Internal,
 }
 
+#[derive(Error, Debug)]
+pub enum ExtabEncodeError {
+    #[error("FPR save range {0} does not fit in 5 bits")]
+    InvalidFprSaveRange(u32),
+    #[error("GPR save range {0} does not fit in 5 bits")]
+    InvalidGprSaveRange(u32),
+    #[error("PC action start pc cannot be zero, as it would be read as the terminator")]
+    ZeroStartPC,
+    #[error("Invalid PC action range {0:08X}:{1:08X}")]
+    InvalidPCRange(u32, u32),
+    #[error("PC action offset 0x{0:X} does not fit in 16 bits")]
+    ActionOffsetTooLarge(u32),
+    #[error("Action at offset 0x{0:X} has offset 0x{1:X} set")]
+    ActionOffsetMismatch(u32, u32),
+    #[error("Action at offset 0x{0:X} has {1} bytes of data, which don't match its type {2:?}")]
+    InvalidActionData(u32, u32, ExAction),
+}
+
 /// Enum holding the data for each action type.
+///
+/// With the `serde` feature, this is serialized as a map of the fields along with
+/// a `type` field holding the name of the variant.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type"))]
 pub enum ExActionData {
     EndOfList,
     Branch {
@@ -101,6 +200,7 @@ pub enum ExActionData {
 
 /// Base enum for exception actions.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExAction {
     EndOfList,
     Branch,
@@ -194,10 +294,225 @@ impl ExAction {
     fn convert_to_string(&self) -> String {
         String::from(Self::ACTION_NAMES[self.to_int() as usize])
     }
+
+    /// Returns the size of the data following the action type and param bytes. For
+    /// specifications, this does not include the array of 32 bit type values that
+    /// follows the fixed size data.
+    pub fn base_data_size(&self) -> u32 {
+        match self {
+            ExAction::EndOfList => 0,
+            ExAction::Branch => 2,
+            ExAction::DestroyLocal => 6,
+            ExAction::DestroyLocalCond => 10,
+            ExAction::DestroyLocalPointer => 6,
+            ExAction::DestroyLocalArray => 10,
+            ExAction::DestroyBase | ExAction::DestroyMember => 10,
+            ExAction::DestroyMemberCond => 14,
+            ExAction::DestroyMemberArray => 18,
+            ExAction::DeletePointer => 6,
+            ExAction::DeletePointerCond => 10,
+            ExAction::CatchBlock => 10,
+            ExAction::ActiveCatchBlock => 2,
+            ExAction::Terminate => 0,
+            ExAction::Specification => 10,
+            ExAction::CatchBlock32 => 14,
+        }
+    }
+}
+
+impl ExActionData {
+    /// Returns the action type this data belongs to.
+    pub fn action_type(&self) -> ExAction {
+        match self {
+            ExActionData::EndOfList => ExAction::EndOfList,
+            ExActionData::Branch { .. } => ExAction::Branch,
+            ExActionData::DestroyLocal { .. } => ExAction::DestroyLocal,
+            ExActionData::DestroyLocalCond { .. } => ExAction::DestroyLocalCond,
+            ExActionData::DestroyLocalPointer { .. } => ExAction::DestroyLocalPointer,
+            ExActionData::DestroyLocalArray { .. } => ExAction::DestroyLocalArray,
+            ExActionData::DestroyBase { .. } => ExAction::DestroyBase,
+            ExActionData::DestroyMember { .. } => ExAction::DestroyMember,
+            ExActionData::DestroyMemberCond { .. } => ExAction::DestroyMemberCond,
+            ExActionData::DestroyMemberArray { .. } => ExAction::DestroyMemberArray,
+            ExActionData::DeletePointer { .. } => ExAction::DeletePointer,
+            ExActionData::DeletePointerCond { .. } => ExAction::DeletePointerCond,
+            ExActionData::CatchBlock { .. } => ExAction::CatchBlock,
+            ExActionData::ActiveCatchBlock { .. } => ExAction::ActiveCatchBlock,
+            ExActionData::Terminate => ExAction::Terminate,
+            ExActionData::Specification { .. } => ExAction::Specification,
+            ExActionData::CatchBlock32 { .. } => ExAction::CatchBlock32,
+        }
+    }
+
+    /// Returns the size of the encoded data, including the type array of specifications.
+    pub fn data_size(&self) -> u32 {
+        let mut size = self.action_type().base_data_size();
+        if let ExActionData::Specification { spec, .. } = self {
+            size += (spec.len() as u32) * 4;
+        }
+        size
+    }
+
+    /// Encodes the data into the big endian byte layout used by the action entry.
+    ///
+    /// The type count of a specification is taken from the length of `spec`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.data_size() as usize);
+
+        match self {
+            ExActionData::EndOfList | ExActionData::Terminate => {}
+            ExActionData::Branch { target_offset } => {
+                mem_utils::write_uint16(&mut bytes, *target_offset);
+            }
+            ExActionData::DestroyLocal {
+                local_offset,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *local_offset);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyLocalCond {
+                condition,
+                local_offset,
+                unk4,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *condition);
+                mem_utils::write_uint16(&mut bytes, *local_offset);
+                mem_utils::write_uint16(&mut bytes, *unk4);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyLocalPointer {
+                local_pointer,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *local_pointer);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyLocalArray {
+                local_array,
+                elements,
+                element_size,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *local_array);
+                mem_utils::write_uint16(&mut bytes, *elements);
+                mem_utils::write_uint16(&mut bytes, *element_size);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyBase {
+                object_pointer,
+                member_offset,
+                dtor_address,
+            }
+            | ExActionData::DestroyMember {
+                object_pointer,
+                member_offset,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *object_pointer);
+                mem_utils::write_uint32(&mut bytes, *member_offset);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyMemberCond {
+                condition,
+                object_pointer,
+                member_offset,
+                unk8,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *condition);
+                mem_utils::write_uint16(&mut bytes, *object_pointer);
+                mem_utils::write_uint32(&mut bytes, *member_offset);
+                mem_utils::write_uint16(&mut bytes, *unk8);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DestroyMemberArray {
+                object_pointer,
+                member_offset,
+                elements,
+                element_size,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *object_pointer);
+                mem_utils::write_uint32(&mut bytes, *member_offset);
+                mem_utils::write_uint32(&mut bytes, *elements);
+                mem_utils::write_uint32(&mut bytes, *element_size);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DeletePointer {
+                object_pointer,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *object_pointer);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::DeletePointerCond {
+                condition,
+                object_pointer,
+                unk4,
+                dtor_address,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *condition);
+                mem_utils::write_uint16(&mut bytes, *object_pointer);
+                mem_utils::write_uint16(&mut bytes, *unk4);
+                mem_utils::write_uint32(&mut bytes, *dtor_address);
+            }
+            ExActionData::CatchBlock {
+                unk0,
+                catch_type,
+                catch_pc_offset,
+                cinfo_ref,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *unk0);
+                mem_utils::write_uint32(&mut bytes, *catch_type);
+                mem_utils::write_uint16(&mut bytes, *catch_pc_offset);
+                mem_utils::write_uint16(&mut bytes, *cinfo_ref);
+            }
+            ExActionData::ActiveCatchBlock { cinfo_ref } => {
+                mem_utils::write_uint16(&mut bytes, *cinfo_ref);
+            }
+            ExActionData::Specification {
+                pc_offset,
+                cinfo_ref,
+                spec,
+                ..
+            } => {
+                mem_utils::write_uint16(&mut bytes, spec.len() as u16);
+                mem_utils::write_uint32(&mut bytes, *pc_offset);
+                mem_utils::write_uint32(&mut bytes, *cinfo_ref);
+                for type_address in spec {
+                    mem_utils::write_uint32(&mut bytes, *type_address);
+                }
+            }
+            ExActionData::CatchBlock32 {
+                unk0,
+                catch_type,
+                catch_pc_offset,
+                cinfo_ref,
+            } => {
+                mem_utils::write_uint16(&mut bytes, *unk0);
+                mem_utils::write_uint32(&mut bytes, *catch_type);
+                mem_utils::write_uint32(&mut bytes, *catch_pc_offset);
+                mem_utils::write_uint32(&mut bytes, *cinfo_ref);
+            }
+        }
+
+        bytes
+    }
 }
 
 /// Struct for exception actions.
+///
+/// With the `serde` feature, the action data is serialized in its decoded form
+/// (see `SerializedAction`) rather than as raw bytes. Serializing an action whose data
+/// is too short for its type gives an error.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(from = "SerializedAction")
+)]
 pub struct ExceptionAction {
     //General values
     pub action_offset: u32,
@@ -218,34 +533,43 @@ impl ExceptionAction {
         }
     }
 
+    /// Creates an action from its decoded data, encoding the data into the byte array.
+    ///
+    /// The action offset is left at zero, and should be set to the position of the
+    /// action in the table before encoding it.
+    pub fn from_data(data: ExActionData, has_end_bit: bool, action_param: u8) -> Self {
+        Self {
+            action_offset: 0,
+            action_type: data.action_type(),
+            action_param,
+            has_end_bit,
+            bytes: data.to_bytes(),
+        }
+    }
+
     /// Returns whether this action has a destuctor reference or not.
     pub fn has_dtor_ref(&self) -> bool {
-        match self.action_type {
-            ExAction::EndOfList => {
-                println!("Warning: null action passed");
-                false
-            }
-            ExAction::Branch
-            | ExAction::CatchBlock
-            | ExAction::ActiveCatchBlock
-            | ExAction::Terminate
-            | ExAction::Specification
-            | ExAction::CatchBlock32 => false,
-            _ => true,
-        }
+        !matches!(
+            self.action_type,
+            ExAction::EndOfList
+                | ExAction::Branch
+                | ExAction::CatchBlock
+                | ExAction::ActiveCatchBlock
+                | ExAction::Terminate
+                | ExAction::Specification
+                | ExAction::CatchBlock32
+        )
     }
 
     /// Calculates the offset of the dtor function address value in this action entry.
     /// If the entry does not have one, this function returns none.
     fn get_dtor_address_value_offset(&self) -> Option<u32> {
-        let offset: u32 =
-        match self.action_type {
+        let offset: u32 = match self.action_type {
             ExAction::DestroyLocal => 2,
             ExAction::DestroyLocalCond => 6,
             ExAction::DestroyLocalPointer => 2,
             ExAction::DestroyLocalArray => 6,
-            ExAction::DestroyBase
-            | ExAction::DestroyMember => 6,
+            ExAction::DestroyBase | ExAction::DestroyMember => 6,
             ExAction::DestroyMemberCond => 10,
             ExAction::DestroyMemberArray => 14,
             ExAction::DeletePointer => 2,
@@ -266,39 +590,115 @@ impl ExceptionAction {
         let offset: u32 = match self.get_dtor_address_value_offset() {
             Some(val) => val,
             None => {
-                println!("Error: tried to get dtor address value offset for table which doesn't have it");
+                log_error!(
+                    "Tried to get dtor address value offset for table which doesn't have it"
+                );
                 return None;
             }
         };
 
-        let address: u32 = mem_utils::read_uint32(&self.bytes, &mut (offset as i32), true);
+        let address: u32 = mem_utils::read_uint32(&self.bytes, &mut (offset as i32), true)?;
         Some((offset, address))
     }
 
+    /// Returns the symbols referenced by this action entry (its dtor, catch type or
+    /// specification types), with their offsets relative to the start of the table.
+    pub(crate) fn reference_offsets(&self) -> Vec<Relocation> {
+        //The action data starts after the type and param bytes
+        let data_offset = self.action_offset + 2;
+        if let Some((offset, address)) = self.get_dtor_relocation() {
+            return vec![Relocation {
+                offset: data_offset + offset,
+                address,
+            }];
+        }
+
+        match self.try_get_exaction_data() {
+            //The catch type follows the unknown halfword
+            Ok(ExActionData::CatchBlock { catch_type, .. })
+            | Ok(ExActionData::CatchBlock32 { catch_type, .. }) => vec![Relocation {
+                offset: data_offset + 2,
+                address: catch_type,
+            }],
+            //The types follow the count, PC and catch info fields
+            Ok(ExActionData::Specification { spec, .. }) => spec
+                .into_iter()
+                .enumerate()
+                .map(|(i, address)| Relocation {
+                    offset: data_offset + 10 + (i as u32) * 4,
+                    address,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     /// Decodes the action data from the byte array depending on the set action type, and converts it
     /// to an ExActionData enum containing the decoded data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the byte array is too short for the action type. Actions returned by
+    /// `decode_extab` always have enough data; use `try_get_exaction_data` for others.
+    #[deprecated(note = "use `try_get_exaction_data`, which returns an error instead of panicking")]
     pub fn get_exaction_data(&self) -> ExActionData {
+        match self.try_get_exaction_data() {
+            Ok(data) => data,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Decodes the action data like `try_get_exaction_data`, and resolves its operands into
+    /// stack or register locations using the action param. Stack locations are relative to
+    /// the given frame base.
+    ///
+    /// Returns an error if the byte array is too short for the action type.
+    pub fn get_operands(&self, base: FrameBase) -> Result<ActionOperands, ExtabDecodeError> {
+        let data = self.try_get_exaction_data()?;
+        Ok(ActionOperands::from_data(data, self.action_param, base))
+    }
+
+    /// Decodes the action data from the byte array depending on the set action type, and converts it
+    /// to an ExActionData enum containing the decoded data.
+    ///
+    /// Returns an error if the byte array is too short for the action type.
+    pub fn try_get_exaction_data(&self) -> Result<ExActionData, ExtabDecodeError> {
+        match ExActionData::from_bytes(self.action_type, &self.bytes) {
+            Some(data) => Ok(data),
+            None => Err(ExtabDecodeError::TruncatedActionPayload(
+                self.action_type,
+                self.action_offset,
+            )),
+        }
+    }
+}
+
+impl ExActionData {
+    /// Decodes the data of an action of the given type from its byte array.
+    ///
+    /// Returns 'None' if the byte array is too short for the action type.
+    pub fn from_bytes(action_type: ExAction, bytes: &[u8]) -> Option<ExActionData> {
         let mut offset: i32 = 0;
 
-        match self.action_type {
+        let data = match action_type {
             ExAction::EndOfList => ExActionData::EndOfList {},
             ExAction::Branch => {
-                let target_offset = mem_utils::read_uint16(&self.bytes, &mut offset, true);
+                let target_offset = mem_utils::read_uint16(bytes, &mut offset, true)?;
                 ExActionData::Branch { target_offset }
             }
             ExAction::DestroyLocal => {
-                let local_offset = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let local_offset = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyLocal {
                     local_offset,
                     dtor_address,
                 }
             }
             ExAction::DestroyLocalCond => {
-                let condition = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let local_offset = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let unk4 = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let condition = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let local_offset = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let unk4 = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyLocalCond {
                     condition,
                     local_offset,
@@ -307,18 +707,18 @@ impl ExceptionAction {
                 }
             }
             ExAction::DestroyLocalPointer => {
-                let local_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let local_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyLocalPointer {
                     local_pointer,
                     dtor_address,
                 }
             }
             ExAction::DestroyLocalArray => {
-                let local_array = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let elements = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let element_size = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let local_array = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let elements = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let element_size = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyLocalArray {
                     local_array,
                     elements,
@@ -327,9 +727,9 @@ impl ExceptionAction {
                 }
             }
             ExAction::DestroyBase => {
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let member_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let member_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyBase {
                     object_pointer,
                     member_offset,
@@ -337,9 +737,9 @@ impl ExceptionAction {
                 }
             }
             ExAction::DestroyMember => {
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let member_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let member_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyMember {
                     object_pointer,
                     member_offset,
@@ -347,11 +747,11 @@ impl ExceptionAction {
                 }
             }
             ExAction::DestroyMemberCond => {
-                let condition = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let member_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let unk8 = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let condition = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let member_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let unk8 = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyMemberCond {
                     condition,
                     object_pointer,
@@ -361,11 +761,11 @@ impl ExceptionAction {
                 }
             }
             ExAction::DestroyMemberArray => {
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let member_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let elements = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let element_size = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let member_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let elements = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let element_size = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DestroyMemberArray {
                     object_pointer,
                     member_offset,
@@ -375,18 +775,18 @@ impl ExceptionAction {
                 }
             }
             ExAction::DeletePointer => {
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DeletePointer {
                     object_pointer,
                     dtor_address,
                 }
             }
             ExAction::DeletePointerCond => {
-                let condition = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let object_pointer = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let unk4 = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let dtor_address = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let condition = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let object_pointer = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let unk4 = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let dtor_address = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::DeletePointerCond {
                     condition,
                     object_pointer,
@@ -395,10 +795,10 @@ impl ExceptionAction {
                 }
             }
             ExAction::CatchBlock => {
-                let unk0 = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let catch_type = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let catch_pc_offset = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let cinfo_ref = mem_utils::read_uint16(&self.bytes, &mut offset, true);
+                let unk0 = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let catch_type = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let catch_pc_offset = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let cinfo_ref = mem_utils::read_uint16(bytes, &mut offset, true)?;
                 ExActionData::CatchBlock {
                     unk0,
                     catch_type,
@@ -407,20 +807,20 @@ impl ExceptionAction {
                 }
             }
             ExAction::ActiveCatchBlock => {
-                let cinfo_ref = mem_utils::read_uint16(&self.bytes, &mut offset, true);
+                let cinfo_ref = mem_utils::read_uint16(bytes, &mut offset, true)?;
                 ExActionData::ActiveCatchBlock { cinfo_ref }
             }
             ExAction::Terminate => ExActionData::Terminate {},
             ExAction::Specification => {
-                let specs = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let pc_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let cinfo_ref = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let specs = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let pc_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let cinfo_ref = mem_utils::read_uint32(bytes, &mut offset, true)?;
 
                 //Read the specified number of 32 bit values and add them to the list
                 let length = specs as i32;
                 let mut spec: Vec<u32> = vec![];
                 for _i in 0..length {
-                    spec.push(mem_utils::read_uint32(&self.bytes, &mut offset, true));
+                    spec.push(mem_utils::read_uint32(bytes, &mut offset, true)?);
                 }
                 ExActionData::Specification {
                     specs,
@@ -430,10 +830,10 @@ impl ExceptionAction {
                 }
             }
             ExAction::CatchBlock32 => {
-                let unk0 = mem_utils::read_uint16(&self.bytes, &mut offset, true);
-                let catch_type = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let catch_pc_offset = mem_utils::read_uint32(&self.bytes, &mut offset, true);
-                let cinfo_ref = mem_utils::read_uint32(&self.bytes, &mut offset, true);
+                let unk0 = mem_utils::read_uint16(bytes, &mut offset, true)?;
+                let catch_type = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let catch_pc_offset = mem_utils::read_uint32(bytes, &mut offset, true)?;
+                let cinfo_ref = mem_utils::read_uint32(bytes, &mut offset, true)?;
                 ExActionData::CatchBlock32 {
                     unk0,
                     catch_type,
@@ -441,7 +841,53 @@ impl ExceptionAction {
                     cinfo_ref,
                 }
             }
-        }
+        };
+
+        Some(data)
+    }
+}
+
+/// Serialized form of `ExceptionAction`, with the decoded action data in place of the
+/// raw bytes. The action type is given by the data's `type` field.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SerializedAction {
+    pub action_offset: u32,
+    pub action_param: u8,
+    pub has_end_bit: bool,
+    pub data: ExActionData,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<&ExceptionAction> for SerializedAction {
+    type Error = ExtabDecodeError;
+
+    fn try_from(action: &ExceptionAction) -> Result<Self, Self::Error> {
+        Ok(Self {
+            action_offset: action.action_offset,
+            action_param: action.action_param,
+            has_end_bit: action.has_end_bit,
+            data: action.try_get_exaction_data()?,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ExceptionAction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedAction::try_from(self)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedAction> for ExceptionAction {
+    fn from(action: SerializedAction) -> Self {
+        let mut exaction =
+            ExceptionAction::from_data(action.data, action.has_end_bit, action.action_param);
+        exaction.action_offset = action.action_offset;
+        exaction
     }
 }
 
@@ -453,6 +899,7 @@ impl Default for ExceptionAction {
 
 /// Struct for pc actions.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PCAction {
     pub start_pc: u32,
     pub end_pc: u32,
@@ -477,13 +924,27 @@ impl Default for PCAction {
 
 /// Struct for exception table relocation (always dtor function address)
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Relocation {
     pub offset: u32,
     pub address: u32,
 }
 
+/// Struct for a dtor reference of an action, resolved against named relocations.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DtorReference {
+    pub action_offset: u32,
+    pub relocation: Relocation,
+    pub name: Option<String>, //None if there is no named relocation at the reference's offset
+}
+
 /// Struct containing all the data from the decoded exception table.
+///
+/// With the `serde` feature, both the raw flag value and the flag fields are serialized.
+/// When encoding a deserialized table, the flag fields take precedence.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExceptionTableData {
     pub flag_val: u16, //0x0-1
     //Flag bits (16 bit value)
@@ -527,11 +988,80 @@ impl ExceptionTableData {
         self.gpr_save_range = ((self.flag_val >> 11) & 0b11111) as u32;
     }
 
+    /// Calculates the header flag value from the flag fields. Bits without a
+    /// corresponding field (0 and 2) are kept from `flag_val`.
+    pub fn calculate_flag_val(&self) -> u16 {
+        let mut flag_val = self.flag_val & 0b101;
+        flag_val |= (self.has_elf_vector as u16) << 1;
+        flag_val |= (self.large_frame as u16) << 3;
+        flag_val |= (self.has_frame_pointer as u16) << 4;
+        flag_val |= (self.saved_cr as u16) << 5;
+        flag_val |= ((self.fpr_save_range & 0b11111) as u16) << 6;
+        flag_val |= ((self.gpr_save_range & 0b11111) as u16) << 11;
+        flag_val
+    }
+
+    /// Returns the register that stack locations in this table's actions are relative to.
+    pub fn frame_base(&self) -> FrameBase {
+        FrameBase::from_has_frame_pointer(self.has_frame_pointer)
+    }
+
+    /// Returns the operands of the given action, with stack locations relative to this
+    /// table's frame base.
+    ///
+    /// Returns an error if the action's byte array is too short for its action type.
+    pub fn action_operands(
+        &self,
+        action: &ExceptionAction,
+    ) -> Result<ActionOperands, ExtabDecodeError> {
+        action.get_operands(self.frame_base())
+    }
+
+    /// Resolves the dtor reference of every action against the given relocation names,
+    /// which are keyed by their offset in the table.
+    pub fn resolve_dtor_references(
+        &self,
+        relocation_names: &HashMap<u32, String>,
+    ) -> Vec<DtorReference> {
+        let mut references: Vec<DtorReference> = vec![];
+
+        for action in &self.exception_actions {
+            if !action.has_dtor_ref() {
+                continue;
+            }
+            for relocation in action.reference_offsets() {
+                references.push(DtorReference {
+                    action_offset: action.action_offset,
+                    name: relocation_names.get(&relocation.offset).cloned(),
+                    relocation,
+                });
+            }
+        }
+
+        references
+    }
+
     /// Converts the table into a string, taking in an array of the function
     /// names required for the table.
     ///
-    /// Returns 'None' if an error occurs.
+    /// The names are assigned to the table's relocations in order. Dtor references
+    /// without a name are shown as unresolved.
+    #[deprecated(note = "names are matched by position, use to_string_with_relocations instead")]
     pub fn to_string(&self, func_names: Vec<String>) -> Option<String> {
+        let relocation_names: HashMap<u32, String> = self
+            .relocations
+            .iter()
+            .map(|reloc| reloc.offset)
+            .zip(func_names)
+            .collect();
+        Some(self.to_string_with_relocations(&relocation_names))
+    }
+
+    /// Converts the table into a string, naming dtor references using the given
+    /// relocation names, which are keyed by their offset in the table.
+    ///
+    /// Dtor references without a name are shown as unresolved, along with their address.
+    pub fn to_string_with_relocations(&self, relocation_names: &HashMap<u32, String>) -> String {
         let mut sb = String::from("");
 
         sb += "Flag values:\n";
@@ -610,8 +1140,8 @@ impl ExceptionTableData {
 
         if num_exactions > 0 {
             sb += "Exception actions:\n";
-            let local_reg_string = if self.has_frame_pointer { "FP" } else { "SP" };
-            let mut func_index: usize = 0;
+            let dtor_references = self.resolve_dtor_references(relocation_names);
+            let mut dtor_references = dtor_references.iter().peekable();
 
             for i in 0..num_exactions {
                 let action = &self.exception_actions[i];
@@ -619,197 +1149,121 @@ impl ExceptionTableData {
                 let action_offset = action.action_offset;
                 let action_name = action.action_type.convert_to_string();
                 line += format!("{action_offset:06X}:\nType: {action_name}\n").as_str();
+                let references = action.reference_offsets();
+                let reference_name = |i: usize| relocation_names.get(&references.get(i)?.offset);
 
-                let has_dtor_ref = action.has_dtor_ref();
-                let exaction_data = action.get_exaction_data();
-
-                match exaction_data {
-                    ExActionData::EndOfList => {}
-                    ExActionData::Branch { target_offset } => {
-                        line += format!("Action: {target_offset:06X}").as_str();
-                    }
-                    ExActionData::DestroyLocal { local_offset, .. } => {
-                        line += format!("Local: {local_offset:#X}({local_reg_string})").as_str();
-                    }
-                    ExActionData::DestroyLocalCond {
-                        condition,
-                        local_offset,
-                        ..
-                    } => {
-                        line += format!("Local: {local_offset:#X}({local_reg_string})").as_str();
-
-                        //The action param is used to determine the type of reference for the condition (0: local offset, 1: register)
-                        if action.action_param == 0 {
-                            //Local offset
-                            line += format!("\nCond: {condition:#X}({local_reg_string})").as_str();
-                        } else {
-                            //Register
-                            //In this case, the local offset param is actually the register number
-                            line += format!("\nCond: r{condition}").as_str();
+                match self.action_operands(action) {
+                    Err(e) => line += e.to_string().as_str(),
+                    Ok(operands) => match operands {
+                        ActionOperands::EndOfList => {}
+                        ActionOperands::Branch { target_offset } => {
+                            line += format!("Action: {target_offset:06X}").as_str();
                         }
-                    }
-                    ExActionData::DestroyLocalPointer { local_pointer, .. } => {
-                        let mode = action.action_param >> 7;
-                        if mode == 0 {
-                            //Local offset
-                            line +=
-                                format!("Pointer: {local_pointer:#X}({local_reg_string})").as_str();
-                        } else {
-                            //Register
-                            line += format!("Pointer: r{local_pointer}").as_str();
+                        ActionOperands::DestroyLocal { local, .. } => {
+                            line += format!("Local: {local}").as_str();
                         }
-                    }
-                    ExActionData::DestroyLocalArray {
-                        local_array,
-                        elements,
-                        element_size,
-                        ..
-                    } => {
-                        line += format!("Array: {local_array:#X}({local_reg_string})\nElements: {elements}\nSize: {element_size}").as_str();
-                    }
-                    ExActionData::DestroyBase {
-                        object_pointer,
-                        member_offset,
-                        ..
-                    } => {
-                        let mode = action.action_param >> 7;
-                        if mode == 0 {
-                            line += format!("Member: {object_pointer:#X}({local_reg_string})+{member_offset:#X}").as_str();
-                        } else {
-                            line +=
-                                format!("Member: {member_offset:#X}(r{object_pointer})").as_str();
+                        ActionOperands::DestroyLocalCond {
+                            condition, local, ..
+                        } => {
+                            line += format!("Local: {local}\nCond: {condition}").as_str();
                         }
-                    }
-                    ExActionData::DestroyMember {
-                        object_pointer,
-                        member_offset,
-                        ..
-                    } => {
-                        let mode = action.action_param >> 7;
-                        if mode == 0 {
-                            line += format!("Member: {object_pointer:#X}({local_reg_string})+{member_offset:#X}").as_str();
-                        } else {
-                            line +=
-                                format!("Member: {member_offset:#X}(r{object_pointer})").as_str();
+                        ActionOperands::DestroyLocalPointer { pointer, .. } => {
+                            line += format!("Pointer: {pointer}").as_str();
                         }
-                    }
-                    ExActionData::DestroyMemberCond {
-                        condition,
-                        object_pointer,
-                        member_offset,
-                        ..
-                    } => {
-                        let mode = (action.action_param >> 6) & 1;
-                        if mode == 0 {
-                            line += format!("Member: {object_pointer:#X}({local_reg_string})+{member_offset:#X}").as_str();
-                        } else {
-                            //Register
-                            line +=
-                                format!("Member: {member_offset:#X}(r{object_pointer})").as_str();
+                        ActionOperands::DestroyLocalArray {
+                            array,
+                            elements,
+                            element_size,
+                            ..
+                        } => {
+                            line += format!(
+                                "Array: {array}\nElements: {elements}\nSize: {element_size}"
+                            )
+                            .as_str();
                         }
-                        let condition_mode = action.action_param >> 7;
-                        if condition_mode == 0 {
-                            //Local offset
-                            line += format!("\nCond: {condition:#X}({local_reg_string})").as_str();
-                        } else {
-                            //Register
-                            line += format!("\nCond: r{condition}").as_str();
+                        ActionOperands::DestroyBase { member, .. }
+                        | ActionOperands::DestroyMember { member, .. } => {
+                            line += format!("Member: {member}").as_str();
                         }
-                    }
-                    ExActionData::DestroyMemberArray {
-                        object_pointer,
-                        member_offset,
-                        elements,
-                        element_size,
-                        ..
-                    } => {
-                        let mode = action.action_param >> 7;
-                        if mode == 0 {
-                            //Local offset
+                        ActionOperands::DestroyMemberCond {
+                            condition, member, ..
+                        } => {
+                            line += format!("Member: {member}\nCond: {condition}").as_str();
+                        }
+                        ActionOperands::DestroyMemberArray {
+                            member,
+                            elements,
+                            element_size,
+                            ..
+                        } => {
                             line += format!(
-                                "Member: {object_pointer:#X}({local_reg_string})+0x{member_offset}"
+                                "Member: {member}\nElements: {elements}\nSize: {element_size}"
                             )
                             .as_str();
-                        } else {
-                            //Register
-                            line +=
-                                format!("Member: {member_offset:#X}(r{object_pointer})").as_str();
                         }
-                        line += format!("\nElements: {elements}\nSize: {element_size}").as_str();
-                    }
-                    ExActionData::DeletePointer { object_pointer, .. } => {
-                        let mode = action.action_param >> 7;
-                        if mode == 0 {
-                            //Local offset
-                            line += format!("Pointer: {object_pointer:#X}({local_reg_string})")
-                                .as_str();
-                        } else {
-                            //Register
-                            line += format!("Pointer: r{object_pointer})").as_str();
+                        ActionOperands::DeletePointer { pointer, .. } => {
+                            line += format!("Pointer: {pointer}").as_str();
                         }
-                    }
-                    ExActionData::DeletePointerCond {
-                        condition,
-                        object_pointer,
-                        ..
-                    } => {
-                        let mode = (action.action_param >> 6) & 1;
-                        if mode == 0 {
-                            //Local offset
-                            line += format!("Pointer: {object_pointer:#X}({local_reg_string})")
-                                .as_str();
-                        } else {
-                            //Register
-                            line += format!("Pointer: r{object_pointer})").as_str();
+                        ActionOperands::DeletePointerCond {
+                            condition, pointer, ..
+                        } => {
+                            line += format!("Pointer: {pointer}\nCond: {condition}").as_str();
                         }
-                        let condition_mode = action.action_param >> 7;
-                        if condition_mode == 0 {
-                            //Local offset
-                            line += format!("\nCond: {condition:#X}({local_reg_string})").as_str();
-                        } else {
-                            //Register
-                            line += format!("\nCond: r{condition}").as_str();
+                        ActionOperands::CatchBlock {
+                            catch_type,
+                            catch_pc_offset,
+                            catch_info,
+                        } => {
+                            line += format!("Local: {catch_info}\nPC: {catch_pc_offset:08X}\ncatch_type_addr: {catch_type:08X}").as_str();
+                            if let Some(name) = reference_name(0) {
+                                line += format!("\nCatch type: \"{name}\"").as_str();
+                            }
                         }
-                    }
-                    ExActionData::CatchBlock {
-                        catch_type,
-                        catch_pc_offset,
-                        cinfo_ref,
-                        ..
-                    } => {
-                        line += format!("Local: {cinfo_ref:#X}({local_reg_string})\nPC: {catch_pc_offset:08X}\ncatch_type_addr: {catch_type:08X}").as_str();
-                    }
-                    ExActionData::ActiveCatchBlock { cinfo_ref } => {
-                        line += format!("Local: {cinfo_ref:#X}({local_reg_string})").as_str();
-                    }
-                    ExActionData::Terminate => {}
-                    ExActionData::Specification {
-                        specs,
-                        pc_offset,
-                        cinfo_ref,
-                        ..
-                    } => {
-                        line += format!("Local: {cinfo_ref:#X}({local_reg_string})\nPC: {pc_offset:08X}\nTypes: {specs}").as_str();
-                    }
-                    ExActionData::CatchBlock32 {
-                        catch_type,
-                        catch_pc_offset,
-                        cinfo_ref,
-                        ..
-                    } => {
-                        line += format!("Local: {cinfo_ref:#X}({local_reg_string})\nPC: {catch_pc_offset:08X}\ncatch_type_addr: {catch_type:08X}").as_str();
-                    }
+                        ActionOperands::ActiveCatchBlock { catch_info } => {
+                            line += format!("Local: {catch_info}").as_str();
+                        }
+                        ActionOperands::Terminate => {}
+                        ActionOperands::Specification {
+                            pc_offset,
+                            catch_info,
+                            spec,
+                        } => {
+                            let specs = spec.len();
+                            line +=
+                                format!("Local: {catch_info}\nPC: {pc_offset:08X}\nTypes: {specs}")
+                                    .as_str();
+                            for (i, spec_type) in spec.iter().enumerate() {
+                                match reference_name(i) {
+                                    Some(name) => line += format!("\nType: \"{name}\"").as_str(),
+                                    None => line += format!("\nType: {spec_type:08X}").as_str(),
+                                }
+                            }
+                        }
+                        ActionOperands::CatchBlock32 {
+                            catch_type,
+                            catch_pc_offset,
+                            catch_info,
+                        } => {
+                            line += format!("Local: {catch_info}\nPC: {catch_pc_offset:08X}\ncatch_type_addr: {catch_type:08X}").as_str();
+                            if let Some(name) = reference_name(0) {
+                                line += format!("\nCatch type: \"{name}\"").as_str();
+                            }
+                        }
+                    },
                 }
 
-                //If the action references a dtor, print it out using the name array
-                if has_dtor_ref {
-                    if func_index >= func_names.len() {
-                        println!("Error: Invalid function array index");
-                        return None;
+                //If the action references a dtor, print out its name
+                if let Some(reference) =
+                    dtor_references.next_if(|reference| reference.action_offset == action_offset)
+                {
+                    match &reference.name {
+                        Some(name) => line += format!("\nDtor: \"{name}\"").as_str(),
+                        None => {
+                            let Relocation { offset, address } = reference.relocation;
+                            line += format!("\nDtor: <unresolved {address:#010X} at {offset:#X}>")
+                                .as_str();
+                        }
                     }
-                    let func_name = func_names[func_index].as_str();
-                    line += format!("\nDtor: \"{func_name}\"").as_str();
-                    func_index += 1;
                 }
 
                 if action.has_end_bit {
@@ -820,166 +1274,52 @@ impl ExceptionTableData {
             }
         }
 
-        Some(sb)
+        sb
     }
 }
 
 struct ExtabDecoder {
     extab_data: ExceptionTableData,
-    offset: i32,
-    data: Vec<u8>,
-    length: i32,
 }
 
 impl ExtabDecoder {
     fn new() -> Self {
         Self {
             extab_data: ExceptionTableData::new(),
-            offset: 0,
-            data: vec![],
-            length: 0,
         }
     }
 
     fn parse_exception_table(&mut self, bytes: &[u8]) -> Result<(), ExtabDecodeError> {
-        self.offset = 0;
-        self.data = Vec::from(bytes);
-        self.length = self.data.len() as i32;
-
-        //If the array is empty, return an error.
-        if self.length < 8 {
-            return Err(ExtabDecodeError::ArrayTooSmall(self.length as u32));
-        }
+        let table = ExceptionTable::parse(bytes)?;
 
         //Parse the header flag value
-        self.extab_data.flag_val = mem_utils::read_uint16(&self.data, &mut self.offset, true);
+        self.extab_data.flag_val = table.flag_val();
         self.extab_data.calculate_flag_values();
-        self.extab_data.et_field = mem_utils::read_uint16(&self.data, &mut self.offset, true);
-
-        //Check whether the table is 8 bytes but the terminator isn't zero. If so,
-        //throw an error.
-        let terminator = mem_utils::read_uint32(&self.data, &mut self.offset, false);
-        if self.length == 8 && terminator != 0 {
-            return Err(ExtabDecodeError::InvalidSmallTableTerminator);
-        }
+        self.extab_data.et_field = table.et_field();
 
-        //Parse range entries until we hit the terminator (32 bit zero value)
-        while mem_utils::read_uint32(&self.data, &mut self.offset, false) != 0 {
-            let mut pcaction = PCAction::new();
-            pcaction.start_pc = mem_utils::read_uint32(&self.data, &mut self.offset, true);
-            let range_size: u32 =
-                (mem_utils::read_uint16(&self.data, &mut self.offset, true) as u32) * 4; //range size is encoded as size >> 2
-            pcaction.end_pc = pcaction.start_pc + range_size;
-            pcaction.action_offset =
-                mem_utils::read_uint16(&self.data, &mut self.offset, true) as u32;
-            self.extab_data.pc_actions.push(pcaction);
-        }
-
-        self.offset += 4; //Skip the terminator
+        self.extab_data.pc_actions = table.pc_actions().collect();
 
         //If there are still bytes remaining, there are action entries to process
-        while self.offset < self.length {
-            //Console.WriteLine("Offset: " + offset);
-            self.parse_action_entry()?;
+        for action in table.actions() {
+            self.parse_action_entry(action?)?;
         }
 
         Ok(())
     }
 
-    fn parse_action_entry(&mut self) -> Result<(), ExtabDecodeError> {
-        let mut exaction = ExceptionAction::new();
-        exaction.action_offset = self.offset as u32;
-        let action_type_byte = mem_utils::read_byte(&self.data, &mut self.offset, true);
-        exaction.has_end_bit = (action_type_byte & 0x80) != 0;
-        let action_type_value: u32 = (action_type_byte & 0x7F) as u32;
-        let result = ExAction::from_int(action_type_value as i32);
-        exaction.action_type = match result {
-            Some(action) => action,
-            None => {
-                return Err(ExtabDecodeError::InvalidActionValue(
-                    action_type_value,
-                    exaction.action_offset,
-                ))
-            }
-        };
-        exaction.action_param = mem_utils::read_byte(&self.data, &mut self.offset, true);
-
-        //Since the way action data is stored is too varied, we just store the remaining data as a byte
-        //array to be used later.
-        let mut size: i32;
-
-        match exaction.action_type {
-            ExAction::EndOfList => {
-                size = 0;
-            }
-            ExAction::Branch => {
-                size = 2;
-            }
-            ExAction::DestroyLocal => {
-                size = 6;
-            }
-            ExAction::DestroyLocalCond => {
-                size = 10;
-            }
-            ExAction::DestroyLocalPointer => {
-                size = 6;
-            }
-            ExAction::DestroyLocalArray => {
-                size = 10;
-            }
-            ExAction::DestroyBase | ExAction::DestroyMember => {
-                size = 10;
-            }
-            ExAction::DestroyMemberCond => {
-                size = 14;
-            }
-            ExAction::DestroyMemberArray => {
-                size = 18;
-            }
-            ExAction::DeletePointer => {
-                size = 6;
-            }
-            ExAction::DeletePointerCond => {
-                size = 10;
-            }
-            ExAction::CatchBlock => {
-                size = 10;
-            }
-            ExAction::ActiveCatchBlock => {
-                size = 2;
-            }
-            ExAction::Terminate => {
-                size = 0;
-            }
-            ExAction::Specification => {
-                size = 10;
-                //Calculate the length of the array, and add it to the base size
-                let length = mem_utils::read_uint16(&self.data, &mut self.offset, false) as i32;
-                size += length * 4;
-            }
-            ExAction::CatchBlock32 => {
-                size = 14;
-            }
-        }
-
-        let start_index = self.offset as usize;
-        let end_index = (self.offset + size) as usize;
-        exaction.bytes = self.data[start_index..end_index].into();
-        self.offset += size;
+    fn parse_action_entry(&mut self, action: ExceptionActionRef) -> Result<(), ExtabDecodeError> {
+        let exaction = action.to_owned_action();
 
         //Check if the action entry has a dtor reference. If so, get the relocation information from it,
         //and add it to the list.
         if exaction.has_dtor_ref() {
-            let (offset, addr) = match exaction.get_dtor_relocation() {
+            let reloc = match exaction.reference_offsets().pop() {
                 Some(val) => val,
                 None => {
                     //If None was returned even though the action should have a reference, return an error
                     return Err(ExtabDecodeError::Internal);
                 }
             };
-
-            let reloc_offset: u32 = (start_index as u32) + offset;
-            let reloc = Relocation { offset: reloc_offset, address: addr };
             self.extab_data.relocations.push(reloc);
         }
 
@@ -996,3 +1336,13 @@ pub fn decode_extab(data: &[u8]) -> Result<ExceptionTableData, ExtabDecodeError>
     decoder.parse_exception_table(data)?;
     Ok(decoder.extab_data)
 }
+
+/// Encodes the provided exception table data back into its binary form.
+///
+/// Decoding a table and encoding the result gives back the original bytes. The actions
+/// are written in order, so each one's `action_offset` has to match where it ends up,
+/// and its bytes have to match the size of its action type.
+pub fn encode_extab(data: &ExceptionTableData) -> Result<Vec<u8>, ExtabEncodeError> {
+    let encoder = encoder::ExtabEncoder::new();
+    encoder.encode_exception_table(data)
+}
@@ -1,26 +1,36 @@
-pub fn read_byte(data: &[u8], offset: &mut i32, update_offset: bool) -> u8 {
-    let index = *offset as usize;
-    let b = data[index];
+fn read_bytes<const N: usize>(
+    data: &[u8],
+    offset: &mut i32,
+    update_offset: bool,
+) -> Option<[u8; N]> {
+    let index = usize::try_from(*offset).ok()?;
+    let bytes = data.get(index..index.checked_add(N)?)?.try_into().ok()?;
     if update_offset {
-        *offset += 1;
+        *offset += N as i32;
     }
-    b
+    Some(bytes)
 }
 
-pub fn read_uint16(data: &[u8], offset: &mut i32, update_offset: bool) -> u16 {
-    let index = *offset as usize;
-    let bytes = data[index..index + 2].try_into().unwrap();
-    if update_offset {
-        *offset += 2;
-    }
-    u16::from_be_bytes(bytes)
+pub fn read_byte(data: &[u8], offset: &mut i32, update_offset: bool) -> Option<u8> {
+    read_bytes::<1>(data, offset, update_offset).map(|bytes| bytes[0])
 }
 
-pub fn read_uint32(data: &[u8], offset: &mut i32, update_offset: bool) -> u32 {
-    let index = *offset as usize;
-    let bytes = data[index..index + 4].try_into().unwrap();
-    if update_offset {
-        *offset += 4;
-    }
-    u32::from_be_bytes(bytes)
+pub fn read_uint16(data: &[u8], offset: &mut i32, update_offset: bool) -> Option<u16> {
+    read_bytes(data, offset, update_offset).map(u16::from_be_bytes)
+}
+
+pub fn read_uint32(data: &[u8], offset: &mut i32, update_offset: bool) -> Option<u32> {
+    read_bytes(data, offset, update_offset).map(u32::from_be_bytes)
+}
+
+pub fn write_byte(data: &mut Vec<u8>, value: u8) {
+    data.push(value);
+}
+
+pub fn write_uint16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn write_uint32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_be_bytes());
 }
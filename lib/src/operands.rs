@@ -0,0 +1,535 @@
+use std::fmt;
+
+use crate::ExActionData;
+
+/// Register used as the base of stack locations. Functions with a frame pointer
+/// address their locals relative to it instead of the stack pointer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FrameBase {
+    FramePointer,
+    StackPointer,
+}
+
+impl FrameBase {
+    pub fn from_has_frame_pointer(has_frame_pointer: bool) -> Self {
+        if has_frame_pointer {
+            FrameBase::FramePointer
+        } else {
+            FrameBase::StackPointer
+        }
+    }
+}
+
+impl fmt::Display for FrameBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBase::FramePointer => write!(f, "FP"),
+            FrameBase::StackPointer => write!(f, "SP"),
+        }
+    }
+}
+
+/// Location of an action operand, either a stack slot or a register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Location {
+    Stack { base: FrameBase, offset: u32 },
+    Register(u32),
+}
+
+impl Location {
+    /// Returns a register location if the given bit of the action param is set,
+    /// and a stack location otherwise.
+    fn from_param_bit(value: u16, action_param: u8, bit: u8, base: FrameBase) -> Self {
+        if (action_param >> bit) & 1 == 0 {
+            Location::Stack {
+                base,
+                offset: value as u32,
+            }
+        } else {
+            Location::Register(value as u32)
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Stack { base, offset } => write!(f, "{offset:#X}({base})"),
+            Location::Register(register) => write!(f, "r{register}"),
+        }
+    }
+}
+
+/// Location of a class member, given by the location of the object and the offset
+/// of the member in it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemberLocation {
+    pub object: Location,
+    pub member_offset: u32,
+}
+
+impl fmt::Display for MemberLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let member_offset = self.member_offset;
+        match self.object {
+            Location::Stack { .. } => write!(f, "{}+{member_offset:#X}", self.object),
+            Location::Register(register) => write!(f, "{member_offset:#X}(r{register})"),
+        }
+    }
+}
+
+/// Enum holding the operands of each action type, with the stack and register
+/// locations decoded from the action param.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionOperands {
+    EndOfList,
+    Branch {
+        target_offset: u16,
+    },
+    DestroyLocal {
+        local: Location,
+        dtor_address: u32,
+    },
+    DestroyLocalCond {
+        condition: Location,
+        local: Location,
+        dtor_address: u32,
+    },
+    DestroyLocalPointer {
+        pointer: Location,
+        dtor_address: u32,
+    },
+    DestroyLocalArray {
+        array: Location,
+        elements: u16,
+        element_size: u16,
+        dtor_address: u32,
+    },
+    DestroyBase {
+        member: MemberLocation,
+        dtor_address: u32,
+    },
+    DestroyMember {
+        member: MemberLocation,
+        dtor_address: u32,
+    },
+    DestroyMemberCond {
+        condition: Location,
+        member: MemberLocation,
+        dtor_address: u32,
+    },
+    DestroyMemberArray {
+        member: MemberLocation,
+        elements: u32,
+        element_size: u32,
+        dtor_address: u32,
+    },
+    DeletePointer {
+        pointer: Location,
+        dtor_address: u32,
+    },
+    DeletePointerCond {
+        condition: Location,
+        pointer: Location,
+        dtor_address: u32,
+    },
+    CatchBlock {
+        catch_type: u32,
+        catch_pc_offset: u16,
+        catch_info: Location,
+    },
+    ActiveCatchBlock {
+        catch_info: Location,
+    },
+    Terminate,
+    Specification {
+        pc_offset: u32,
+        catch_info: Location,
+        spec: Vec<u32>,
+    },
+    CatchBlock32 {
+        catch_type: u32,
+        catch_pc_offset: u32,
+        catch_info: Location,
+    },
+}
+
+impl ActionOperands {
+    /// Decodes the operand locations of the given action data, using the action param
+    /// to choose between stack and register locations.
+    pub fn from_data(data: ExActionData, action_param: u8, base: FrameBase) -> Self {
+        let stack = |offset: u16| Location::Stack {
+            base,
+            offset: offset as u32,
+        };
+        let param_bit =
+            |value: u16, bit: u8| Location::from_param_bit(value, action_param, bit, base);
+
+        match data {
+            ExActionData::EndOfList => ActionOperands::EndOfList,
+            ExActionData::Branch { target_offset } => ActionOperands::Branch { target_offset },
+            ExActionData::DestroyLocal {
+                local_offset,
+                dtor_address,
+            } => ActionOperands::DestroyLocal {
+                local: stack(local_offset),
+                dtor_address,
+            },
+            ExActionData::DestroyLocalCond {
+                condition,
+                local_offset,
+                dtor_address,
+                ..
+            } => {
+                //The action param is used to determine the type of reference for the condition (0: local offset, otherwise register)
+                let condition = if action_param == 0 {
+                    stack(condition)
+                } else {
+                    Location::Register(condition as u32)
+                };
+                ActionOperands::DestroyLocalCond {
+                    condition,
+                    local: stack(local_offset),
+                    dtor_address,
+                }
+            }
+            ExActionData::DestroyLocalPointer {
+                local_pointer,
+                dtor_address,
+            } => ActionOperands::DestroyLocalPointer {
+                pointer: param_bit(local_pointer, 7),
+                dtor_address,
+            },
+            ExActionData::DestroyLocalArray {
+                local_array,
+                elements,
+                element_size,
+                dtor_address,
+            } => ActionOperands::DestroyLocalArray {
+                array: stack(local_array),
+                elements,
+                element_size,
+                dtor_address,
+            },
+            ExActionData::DestroyBase {
+                object_pointer,
+                member_offset,
+                dtor_address,
+            } => ActionOperands::DestroyBase {
+                member: MemberLocation {
+                    object: param_bit(object_pointer, 7),
+                    member_offset,
+                },
+                dtor_address,
+            },
+            ExActionData::DestroyMember {
+                object_pointer,
+                member_offset,
+                dtor_address,
+            } => ActionOperands::DestroyMember {
+                member: MemberLocation {
+                    object: param_bit(object_pointer, 7),
+                    member_offset,
+                },
+                dtor_address,
+            },
+            ExActionData::DestroyMemberCond {
+                condition,
+                object_pointer,
+                member_offset,
+                dtor_address,
+                ..
+            } => ActionOperands::DestroyMemberCond {
+                condition: param_bit(condition, 7),
+                member: MemberLocation {
+                    object: param_bit(object_pointer, 6),
+                    member_offset,
+                },
+                dtor_address,
+            },
+            ExActionData::DestroyMemberArray {
+                object_pointer,
+                member_offset,
+                elements,
+                element_size,
+                dtor_address,
+            } => ActionOperands::DestroyMemberArray {
+                member: MemberLocation {
+                    object: param_bit(object_pointer, 7),
+                    member_offset,
+                },
+                elements,
+                element_size,
+                dtor_address,
+            },
+            ExActionData::DeletePointer {
+                object_pointer,
+                dtor_address,
+            } => ActionOperands::DeletePointer {
+                pointer: param_bit(object_pointer, 7),
+                dtor_address,
+            },
+            ExActionData::DeletePointerCond {
+                condition,
+                object_pointer,
+                dtor_address,
+                ..
+            } => ActionOperands::DeletePointerCond {
+                condition: param_bit(condition, 7),
+                pointer: param_bit(object_pointer, 6),
+                dtor_address,
+            },
+            ExActionData::CatchBlock {
+                catch_type,
+                catch_pc_offset,
+                cinfo_ref,
+                ..
+            } => ActionOperands::CatchBlock {
+                catch_type,
+                catch_pc_offset,
+                catch_info: stack(cinfo_ref),
+            },
+            ExActionData::ActiveCatchBlock { cinfo_ref } => ActionOperands::ActiveCatchBlock {
+                catch_info: stack(cinfo_ref),
+            },
+            ExActionData::Terminate => ActionOperands::Terminate,
+            ExActionData::Specification {
+                pc_offset,
+                cinfo_ref,
+                spec,
+                ..
+            } => ActionOperands::Specification {
+                pc_offset,
+                catch_info: Location::Stack {
+                    base,
+                    offset: cinfo_ref,
+                },
+                spec,
+            },
+            ExActionData::CatchBlock32 {
+                catch_type,
+                catch_pc_offset,
+                cinfo_ref,
+                ..
+            } => ActionOperands::CatchBlock32 {
+                catch_type,
+                catch_pc_offset,
+                catch_info: Location::Stack {
+                    base,
+                    offset: cinfo_ref,
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionOperands, FrameBase, Location, MemberLocation};
+    use crate::ExActionData;
+
+    const DTOR: u32 = 0x80001234;
+
+    fn stack(base: FrameBase, offset: u32) -> Location {
+        Location::Stack { base, offset }
+    }
+
+    #[test]
+    fn stack_locations() {
+        let data = ExActionData::DestroyLocal {
+            local_offset: 0x8,
+            dtor_address: DTOR,
+        };
+        //Locals are always on the stack, relative to the frame base
+        for (param, base, text) in [
+            (0x00, FrameBase::StackPointer, "0x8(SP)"),
+            (0x80, FrameBase::FramePointer, "0x8(FP)"),
+        ] {
+            assert_eq!(
+                ActionOperands::from_data(data.clone(), param, base),
+                ActionOperands::DestroyLocal {
+                    local: stack(base, 0x8),
+                    dtor_address: DTOR,
+                }
+            );
+            assert_eq!(stack(base, 0x8).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn register_locations() {
+        let data = ExActionData::DestroyLocalPointer {
+            local_pointer: 31,
+            dtor_address: DTOR,
+        };
+        let operands = ActionOperands::from_data(data.clone(), 0x80, FrameBase::StackPointer);
+        assert_eq!(
+            operands,
+            ActionOperands::DestroyLocalPointer {
+                pointer: Location::Register(31),
+                dtor_address: DTOR,
+            }
+        );
+        assert_eq!(Location::Register(31).to_string(), "r31");
+
+        //Without bit 7, the pointer is stored on the stack
+        let operands = ActionOperands::from_data(data, 0x00, FrameBase::FramePointer);
+        assert_eq!(
+            operands,
+            ActionOperands::DestroyLocalPointer {
+                pointer: stack(FrameBase::FramePointer, 31),
+                dtor_address: DTOR,
+            }
+        );
+    }
+
+    #[test]
+    fn stack_member_locations() {
+        let data = ExActionData::DestroyMember {
+            object_pointer: 0x10,
+            member_offset: 0x4,
+            dtor_address: DTOR,
+        };
+        let member = MemberLocation {
+            object: stack(FrameBase::StackPointer, 0x10),
+            member_offset: 0x4,
+        };
+        assert_eq!(
+            ActionOperands::from_data(data, 0x00, FrameBase::StackPointer),
+            ActionOperands::DestroyMember {
+                member,
+                dtor_address: DTOR,
+            }
+        );
+        assert_eq!(member.to_string(), "0x10(SP)+0x4");
+    }
+
+    #[test]
+    fn register_member_locations() {
+        let data = ExActionData::DestroyBase {
+            object_pointer: 30,
+            member_offset: 0x8,
+            dtor_address: DTOR,
+        };
+        let member = MemberLocation {
+            object: Location::Register(30),
+            member_offset: 0x8,
+        };
+        assert_eq!(
+            ActionOperands::from_data(data, 0x80, FrameBase::StackPointer),
+            ActionOperands::DestroyBase {
+                member,
+                dtor_address: DTOR,
+            }
+        );
+        assert_eq!(member.to_string(), "0x8(r30)");
+    }
+
+    #[test]
+    fn conditional_locations() {
+        //Bit 7 gives the location of the condition, and bit 6 the location of the object
+        let data = ExActionData::DestroyMemberCond {
+            condition: 0x20,
+            object_pointer: 29,
+            member_offset: 0xC,
+            unk8: 0,
+            dtor_address: DTOR,
+        };
+        assert_eq!(
+            ActionOperands::from_data(data.clone(), 0x40, FrameBase::StackPointer),
+            ActionOperands::DestroyMemberCond {
+                condition: stack(FrameBase::StackPointer, 0x20),
+                member: MemberLocation {
+                    object: Location::Register(29),
+                    member_offset: 0xC,
+                },
+                dtor_address: DTOR,
+            }
+        );
+        assert_eq!(
+            ActionOperands::from_data(data, 0x80, FrameBase::StackPointer),
+            ActionOperands::DestroyMemberCond {
+                condition: Location::Register(0x20),
+                member: MemberLocation {
+                    object: stack(FrameBase::StackPointer, 29),
+                    member_offset: 0xC,
+                },
+                dtor_address: DTOR,
+            }
+        );
+
+        let data = ExActionData::DeletePointerCond {
+            condition: 28,
+            object_pointer: 27,
+            unk4: 0,
+            dtor_address: DTOR,
+        };
+        assert_eq!(
+            ActionOperands::from_data(data, 0xC0, FrameBase::FramePointer),
+            ActionOperands::DeletePointerCond {
+                condition: Location::Register(28),
+                pointer: Location::Register(27),
+                dtor_address: DTOR,
+            }
+        );
+    }
+
+    #[test]
+    fn local_condition_locations() {
+        //The condition of a local is in a register for any nonzero param
+        let data = ExActionData::DestroyLocalCond {
+            condition: 0x18,
+            local_offset: 0x8,
+            unk4: 0,
+            dtor_address: DTOR,
+        };
+        for (param, condition) in [
+            (0x00, stack(FrameBase::StackPointer, 0x18)),
+            (0x01, Location::Register(0x18)),
+            (0x80, Location::Register(0x18)),
+        ] {
+            assert_eq!(
+                ActionOperands::from_data(data.clone(), param, FrameBase::StackPointer),
+                ActionOperands::DestroyLocalCond {
+                    condition,
+                    local: stack(FrameBase::StackPointer, 0x8),
+                    dtor_address: DTOR,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn catch_info_locations() {
+        //Catch info is always on the stack, whatever the param is
+        let data = ExActionData::CatchBlock {
+            unk0: 0,
+            catch_type: 0x80005678,
+            catch_pc_offset: 0x40,
+            cinfo_ref: 0x18,
+        };
+        assert_eq!(
+            ActionOperands::from_data(data, 0x80, FrameBase::FramePointer),
+            ActionOperands::CatchBlock {
+                catch_type: 0x80005678,
+                catch_pc_offset: 0x40,
+                catch_info: stack(FrameBase::FramePointer, 0x18),
+            }
+        );
+
+        let data = ExActionData::Specification {
+            specs: 1,
+            pc_offset: 0x50,
+            cinfo_ref: 0x20,
+            spec: vec![0x80005678],
+        };
+        assert_eq!(
+            ActionOperands::from_data(data, 0x00, FrameBase::StackPointer),
+            ActionOperands::Specification {
+                pc_offset: 0x50,
+                catch_info: stack(FrameBase::StackPointer, 0x20),
+                spec: vec![0x80005678],
+            }
+        );
+    }
+}
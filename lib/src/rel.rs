@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::section::split_tables;
+use crate::{
+    decode_extab, mem_utils, parse_extabindex, ExceptionTableData, ExtabDecodeError,
+    ExtabIndexError, EXTABINDEX_ENTRY_SIZE,
+};
+
+/// Size of the version 1 header. Later versions add alignment values after it.
+const REL_HEADER_SIZE: u32 = 0x40;
+const SECTION_INFO_SIZE: u32 = 8;
+const IMPORT_SIZE: u32 = 8;
+const RELOCATION_SIZE: u32 = 8;
+
+const R_PPC_NONE: u8 = 0;
+const R_DOLPHIN_NOP: u8 = 201;
+const R_DOLPHIN_SECTION: u8 = 202;
+const R_DOLPHIN_END: u8 = 203;
+
+#[derive(Error, Debug)]
+pub enum RelExtabError {
+    #[error("REL file should at least be 64 bytes long. Given file is {0} bytes long.")]
+    FileTooSmall(u32),
+    #[error("Unsupported REL version {0}")]
+    UnsupportedVersion(u32),
+    #[error("REL section table at offset 0x{0:X} is outside of the file")]
+    InvalidSectionTable(u32),
+    #[error("REL section {0} is outside of the file")]
+    InvalidSection(u32),
+    #[error("REL import table at offset 0x{0:X} is outside of the file")]
+    InvalidImportTable(u32),
+    #[error("Relocations for module {0} run past the end of the file")]
+    TruncatedRelocations(u32),
+    #[error("REL section {0} is not a section with data")]
+    MissingSection(u32),
+    #[error("Invalid extabindex section: {0}")]
+    Index(#[from] ExtabIndexError),
+    #[error("extabindex entry at offset 0x{0:X} has no relocation for its function")]
+    InvalidFunctionAddress(u32),
+    #[error("extabindex entry at offset 0x{0:X} does not point into the extab section")]
+    InvalidTablePointer(u32),
+    #[error("Failed to decode the exception table of the function at {0}: {1}")]
+    Decode(RelTarget, ExtabDecodeError),
+}
+
+/// Struct for the header of a REL module.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelHeader {
+    pub id: u32,
+    pub section_count: u32,
+    pub section_info_offset: u32,
+    pub name_offset: u32,
+    pub name_size: u32,
+    pub version: u32,
+    pub bss_size: u32,
+    pub relocation_offset: u32,
+    pub import_offset: u32,
+    pub import_size: u32,
+    pub prolog_section: u8,
+    pub epilog_section: u8,
+    pub unresolved_section: u8,
+    pub bss_section: u8,
+    pub prolog: u32,
+    pub epilog: u32,
+    pub unresolved: u32,
+}
+
+/// Struct for an entry of the section table of a REL module.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelSection {
+    pub index: u32,
+    pub file_offset: u32, //Zero for bss sections and unused entries
+    pub size: u32,
+    pub executable: bool,
+}
+
+impl RelSection {
+    /// Returns whether the section has data in the file.
+    pub fn has_data(&self) -> bool {
+        self.file_offset != 0 && self.size != 0
+    }
+}
+
+/// Struct for a relocation of a REL module, with the position it applies to resolved
+/// from the relocation list.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelRelocation {
+    pub section: u32, //Section the relocation applies to
+    pub offset: u32,  //Offset in that section
+    pub kind: u8,     //R_PPC relocation type
+    pub target: RelTarget,
+}
+
+/// Target of a REL relocation. For module 0 (the main executable), the section is 0
+/// and the offset is the absolute address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelTarget {
+    pub module_id: u32,
+    pub section: u32,
+    pub offset: u32,
+}
+
+impl fmt::Display for RelTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.module_id == 0 {
+            write!(f, "0x{:08X}", self.offset)
+        } else {
+            write!(
+                f,
+                "module {} section {} + 0x{:X}",
+                self.module_id, self.section, self.offset
+            )
+        }
+    }
+}
+
+/// Struct for a REL module, with its sections and relocations.
+#[derive(Debug, Clone)]
+pub struct Rel<'a> {
+    data: &'a [u8],
+    pub header: RelHeader,
+    pub sections: Vec<RelSection>,
+    pub relocations: Vec<RelRelocation>,
+}
+
+fn in_file(data: &[u8], offset: u32, size: u32) -> bool {
+    offset
+        .checked_add(size)
+        .map_or(false, |end| end as usize <= data.len())
+}
+
+impl<'a> Rel<'a> {
+    /// Parses the header, section table and relocation lists of a REL module.
+    pub fn parse(data: &'a [u8]) -> Result<Self, RelExtabError> {
+        if (data.len() as u32) < REL_HEADER_SIZE {
+            return Err(RelExtabError::FileTooSmall(data.len() as u32));
+        }
+        //The header is checked to be large enough, so these reads can't fail
+        let read =
+            |offset: u32| mem_utils::read_uint32(data, &mut (offset as i32), false).unwrap_or(0);
+        let header = RelHeader {
+            id: read(0x00),
+            section_count: read(0x0C),
+            section_info_offset: read(0x10),
+            name_offset: read(0x14),
+            name_size: read(0x18),
+            version: read(0x1C),
+            bss_size: read(0x20),
+            relocation_offset: read(0x24),
+            import_offset: read(0x28),
+            import_size: read(0x2C),
+            prolog_section: data[0x30],
+            epilog_section: data[0x31],
+            unresolved_section: data[0x32],
+            bss_section: data[0x33],
+            prolog: read(0x34),
+            epilog: read(0x38),
+            unresolved: read(0x3C),
+        };
+        if !(1..=3).contains(&header.version) {
+            return Err(RelExtabError::UnsupportedVersion(header.version));
+        }
+
+        //Parse the section table
+        let table_size = header.section_count.checked_mul(SECTION_INFO_SIZE);
+        if !table_size.map_or(false, |size| {
+            in_file(data, header.section_info_offset, size)
+        }) {
+            return Err(RelExtabError::InvalidSectionTable(
+                header.section_info_offset,
+            ));
+        }
+        let mut sections: Vec<RelSection> = vec![];
+        for index in 0..header.section_count {
+            let info_offset = header.section_info_offset + index * SECTION_INFO_SIZE;
+            let offset_and_flags = read(info_offset);
+            let section = RelSection {
+                index,
+                file_offset: offset_and_flags & !1,
+                size: read(info_offset + 4),
+                executable: offset_and_flags & 1 != 0,
+            };
+            if section.has_data() && !in_file(data, section.file_offset, section.size) {
+                return Err(RelExtabError::InvalidSection(index));
+            }
+            sections.push(section);
+        }
+
+        //Parse the relocation list of every imported module
+        if header.import_size % IMPORT_SIZE != 0
+            || !in_file(data, header.import_offset, header.import_size)
+        {
+            return Err(RelExtabError::InvalidImportTable(header.import_offset));
+        }
+        let mut relocations: Vec<RelRelocation> = vec![];
+        for import_offset in (0..header.import_size).step_by(IMPORT_SIZE as usize) {
+            let module_id = read(header.import_offset + import_offset);
+            let list_offset = read(header.import_offset + import_offset + 4);
+            parse_relocations(data, module_id, list_offset, &mut relocations)?;
+        }
+
+        Ok(Self {
+            data,
+            header,
+            sections,
+            relocations,
+        })
+    }
+
+    /// Returns the data of the given section, if it has any in the file.
+    pub fn section_data(&self, index: u32) -> Option<&'a [u8]> {
+        let section = self.sections.get(index as usize)?;
+        if !section.has_data() {
+            return None;
+        }
+        let start = section.file_offset as usize;
+        Some(&self.data[start..start + section.size as usize])
+    }
+
+    /// Returns the relocations applying to the given section, keyed by their offset
+    /// in the section.
+    pub fn section_relocations(&self, index: u32) -> HashMap<u32, RelTarget> {
+        self.relocations
+            .iter()
+            .filter(|relocation| relocation.section == index)
+            .map(|relocation| (relocation.offset, relocation.target))
+            .collect()
+    }
+}
+
+/// Parses the relocation list at the given offset, which applies relocations against
+/// the given module, until the `R_DOLPHIN_END` entry.
+fn parse_relocations(
+    data: &[u8],
+    module_id: u32,
+    list_offset: u32,
+    relocations: &mut Vec<RelRelocation>,
+) -> Result<(), RelExtabError> {
+    let mut section: u32 = 0;
+    let mut offset: u32 = 0;
+
+    let mut entry_offset = list_offset;
+    loop {
+        if !in_file(data, entry_offset, RELOCATION_SIZE) {
+            return Err(RelExtabError::TruncatedRelocations(module_id));
+        }
+        //The entry is checked to be in the file, so these reads can't fail
+        let mut read_offset = entry_offset as i32;
+        let delta = mem_utils::read_uint16(data, &mut read_offset, true).unwrap_or(0) as u32;
+        let kind = mem_utils::read_byte(data, &mut read_offset, true).unwrap_or(0);
+        let target_section = mem_utils::read_byte(data, &mut read_offset, true).unwrap_or(0) as u32;
+        let addend = mem_utils::read_uint32(data, &mut read_offset, true).unwrap_or(0);
+        entry_offset += RELOCATION_SIZE;
+
+        //Each entry's offset is relative to the previous entry
+        offset = offset.wrapping_add(delta);
+        match kind {
+            R_DOLPHIN_END => break,
+            R_DOLPHIN_SECTION => {
+                section = target_section;
+                offset = 0;
+            }
+            R_DOLPHIN_NOP | R_PPC_NONE => {}
+            _ => relocations.push(RelRelocation {
+                section,
+                offset,
+                kind,
+                target: RelTarget {
+                    module_id,
+                    section: target_section,
+                    offset: addend,
+                },
+            }),
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the extab and extabindex sections of the module, returning their indices.
+///
+/// REL sections have no names, so the extabindex section is found as the data section
+/// whose every entry has relocations to a function in an executable section and to a
+/// table in a single other data section, which is the extab section.
+pub fn find_rel_extab_sections(rel: &Rel) -> Option<(u32, u32)> {
+    for section in &rel.sections {
+        if section.executable || !section.has_data() || section.size % EXTABINDEX_ENTRY_SIZE != 0 {
+            continue;
+        }
+
+        let relocations = rel.section_relocations(section.index);
+        let is_own = |target: &RelTarget| target.module_id == rel.header.id;
+        let mut extab_index: Option<u32> = None;
+        let is_index = (0..section.size)
+            .step_by(EXTABINDEX_ENTRY_SIZE as usize)
+            .all(|entry_offset| {
+                let function = relocations.get(&entry_offset).filter(|t| is_own(t));
+                let table = relocations.get(&(entry_offset + 8)).filter(|t| is_own(t));
+                let (function, table) = match (function, table) {
+                    (Some(function), Some(table)) => (function, table),
+                    _ => return false,
+                };
+                let function_in_code = matches!(
+                    rel.sections.get(function.section as usize),
+                    Some(section) if section.executable
+                );
+                let table_in_data = matches!(
+                    rel.sections.get(table.section as usize),
+                    Some(section) if !section.executable && section.has_data()
+                );
+                let same_extab = *extab_index.get_or_insert(table.section) == table.section;
+                function_in_code && table_in_data && same_extab && table.section != section.index
+            });
+        if let (true, Some(extab_index)) = (is_index, extab_index) {
+            return Some((extab_index, section.index));
+        }
+    }
+
+    None
+}
+
+/// Struct containing the decoded exception table of a function in a REL module.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelFunctionExtab {
+    pub function: RelTarget,
+    pub function_size: u32,
+    pub extab_offset: u32, //Offset of the table in the extab section
+    pub data: ExceptionTableData,
+    //Targets of the relocations in the table (destructors and catch types), keyed by
+    //their offset in the table.
+    pub relocation_targets: HashMap<u32, RelTarget>,
+}
+
+/// Decodes the exception tables of every function in the given REL module, in the
+/// order of their extabindex entries.
+///
+/// The extab and extabindex sections can be given by their indices. Otherwise they
+/// are found with `find_rel_extab_sections`, and modules without them give an empty
+/// list.
+pub fn decode_rel(
+    data: &[u8],
+    extab_sections: Option<(u32, u32)>,
+) -> Result<Vec<RelFunctionExtab>, RelExtabError> {
+    let rel = Rel::parse(data)?;
+    let (extab_index, extabindex_index) = match extab_sections {
+        Some(sections) => sections,
+        None => match find_rel_extab_sections(&rel) {
+            Some(sections) => sections,
+            None => return Ok(vec![]),
+        },
+    };
+    let extab = rel
+        .section_data(extab_index)
+        .ok_or(RelExtabError::MissingSection(extab_index))?;
+    let index = rel
+        .section_data(extabindex_index)
+        .ok_or(RelExtabError::MissingSection(extabindex_index))?;
+    let index_entries = parse_extabindex(index)?;
+
+    let index_relocations = rel.section_relocations(extabindex_index);
+    let extab_relocations = rel.section_relocations(extab_index);
+
+    //Resolve the index entries. The function and table addresses are relocations, and
+    //only the function size is stored directly.
+    let mut entries: Vec<(RelTarget, u32, u32)> = vec![];
+    for (i, entry) in index_entries.iter().enumerate() {
+        let entry_offset = (i as u32) * EXTABINDEX_ENTRY_SIZE;
+        let function = *index_relocations
+            .get(&entry_offset)
+            .ok_or(RelExtabError::InvalidFunctionAddress(entry_offset))?;
+        let function_size = entry.function_size;
+        let extab_offset = index_relocations
+            .get(&(entry_offset + 8))
+            .filter(|target| target.module_id == rel.header.id && target.section == extab_index)
+            .map(|target| target.offset)
+            .filter(|offset| (*offset as usize) < extab.len())
+            .ok_or(RelExtabError::InvalidTablePointer(entry_offset))?;
+        entries.push((function, function_size, extab_offset));
+    }
+
+    let table_bytes = split_tables(extab, entries.iter().map(|entry| entry.2));
+
+    let mut tables: Vec<RelFunctionExtab> = vec![];
+    for (function, function_size, extab_offset) in entries {
+        let bytes = table_bytes[&extab_offset];
+        let end_offset = extab_offset + bytes.len() as u32;
+        let data = decode_extab(bytes).map_err(|e| RelExtabError::Decode(function, e))?;
+        let relocation_targets: HashMap<u32, RelTarget> = extab_relocations
+            .iter()
+            .filter(|(offset, _)| (extab_offset..end_offset).contains(offset))
+            .map(|(offset, target)| (offset - extab_offset, *target))
+            .collect();
+
+        tables.push(RelFunctionExtab {
+            function,
+            function_size,
+            extab_offset,
+            data,
+            relocation_targets,
+        });
+    }
+
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_rel, find_rel_extab_sections, Rel, RelExtabError, RelTarget, R_DOLPHIN_END,
+        R_DOLPHIN_SECTION,
+    };
+    use crate::fixtures::{DESTROY_LOCAL_TABLE, EMPTY_TABLE};
+    use crate::{mem_utils, ExtabIndexError};
+
+    const MODULE_ID: u32 = 5;
+    const R_PPC_ADDR32: u8 = 1;
+
+    /// Returns the extab section: a table without PC ranges, followed by a table
+    /// destroying a local whose destructor is in the main executable.
+    fn extab_section() -> Vec<u8> {
+        [&EMPTY_TABLE[..], &DESTROY_LOCAL_TABLE].concat()
+    }
+
+    fn write_relocation(data: &mut Vec<u8>, delta: u16, kind: u8, section: u8, addend: u32) {
+        mem_utils::write_uint16(data, delta);
+        mem_utils::write_byte(data, kind);
+        mem_utils::write_byte(data, section);
+        mem_utils::write_uint32(data, addend);
+    }
+
+    /// Builds a module with a text section for two functions, followed by the extab
+    /// and extabindex sections, the import table and the relocation lists.
+    fn build_rel() -> Vec<u8> {
+        let mut data: Vec<u8> = vec![];
+        let header = [
+            MODULE_ID, 0, 0, 4, 0x40, 0, 0, 1, 0, 0xC8, 0xB8, 0x10, 0, 0, 0, 0,
+        ];
+        for value in header {
+            mem_utils::write_uint32(&mut data, value);
+        }
+        //Section table: null section, text, extab and extabindex
+        for (offset, size) in [(0, 0), (0x61, 0x20), (0x80, 0x20), (0xA0, 0x18)] {
+            mem_utils::write_uint32(&mut data, offset);
+            mem_utils::write_uint32(&mut data, size);
+        }
+        data.extend_from_slice(&[0x60, 0x00, 0x00, 0x00].repeat(8));
+        data.extend_from_slice(&extab_section());
+        //extabindex, with the function and table addresses left to the relocations
+        for _ in 0..2 {
+            for value in [0, 0x10, 0] {
+                mem_utils::write_uint32(&mut data, value);
+            }
+        }
+        //Import table
+        for value in [MODULE_ID, 0xC8, 0, 0xF8] {
+            mem_utils::write_uint32(&mut data, value);
+        }
+        //Relocations against the module itself
+        write_relocation(&mut data, 0, R_DOLPHIN_SECTION, 3, 0);
+        write_relocation(&mut data, 0, R_PPC_ADDR32, 1, 0);
+        write_relocation(&mut data, 8, R_PPC_ADDR32, 2, 0);
+        write_relocation(&mut data, 4, R_PPC_ADDR32, 1, 0x10);
+        write_relocation(&mut data, 8, R_PPC_ADDR32, 2, 8);
+        write_relocation(&mut data, 0, R_DOLPHIN_END, 0, 0);
+        //Relocations against the main executable
+        write_relocation(&mut data, 0, R_DOLPHIN_SECTION, 2, 0);
+        write_relocation(&mut data, 0x1C, R_PPC_ADDR32, 0, 0x80123456);
+        write_relocation(&mut data, 0, R_DOLPHIN_END, 0, 0);
+        data
+    }
+
+    #[test]
+    fn parse_module() {
+        let data = build_rel();
+        let rel = Rel::parse(&data).unwrap();
+        assert_eq!(rel.header.id, MODULE_ID);
+        assert_eq!(rel.sections.len(), 4);
+        assert!(rel.sections[1].executable);
+        assert_eq!(rel.sections[1].file_offset, 0x60);
+        assert!(!rel.sections[0].has_data());
+        assert_eq!(rel.section_data(2), Some(&extab_section()[..]));
+
+        assert_eq!(rel.relocations.len(), 5);
+        let relocations = rel.section_relocations(3);
+        assert_eq!(
+            relocations[&0x14],
+            RelTarget {
+                module_id: MODULE_ID,
+                section: 2,
+                offset: 8
+            }
+        );
+        assert_eq!(rel.section_relocations(2)[&0x1C].offset, 0x80123456);
+    }
+
+    #[test]
+    fn invalid_modules() {
+        assert!(matches!(
+            Rel::parse(&[0; 0x20]),
+            Err(RelExtabError::FileTooSmall(0x20))
+        ));
+
+        let mut data = build_rel();
+        data[0x1F] = 4;
+        assert!(matches!(
+            Rel::parse(&data),
+            Err(RelExtabError::UnsupportedVersion(4))
+        ));
+
+        //Drop the end of the list of relocations against the main executable
+        let mut data = build_rel();
+        data.truncate(data.len() - 8);
+        assert!(matches!(
+            Rel::parse(&data),
+            Err(RelExtabError::TruncatedRelocations(0))
+        ));
+    }
+
+    #[test]
+    fn find_sections() {
+        let data = build_rel();
+        let rel = Rel::parse(&data).unwrap();
+        assert_eq!(find_rel_extab_sections(&rel), Some((2, 3)));
+    }
+
+    #[test]
+    fn decode_tables() {
+        let data = build_rel();
+        for extab_sections in [None, Some((2, 3))] {
+            let tables = decode_rel(&data, extab_sections).unwrap();
+            let functions: Vec<(u32, u32, u32)> = tables
+                .iter()
+                .map(|table| {
+                    (
+                        table.function.offset,
+                        table.function_size,
+                        table.extab_offset,
+                    )
+                })
+                .collect();
+            assert_eq!(functions, [(0, 0x10, 0), (0x10, 0x10, 8)]);
+
+            assert!(tables[0].relocation_targets.is_empty());
+            let table = &tables[1];
+            assert_eq!(table.data.exception_actions.len(), 1);
+            //The destructor is relocated relative to the start of the table
+            assert_eq!(
+                table.relocation_targets[&0x14],
+                RelTarget {
+                    module_id: 0,
+                    section: 0,
+                    offset: 0x80123456
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_index_size() {
+        let mut data = build_rel();
+        //Cut the last entry of the extabindex section short
+        data[0x5C..0x60].copy_from_slice(&0x14u32.to_be_bytes());
+        assert!(matches!(
+            decode_rel(&data, Some((2, 3))),
+            Err(RelExtabError::Index(ExtabIndexError::InvalidIndexSize(
+                0x14
+            )))
+        ));
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::{ActionChainError, ActionOperands, ExAction, ExceptionTableData, Location, Relocation};
+
+/// Struct for a scope of a function, opened by an action which the runtime runs when
+/// unwinding through it: a constructed object, a try block, a catch handler, a throw
+/// specification or a region which terminates on exceptions.
+#[derive(Debug, Clone)]
+pub struct ScopeNode {
+    pub action_offset: u32,
+    pub action_type: ExAction,
+    pub operands: ActionOperands,
+    //Symbols referenced by the action (its dtor, catch type or specification types)
+    pub references: Vec<Relocation>,
+    //PC ranges whose innermost scope is this one
+    pub pc_ranges: Vec<(u32, u32)>,
+    //Scopes opened inside this one, in PC order
+    pub children: Vec<ScopeNode>,
+}
+
+impl ScopeNode {
+    /// Returns the PC range covered by the scope and the scopes inside it.
+    pub fn pc_extent(&self) -> (u32, u32) {
+        let mut extent = (u32::MAX, 0);
+        for (start, end) in &self.pc_ranges {
+            extent = (extent.0.min(*start), extent.1.max(*end));
+        }
+        for child in &self.children {
+            let (start, end) = child.pc_extent();
+            extent = (extent.0.min(start), extent.1.max(end));
+        }
+        extent
+    }
+
+    fn sort(&mut self) {
+        self.pc_ranges.sort_unstable();
+        for child in &mut self.children {
+            child.sort();
+        }
+        self.children.sort_by_key(|child| child.pc_extent().0);
+    }
+}
+
+/// Struct for the scopes of a function, nested as the action chains of its PC ranges.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeTree {
+    //PC ranges without any actions to run
+    pub pc_ranges: Vec<(u32, u32)>,
+    //Outermost scopes of the function, in PC order
+    pub scopes: Vec<ScopeNode>,
+}
+
+/// Returns the class name from a CodeWarrior mangled dtor or type info name, such as
+/// `__dt__3FooFv`, `__dt__Q23std6vectorFv`, `__dt__Q_10_1a1b...Fv` or `__RTTI__3Foo`.
+pub(crate) fn demangle_class_name(name: &str) -> Option<String> {
+    let mangled = name
+        .strip_prefix("__dt__")
+        .or_else(|| name.strip_prefix("__RTTI__"))?;
+
+    //Qualified names give the number of parts first, as a single digit (`Q2`) or
+    //between underscores for any count (`Q_10_`)
+    let (count, mut rest) = match mangled.strip_prefix('Q') {
+        Some(rest) => match rest.strip_prefix('_') {
+            Some(rest) => {
+                let end = rest.find('_')?;
+                (rest[..end].parse::<usize>().ok()?, &rest[end + 1..])
+            }
+            None => (rest.get(..1)?.parse::<usize>().ok()?, &rest[1..]),
+        },
+        None => (1, mangled),
+    };
+    if count == 0 {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = vec![];
+    for _ in 0..count {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let length: usize = rest[..digits].parse().ok()?;
+        parts.push(rest.get(digits..digits + length)?);
+        rest = &rest[digits + length..];
+    }
+    Some(parts.join("::"))
+}
+
+fn variable_name(prefix: &str, location: &Location) -> String {
+    match location {
+        Location::Stack { offset, .. } => format!("{prefix}_{offset:X}"),
+        Location::Register(register) => format!("{prefix}_r{register}"),
+    }
+}
+
+/// Writer for the pseudo-source of a scope tree.
+struct SourceWriter<'a> {
+    relocation_names: &'a HashMap<u32, String>,
+    out: String,
+}
+
+impl<'a> SourceWriter<'a> {
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.out += "    ";
+        }
+        self.out += text;
+        self.out += "\n";
+    }
+
+    /// Returns the name of the symbol referenced at the given table offset, or its
+    /// address if there is no name for it.
+    fn symbol(&self, offset: u32, address: u32) -> String {
+        match self.relocation_names.get(&offset) {
+            Some(name) => name.clone(),
+            None => format!("{address:#010X}"),
+        }
+    }
+
+    /// Returns the type name for the given catch or specification type.
+    fn type_name(&self, offset: u32, address: u32) -> String {
+        if address == 0 && !self.relocation_names.contains_key(&offset) {
+            return String::from("...");
+        }
+        let symbol = self.symbol(offset, address);
+        demangle_class_name(&symbol).unwrap_or(symbol)
+    }
+
+    /// Returns the declaration of the object destroyed by the given action, along
+    /// with a comment describing how it is destroyed.
+    fn object_declaration(&self, node: &ScopeNode) -> Option<(String, String)> {
+        let dtor_offset = node.references.first()?.offset;
+        let dtor = |address: u32| {
+            let name = self.symbol(dtor_offset, address);
+            let class = demangle_class_name(&name).unwrap_or_else(|| String::from("Object"));
+            (class, name)
+        };
+
+        let declaration = match &node.operands {
+            ActionOperands::DestroyLocal {
+                local,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let name = variable_name("local", local);
+                (
+                    format!("{class} {name};"),
+                    format!("at {local}, destroyed by {dtor}"),
+                )
+            }
+            ActionOperands::DestroyLocalCond {
+                condition,
+                local,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let name = variable_name("temp", local);
+                let comment =
+                    format!("at {local}, constructed if {condition} is set, destroyed by {dtor}");
+                (format!("{class} {name};"), comment)
+            }
+            ActionOperands::DestroyLocalPointer {
+                pointer,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let name = variable_name("object", pointer);
+                let comment = format!("pointed to by {pointer}, destroyed by {dtor}");
+                (format!("{class}* {name};"), comment)
+            }
+            ActionOperands::DestroyLocalArray {
+                array,
+                elements,
+                element_size,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let name = variable_name("array", array);
+                let comment =
+                    format!("at {array}, element size {element_size:#X}, destroyed by {dtor}");
+                (format!("{class} {name}[{elements}];"), comment)
+            }
+            ActionOperands::DestroyBase {
+                member,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let comment = format!("base class at {member}, destroyed by {dtor}");
+                (format!("{class} base_{:X};", member.member_offset), comment)
+            }
+            ActionOperands::DestroyMember {
+                member,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let comment = format!("at {member}, destroyed by {dtor}");
+                (
+                    format!("{class} member_{:X};", member.member_offset),
+                    comment,
+                )
+            }
+            ActionOperands::DestroyMemberCond {
+                condition,
+                member,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let comment =
+                    format!("at {member}, constructed if {condition} is set, destroyed by {dtor}");
+                (
+                    format!("{class} member_{:X};", member.member_offset),
+                    comment,
+                )
+            }
+            ActionOperands::DestroyMemberArray {
+                member,
+                elements,
+                element_size,
+                dtor_address,
+            } => {
+                let (class, dtor) = dtor(*dtor_address);
+                let comment =
+                    format!("at {member}, element size {element_size:#X}, destroyed by {dtor}");
+                let name = format!("member_{:X}", member.member_offset);
+                (format!("{class} {name}[{elements}];"), comment)
+            }
+            ActionOperands::DeletePointer {
+                pointer,
+                dtor_address,
+            } => {
+                let name = variable_name("new", pointer);
+                let delete = self.symbol(dtor_offset, *dtor_address);
+                let comment = format!("freed by {delete} if the constructor throws");
+                (format!("Object* {name} = new Object;"), comment)
+            }
+            ActionOperands::DeletePointerCond {
+                condition,
+                pointer,
+                dtor_address,
+            } => {
+                let name = variable_name("new", pointer);
+                let delete = self.symbol(dtor_offset, *dtor_address);
+                let comment =
+                    format!("freed by {delete} if the constructor throws and {condition} is set");
+                (format!("Object* {name} = new Object;"), comment)
+            }
+            _ => return None,
+        };
+        Some(declaration)
+    }
+
+    fn pc_range(&mut self, depth: usize, (start, end): (u32, u32)) {
+        self.line(depth, &format!("// PC {start:08X}-{end:08X}"));
+    }
+
+    /// Writes the PC ranges and child scopes of a scope in PC order.
+    fn contents(&mut self, depth: usize, pc_ranges: &[(u32, u32)], children: &[ScopeNode]) {
+        let mut pc_ranges = pc_ranges.iter().peekable();
+        for child in children {
+            let child_start = child.pc_extent().0;
+            while let Some(range) = pc_ranges.next_if(|range| range.0 < child_start) {
+                self.pc_range(depth, *range);
+            }
+            self.scope(depth, child);
+        }
+        for range in pc_ranges {
+            self.pc_range(depth, *range);
+        }
+    }
+
+    fn scope(&mut self, depth: usize, node: &ScopeNode) {
+        let (start, end) = node.pc_extent();
+        let extent = format!("PC {start:08X}-{end:08X}");
+
+        if let Some((declaration, comment)) = self.object_declaration(node) {
+            self.line(depth, "{");
+            self.line(depth + 1, &format!("{declaration} // {comment} ({extent})"));
+            self.contents(depth + 1, &node.pc_ranges, &node.children);
+            self.line(depth, "}");
+            return;
+        }
+
+        match &node.operands {
+            ActionOperands::CatchBlock { .. } | ActionOperands::CatchBlock32 { .. } => {
+                //Catch clauses of the same try block follow each other in the chain,
+                //so the innermost one holds the try block's contents
+                let mut clauses: Vec<&ScopeNode> = vec![node];
+                let mut inner = node;
+                while let [child] = &inner.children[..] {
+                    let same_try = matches!(
+                        (catch_clause(inner), catch_clause(child)),
+                        (Some((_, _, info)), Some((_, _, child_info))) if info == child_info
+                    );
+                    if !same_try || !inner.pc_ranges.is_empty() {
+                        break;
+                    }
+                    clauses.push(child);
+                    inner = child;
+                }
+
+                self.line(depth, &format!("try {{ // {extent}"));
+                self.contents(depth + 1, &inner.pc_ranges, &inner.children);
+                for clause in clauses.iter().rev() {
+                    let Some((catch_type, handler_pc, catch_info)) = catch_clause(clause) else {
+                        continue;
+                    };
+                    let Some(reference) = clause.references.first() else {
+                        continue;
+                    };
+                    let type_name = self.type_name(reference.offset, catch_type);
+                    let catch_type = if type_name == "..." {
+                        type_name
+                    } else {
+                        format!("{type_name}&")
+                    };
+                    self.line(
+                        depth,
+                        &format!(
+                            "}} catch ({catch_type}) {{ // handler at PC {handler_pc:08X}, catch info at {catch_info}"
+                        ),
+                    );
+                }
+                self.line(depth, "}");
+            }
+            ActionOperands::ActiveCatchBlock { catch_info } => {
+                self.line(
+                    depth,
+                    &format!("{{ // inside a catch handler, catch info at {catch_info} ({extent})"),
+                );
+                self.contents(depth + 1, &node.pc_ranges, &node.children);
+                self.line(depth, "}");
+            }
+            ActionOperands::Terminate => {
+                self.line(
+                    depth,
+                    &format!("{{ // terminate() on exceptions ({extent})"),
+                );
+                self.contents(depth + 1, &node.pc_ranges, &node.children);
+                self.line(depth, "}");
+            }
+            ActionOperands::Specification {
+                pc_offset,
+                catch_info,
+                ..
+            } => {
+                let types: Vec<String> = node
+                    .references
+                    .iter()
+                    .map(|reference| self.type_name(reference.offset, reference.address))
+                    .collect();
+                self.line(
+                    depth,
+                    &format!(
+                        "{{ // throw({}), unexpected handler at PC {pc_offset:08X}, catch info at {catch_info} ({extent})",
+                        types.join(", ")
+                    ),
+                );
+                self.contents(depth + 1, &node.pc_ranges, &node.children);
+                self.line(depth, "}");
+            }
+            _ => self.contents(depth, &node.pc_ranges, &node.children),
+        }
+    }
+}
+
+/// Returns the catch type, handler PC and catch info location of a catch clause.
+fn catch_clause(node: &ScopeNode) -> Option<(u32, u32, Location)> {
+    match node.operands {
+        ActionOperands::CatchBlock {
+            catch_type,
+            catch_pc_offset,
+            catch_info,
+        } => Some((catch_type, catch_pc_offset as u32, catch_info)),
+        ActionOperands::CatchBlock32 {
+            catch_type,
+            catch_pc_offset,
+            catch_info,
+        } => Some((catch_type, catch_pc_offset, catch_info)),
+        _ => None,
+    }
+}
+
+impl ExceptionTableData {
+    /// Nests the action chains of the PC ranges into a tree of scopes. The actions
+    /// later in a chain are run after the earlier ones when unwinding, so they belong
+    /// to enclosing scopes, and chains which share actions share those scopes.
+    ///
+    /// Branch and null actions only link the actions together, so they don't open
+    /// scopes of their own.
+    pub fn scope_tree(&self) -> Result<ScopeTree, ActionChainError> {
+        let mut tree = ScopeTree::default();
+
+        for pcaction in &self.pc_actions {
+            let range = (pcaction.start_pc, pcaction.end_pc);
+            let chain = self.pc_action_chain(pcaction)?;
+
+            let mut scopes = &mut tree.scopes;
+            let mut pc_ranges = &mut tree.pc_ranges;
+            //Go from the outermost scope inwards, adding the scopes not seen yet
+            for action in chain.iter().rev() {
+                if matches!(action.action_type, ExAction::Branch | ExAction::EndOfList) {
+                    continue;
+                }
+                let index = match scopes
+                    .iter()
+                    .position(|scope| scope.action_offset == action.action_offset)
+                {
+                    Some(index) => index,
+                    None => {
+                        let operands = action.try_get_exaction_data().map_err(|_| {
+                            ActionChainError::InvalidActionData(action.action_offset)
+                        })?;
+                        scopes.push(ScopeNode {
+                            action_offset: action.action_offset,
+                            action_type: action.action_type,
+                            operands: ActionOperands::from_data(
+                                operands,
+                                action.action_param,
+                                self.frame_base(),
+                            ),
+                            references: action.reference_offsets(),
+                            pc_ranges: vec![],
+                            children: vec![],
+                        });
+                        scopes.len() - 1
+                    }
+                };
+                let scope = &mut scopes[index];
+                pc_ranges = &mut scope.pc_ranges;
+                scopes = &mut scope.children;
+            }
+
+            //The range belongs to the innermost scope, or the function itself
+            pc_ranges.push(range);
+        }
+
+        tree.pc_ranges.sort_unstable();
+        for scope in &mut tree.scopes {
+            scope.sort();
+        }
+        tree.scopes.sort_by_key(|scope| scope.pc_extent().0);
+        Ok(tree)
+    }
+
+    /// Writes the scope tree of the table as C++-like pseudo-source, to use as a
+    /// skeleton for the function. Every object with a destructor is declared in its
+    /// own block, try blocks are shown with their catch clauses, and the PC ranges are
+    /// given in comments where they are in the innermost scope.
+    ///
+    /// Dtors and types are named using the given relocation names, which are keyed by
+    /// their offset in the table.
+    pub fn to_pseudo_source(
+        &self,
+        relocation_names: &HashMap<u32, String>,
+    ) -> Result<String, ActionChainError> {
+        let tree = self.scope_tree()?;
+        let mut writer = SourceWriter {
+            relocation_names,
+            out: String::new(),
+        };
+
+        let mut frame: Vec<String> = vec![format!("locals relative to {}", self.frame_base())];
+        if self.gpr_save_range != 0 {
+            frame.push(format!("saves r{}-r31", 32 - self.gpr_save_range));
+        }
+        if self.fpr_save_range != 0 {
+            frame.push(format!("saves f{}-f31", 32 - self.fpr_save_range));
+        }
+        if self.saved_cr {
+            frame.push(String::from("saves CR"));
+        }
+        let _ = writeln!(writer.out, "void function() {{ // {}", frame.join(", "));
+        writer.contents(1, &tree.pc_ranges, &tree.scopes);
+        writer.out += "}\n";
+
+        Ok(writer.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::demangle_class_name;
+    use crate::text::parse_text;
+    use crate::ExAction;
+
+    /// Function constructing a Foo, then a try block constructing a Bar, with a catch
+    /// clause for Baz and one for any exception. The Foo is still alive after the try
+    /// block, so its action is shared by the ranges before and after it.
+    const TEXT: &str = r#"
+.flags gpr_save_range=2
+.pc_range 0x10, 0x20, foo
+.pc_range 0x20, 0x30, try
+.pc_range 0x30, 0x40, bar
+.pc_range 0x40, 0x50, foo
+.pc_range 0x50, 0x60, none
+
+bar:
+    destroy_local local_offset=0xC, dtor_address="__dt__3BarFv"
+try:
+    catch_block catch_type="__RTTI__3Baz", catch_pc_offset=0x70, cinfo_ref=0x18
+    catch_block catch_type=0, catch_pc_offset=0x80, cinfo_ref=0x18
+foo:
+    destroy_local local_offset=0x8, dtor_address="__dt__3FooFv", end
+"#;
+
+    const PSEUDO_SOURCE: &str = "\
+void function() { // locals relative to SP, saves r30-r31
+    {
+        Foo local_8; // at 0x8(SP), destroyed by __dt__3FooFv (PC 00000010-00000050)
+        // PC 00000010-00000020
+        try { // PC 00000020-00000040
+            // PC 00000020-00000030
+            {
+                Bar local_C; // at 0xC(SP), destroyed by __dt__3BarFv (PC 00000030-00000040)
+                // PC 00000030-00000040
+            }
+        } catch (Baz&) { // handler at PC 00000070, catch info at 0x18(SP)
+        } catch (...) { // handler at PC 00000080, catch info at 0x18(SP)
+        }
+        // PC 00000040-00000050
+    }
+    // PC 00000050-00000060
+}
+";
+
+    #[test]
+    fn demangle_class_names() {
+        let demangle = |name: &str| demangle_class_name(name);
+        assert_eq!(demangle("__dt__3FooFv").as_deref(), Some("Foo"));
+        assert_eq!(demangle("__RTTI__3Foo").as_deref(), Some("Foo"));
+        assert_eq!(
+            demangle("__dt__Q23std6vectorFv").as_deref(),
+            Some("std::vector")
+        );
+        assert_eq!(demangle("__RTTI__Q_2_1a3Bar").as_deref(), Some("a::Bar"));
+        assert_eq!(
+            demangle("__dt__Q_10_1a1b1c1d1e1f1g1h1i1jFv").as_deref(),
+            Some("a::b::c::d::e::f::g::h::i::j")
+        );
+    }
+
+    #[test]
+    fn invalid_class_names() {
+        assert_eq!(demangle_class_name("Foo"), None);
+        assert_eq!(demangle_class_name("__dt__Q_2a3FooFv"), None);
+        assert_eq!(demangle_class_name("__dt__Q_0_Fv"), None);
+        assert_eq!(demangle_class_name("__dt__Q31a1bFv"), None);
+        assert_eq!(demangle_class_name("__dt__9FooFv"), None);
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let (table, _) = parse_text(TEXT).unwrap();
+        let tree = table.scope_tree().unwrap();
+        assert_eq!(tree.pc_ranges, vec![(0x50, 0x60)]);
+        assert_eq!(tree.scopes.len(), 1);
+
+        //The Foo scope holds the ranges on both sides of the try block
+        let foo = &tree.scopes[0];
+        assert!(matches!(foo.action_type, ExAction::DestroyLocal));
+        assert_eq!(foo.pc_ranges, vec![(0x10, 0x20), (0x40, 0x50)]);
+        assert_eq!(foo.pc_extent(), (0x10, 0x50));
+        assert_eq!(foo.children.len(), 1);
+
+        //Catch clauses later in the chain enclose the earlier ones
+        let catch_all = &foo.children[0];
+        assert!(matches!(catch_all.action_type, ExAction::CatchBlock));
+        assert!(catch_all.pc_ranges.is_empty());
+        assert_eq!(catch_all.children.len(), 1);
+        let catch_baz = &catch_all.children[0];
+        assert_eq!(catch_baz.pc_ranges, vec![(0x20, 0x30)]);
+        assert_eq!(catch_baz.references.len(), 1);
+        assert_eq!(catch_baz.children.len(), 1);
+
+        let bar = &catch_baz.children[0];
+        assert_eq!(bar.pc_ranges, vec![(0x30, 0x40)]);
+        assert!(bar.children.is_empty());
+    }
+
+    #[test]
+    fn pseudo_source() {
+        let (table, names) = parse_text(TEXT).unwrap();
+        let source = table.to_pseudo_source(&names).unwrap();
+        assert_eq!(source, PSEUDO_SOURCE);
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::{
+    decode_extab, parse_extabindex, ExAction, ExActionData, ExceptionTable, ExceptionTableData,
+    ExtabDecodeError, ExtabIndexEntry, ExtabIndexError, EXTABINDEX_ENTRY_SIZE,
+};
+
+#[derive(Error, Debug)]
+pub enum ExtabSectionError {
+    #[error("Invalid extabindex section: {0}")]
+    Index(#[from] ExtabIndexError),
+    #[error("extabindex entry at offset 0x{0:X} does not point into the extab section")]
+    InvalidTablePointer(u32),
+    #[error("Failed to decode the exception table at offset 0x{0:X}: {1}")]
+    Decode(u32, ExtabDecodeError),
+}
+
+/// Struct for an exception table found in an extab section.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionTable {
+    pub offset: u32, //Offset of the table in the extab section
+    pub size: u32,
+    //extabindex entries pointing to the table, giving the functions it belongs to.
+    //This is empty when the table boundaries were found without an index.
+    pub index_entries: Vec<ExtabIndexEntry>,
+    pub data: ExceptionTableData,
+}
+
+/// Splits an extab section into its tables and decodes each of them, returning the
+/// tables in order of their offset.
+///
+/// If the extabindex section is given, the tables start at the offsets its entries
+/// point to (which are converted using the address of the extab section), and each
+/// one runs until the start of the next one. Otherwise, the end of each table is
+/// found from its action chains, see `find_table_size`, and zeroes at the end of the
+/// section are skipped as padding.
+pub fn decode_extab_section(
+    extab: &[u8],
+    extab_address: u32,
+    extabindex: Option<&[u8]>,
+) -> Result<Vec<SectionTable>, ExtabSectionError> {
+    let mut tables: Vec<SectionTable> = vec![];
+
+    match extabindex {
+        Some(index_data) => {
+            let entries = parse_extabindex(index_data)?;
+
+            //Find the start of every table, along with the entries pointing to it
+            let mut starts: Vec<(u32, ExtabIndexEntry)> = vec![];
+            for (i, entry) in entries.into_iter().enumerate() {
+                let entry_offset = (i as u32) * EXTABINDEX_ENTRY_SIZE;
+                let table_offset = entry
+                    .extab_offset(extab_address, extab.len() as u32)
+                    .ok_or(ExtabSectionError::InvalidTablePointer(entry_offset))?;
+                starts.push((table_offset, entry));
+            }
+            starts.sort_by_key(|(offset, entry)| (*offset, entry.function_address));
+            let table_bytes = split_tables(extab, starts.iter().map(|(offset, _)| *offset));
+
+            for (offset, entry) in starts {
+                if let Some(table) = tables.last_mut() {
+                    if table.offset == offset {
+                        table.index_entries.push(entry);
+                        continue;
+                    }
+                }
+
+                let bytes = table_bytes[&offset];
+                let data = decode_extab(bytes).map_err(|e| ExtabSectionError::Decode(offset, e))?;
+                tables.push(SectionTable {
+                    offset,
+                    size: bytes.len() as u32,
+                    index_entries: vec![entry],
+                    data,
+                });
+            }
+        }
+        None => {
+            let mut offset: u32 = 0;
+            while offset < extab.len() as u32 {
+                let table_bytes = &extab[offset as usize..];
+                //Zeroes at the end of the section are padding rather than a table
+                if table_bytes.iter().all(|byte| *byte == 0) {
+                    break;
+                }
+                let size = find_table_size(table_bytes)
+                    .map_err(|e| ExtabSectionError::Decode(offset, e))?;
+                let data = decode_extab(&table_bytes[..size as usize])
+                    .map_err(|e| ExtabSectionError::Decode(offset, e))?;
+                tables.push(SectionTable {
+                    offset,
+                    size,
+                    index_entries: vec![],
+                    data,
+                });
+                offset += size;
+            }
+        }
+    }
+
+    Ok(tables)
+}
+
+/// Splits an extab section at the given table offsets, which must be inside the
+/// section, returning the bytes of the table at each offset. Each table runs until the
+/// start of the next one, or the end of the section.
+pub(crate) fn split_tables(
+    extab: &[u8],
+    offsets: impl IntoIterator<Item = u32>,
+) -> BTreeMap<u32, &[u8]> {
+    let mut tables: BTreeMap<u32, &[u8]> = offsets
+        .into_iter()
+        .map(|offset| (offset, &[][..]))
+        .collect();
+
+    let mut end = extab.len();
+    for (offset, bytes) in tables.iter_mut().rev() {
+        *bytes = &extab[*offset as usize..end];
+        end = *offset as usize;
+    }
+
+    tables
+}
+
+/// Finds the size of the table at the start of the given data, which may be followed
+/// by other tables.
+///
+/// Tables without PC actions end after their terminator, as with the 8 byte tables
+/// which only describe the saved registers. Otherwise, the actions are read until the
+/// action chain of the last referenced action ends, and the table is padded up to a
+/// multiple of 4 bytes.
+pub fn find_table_size(data: &[u8]) -> Result<u32, ExtabDecodeError> {
+    //Only the header and range entries are checked, as the data may hold more than one table
+    let table = ExceptionTable::parse(data)?;
+
+    let mut last_referenced: u32 = 0;
+    for pcaction in table.pc_actions() {
+        last_referenced = last_referenced.max(pcaction.action_offset);
+    }
+
+    let mut end = table.actions_offset();
+    if last_referenced != 0 {
+        for action in table.actions() {
+            let action = action?;
+            end = action.action_offset + 2 + action.bytes.len() as u32;
+
+            if let ExAction::Branch = action.action_type {
+                if let Ok(ExActionData::Branch { target_offset }) = action.try_get_exaction_data() {
+                    last_referenced = last_referenced.max(target_offset as u32);
+                }
+            }
+
+            let ends_chain = action.has_end_bit
+                || matches!(action.action_type, ExAction::EndOfList | ExAction::Branch);
+            if action.action_offset >= last_referenced && ends_chain {
+                break;
+            }
+        }
+    }
+
+    //Tables are aligned to 4 bytes, with null actions as padding
+    let size = (end + 3) & !3;
+    Ok(size.min(data.len() as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_extab_section, find_table_size, ExtabSectionError};
+    use crate::fixtures::EMPTY_TABLE;
+    use crate::ExtabIndexError;
+
+    /// Table whose first action branches past the rest of its actions.
+    #[rustfmt::skip]
+    const BRANCH_TABLE: [u8; 32] = [
+        0x10, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x01, 0x00, 0x00, 0x1C, //Branch to the end of the list
+        0x82, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, //Destroy local
+        0x80, 0x00, 0x00, 0x00, //End of list, padding
+    ];
+
+    /// Returns a section holding a table without PC ranges, the branch table, another
+    /// table without PC ranges, and padding.
+    fn extab_section() -> Vec<u8> {
+        [&EMPTY_TABLE[..], &BRANCH_TABLE, &EMPTY_TABLE, &[0; 8]].concat()
+    }
+
+    const EXTAB_ADDRESS: u32 = 0x80005000;
+
+    #[rustfmt::skip]
+    const EXTABINDEX_SECTION: [u8; 48] = [
+        0x80, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x20, 0x80, 0x00, 0x50, 0x00,
+        0x80, 0x00, 0x10, 0x20, 0x00, 0x00, 0x00, 0x20, 0x80, 0x00, 0x50, 0x08,
+        0x80, 0x00, 0x10, 0x40, 0x00, 0x00, 0x00, 0x20, 0x80, 0x00, 0x50, 0x08,
+        0x80, 0x00, 0x10, 0x60, 0x00, 0x00, 0x00, 0x20, 0x80, 0x00, 0x50, 0x28,
+    ];
+
+    #[test]
+    fn table_sizes() {
+        let section = extab_section();
+        assert_eq!(find_table_size(&section).unwrap(), 8);
+        assert_eq!(find_table_size(&section[8..]).unwrap(), 0x20);
+        assert_eq!(find_table_size(&section[0x28..]).unwrap(), 8);
+    }
+
+    #[test]
+    fn section_without_index() {
+        let tables = decode_extab_section(&extab_section(), EXTAB_ADDRESS, None).unwrap();
+        let bounds: Vec<(u32, u32)> = tables
+            .iter()
+            .map(|table| (table.offset, table.size))
+            .collect();
+        assert_eq!(bounds, [(0, 8), (8, 0x20), (0x28, 8)]);
+        //The padding of the table is decoded as a null action
+        assert_eq!(tables[1].data.exception_actions.len(), 4);
+        assert!(tables.iter().all(|table| table.index_entries.is_empty()));
+    }
+
+    #[test]
+    fn section_with_index() {
+        let tables =
+            decode_extab_section(&extab_section(), EXTAB_ADDRESS, Some(&EXTABINDEX_SECTION))
+                .unwrap();
+        let bounds: Vec<(u32, u32)> = tables
+            .iter()
+            .map(|table| (table.offset, table.size))
+            .collect();
+        //The last table runs until the end of the section
+        assert_eq!(bounds, [(0, 8), (8, 0x20), (0x28, 0x10)]);
+
+        let functions: Vec<u32> = tables[1]
+            .index_entries
+            .iter()
+            .map(|entry| entry.function_address)
+            .collect();
+        assert_eq!(functions, [0x80001020, 0x80001040]);
+    }
+
+    #[test]
+    fn index_pointing_outside_of_the_section() {
+        let mut extabindex = EXTABINDEX_SECTION;
+        extabindex[0x22] = 0x60;
+        assert!(matches!(
+            decode_extab_section(&extab_section(), EXTAB_ADDRESS, Some(&extabindex)),
+            Err(ExtabSectionError::InvalidTablePointer(0x18))
+        ));
+    }
+
+    #[test]
+    fn invalid_index_size() {
+        let extabindex = &EXTABINDEX_SECTION[..40];
+        assert!(matches!(
+            decode_extab_section(&extab_section(), EXTAB_ADDRESS, Some(extabindex)),
+            Err(ExtabSectionError::Index(ExtabIndexError::InvalidIndexSize(
+                40
+            )))
+        ));
+    }
+}
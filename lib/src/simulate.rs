@@ -0,0 +1,691 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::{
+    ActionChainError, ActionOperands, ExAction, ExceptionAction, ExceptionTableData, Location,
+    MemberLocation, PCAction, Relocation,
+};
+
+/// Values of the function's frame which the runtime reads while unwinding, for the
+/// conditions of the conditional actions.
+#[derive(Debug, Clone)]
+pub struct FrameModel {
+    /// Values of the condition flags, keyed by their stack slot or register.
+    pub conditions: HashMap<Location, bool>,
+    /// Value used for conditions which aren't in the map. Conditions are set once their
+    /// object is constructed, so this defaults to true.
+    pub default_condition: bool,
+}
+
+impl FrameModel {
+    pub fn new() -> Self {
+        Self {
+            conditions: HashMap::new(),
+            default_condition: true,
+        }
+    }
+
+    pub fn condition(&self, location: &Location) -> bool {
+        self.conditions
+            .get(location)
+            .copied()
+            .unwrap_or(self.default_condition)
+    }
+}
+
+impl Default for FrameModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Object destroyed by an action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnwindObject {
+    /// Object stored at the location.
+    Local(Location),
+    /// Object pointed to by the value at the location.
+    Pointer(Location),
+    /// Member or base class of an object.
+    Member(MemberLocation),
+}
+
+/// Step taken by the runtime while unwinding through a function. Symbol references
+/// are given with their offset in the table, so they can be named.
+#[derive(Debug, Clone)]
+pub enum UnwindStep {
+    Branch {
+        action_offset: u32,
+        target_offset: u32,
+    },
+    /// Calls the dtor on the object, or on every element of the array if the number
+    /// of elements is given.
+    Destroy {
+        action_offset: u32,
+        action_type: ExAction,
+        object: UnwindObject,
+        elements: Option<u32>,
+        dtor: Relocation,
+    },
+    /// Frees the memory of an object whose constructor threw.
+    Delete {
+        action_offset: u32,
+        pointer: Location,
+        delete: Relocation,
+    },
+    /// Skips a conditional action, as its condition isn't set.
+    SkipCondition {
+        action_offset: u32,
+        condition: Location,
+    },
+    /// Destroys the exception caught by the catch handler being left.
+    DestroyCaughtException {
+        action_offset: u32,
+        catch_info: Location,
+    },
+    /// Skips a catch clause whose type doesn't match the thrown type.
+    SkipCatch {
+        action_offset: u32,
+        catch_type: Relocation,
+    },
+    /// Passes a throw specification which allows the thrown type.
+    PassSpecification { action_offset: u32 },
+    /// Lands in the catch handler.
+    Catch {
+        action_offset: u32,
+        catch_type: Relocation,
+        handler_pc: u32,
+        catch_info: Location,
+    },
+    /// Lands in the handler of a throw specification which doesn't allow the thrown
+    /// type, which calls `unexpected`.
+    Unexpected {
+        action_offset: u32,
+        handler_pc: u32,
+        catch_info: Location,
+    },
+    /// Calls `terminate`, without running any other action.
+    Terminate { action_offset: u32 },
+}
+
+/// Where control goes after unwinding through the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnwindOutcome {
+    /// Caught by the handler at the given PC.
+    Caught { handler_pc: u32 },
+    /// Not allowed by a throw specification, whose handler at the given PC calls
+    /// `unexpected`.
+    Unexpected { handler_pc: u32 },
+    /// `terminate` is called.
+    Terminated,
+    /// The exception leaves the function, and unwinding goes on in the caller.
+    Propagated,
+}
+
+/// Result of simulating an exception thrown at a PC.
+#[derive(Debug, Clone)]
+pub struct UnwindTrace {
+    pub pc: u32,
+    /// PC action whose range contains the PC, if any.
+    pub pc_action: Option<PCAction>,
+    pub steps: Vec<UnwindStep>,
+    pub outcome: UnwindOutcome,
+}
+
+fn symbol_name(relocation_names: &HashMap<u32, String>, symbol: &Relocation) -> String {
+    match relocation_names.get(&symbol.offset) {
+        Some(name) => name.clone(),
+        None => format!("{:#010X}", symbol.address),
+    }
+}
+
+impl UnwindTrace {
+    /// Converts the trace into a string, with one line per step. Symbols are named
+    /// using the given relocation names, which are keyed by their offset in the table.
+    pub fn to_string_with_relocations(&self, relocation_names: &HashMap<u32, String>) -> String {
+        let name = |symbol: &Relocation| symbol_name(relocation_names, symbol);
+        let mut out = String::new();
+
+        let pc = self.pc;
+        match &self.pc_action {
+            Some(pcaction) => {
+                let _ = writeln!(
+                    out,
+                    "Exception thrown at PC {pc:08X} (range {:08X}-{:08X}, action {:06X})",
+                    pcaction.start_pc, pcaction.end_pc, pcaction.action_offset
+                );
+            }
+            None => {
+                let _ = writeln!(out, "Exception thrown at PC {pc:08X} (no PC range)");
+            }
+        }
+
+        for step in &self.steps {
+            let line = match step {
+                UnwindStep::Branch {
+                    action_offset,
+                    target_offset,
+                } => format!("{action_offset:06X}: branch to {target_offset:06X}"),
+                UnwindStep::Destroy {
+                    action_offset,
+                    action_type,
+                    object,
+                    elements,
+                    dtor,
+                } => {
+                    let object = match object {
+                        UnwindObject::Local(location) => format!("object at {location}"),
+                        UnwindObject::Pointer(location) => {
+                            format!("object pointed to by {location}")
+                        }
+                        UnwindObject::Member(member) => match action_type {
+                            ExAction::DestroyBase => format!("base at {member}"),
+                            _ => format!("member at {member}"),
+                        },
+                    };
+                    let object = match elements {
+                        Some(elements) => format!("{elements} elements of array {object}"),
+                        None => object,
+                    };
+                    format!("{action_offset:06X}: destroy {object} with {}", name(dtor))
+                }
+                UnwindStep::Delete {
+                    action_offset,
+                    pointer,
+                    delete,
+                } => format!(
+                    "{action_offset:06X}: free object pointed to by {pointer} with {}",
+                    name(delete)
+                ),
+                UnwindStep::SkipCondition {
+                    action_offset,
+                    condition,
+                } => format!("{action_offset:06X}: skipped, condition {condition} is not set"),
+                UnwindStep::DestroyCaughtException {
+                    action_offset,
+                    catch_info,
+                } => format!(
+                    "{action_offset:06X}: destroy the caught exception, catch info at {catch_info}"
+                ),
+                UnwindStep::SkipCatch {
+                    action_offset,
+                    catch_type,
+                } => format!(
+                    "{action_offset:06X}: catch clause for {} does not match",
+                    name(catch_type)
+                ),
+                UnwindStep::PassSpecification { action_offset } => {
+                    format!("{action_offset:06X}: allowed by the throw specification")
+                }
+                UnwindStep::Catch {
+                    action_offset,
+                    catch_type,
+                    handler_pc,
+                    catch_info,
+                } => {
+                    let catch_type = if catch_type.address == 0
+                        && !relocation_names.contains_key(&catch_type.offset)
+                    {
+                        String::from("...")
+                    } else {
+                        name(catch_type)
+                    };
+                    format!("{action_offset:06X}: caught by catch ({catch_type}) at PC {handler_pc:08X}, catch info at {catch_info}")
+                }
+                UnwindStep::Unexpected {
+                    action_offset,
+                    handler_pc,
+                    catch_info,
+                } => format!("{action_offset:06X}: not allowed by the throw specification, unexpected() handler at PC {handler_pc:08X}, catch info at {catch_info}"),
+                UnwindStep::Terminate { action_offset } => {
+                    format!("{action_offset:06X}: terminate()")
+                }
+            };
+            out += &line;
+            out += "\n";
+        }
+
+        let outcome = match self.outcome {
+            UnwindOutcome::Caught { handler_pc } => {
+                format!("Result: control lands in the catch handler at PC {handler_pc:08X}")
+            }
+            UnwindOutcome::Unexpected { handler_pc } => {
+                format!("Result: control lands in the unexpected() handler at PC {handler_pc:08X}")
+            }
+            UnwindOutcome::Terminated => String::from("Result: terminate() is called"),
+            UnwindOutcome::Propagated => {
+                String::from("Result: the exception propagates to the caller")
+            }
+        };
+        out += &outcome;
+        out += "\n";
+        out
+    }
+}
+
+impl ExceptionTableData {
+    /// Returns the operands of an action in a chain, which is checked when decoding.
+    pub(crate) fn chain_operands(
+        &self,
+        action: &ExceptionAction,
+    ) -> Result<ActionOperands, ActionChainError> {
+        let data = action
+            .try_get_exaction_data()
+            .map_err(|_| ActionChainError::InvalidActionData(action.action_offset))?;
+        Ok(ActionOperands::from_data(
+            data,
+            action.action_param,
+            self.frame_base(),
+        ))
+    }
+
+    /// Searches the action chain for the action which ends unwinding in this function:
+    /// a matching catch clause, a throw specification which doesn't allow the thrown
+    /// type, or a terminate action. Returns its index in the chain.
+    ///
+    /// Null types are only treated as `catch (...)` if they have no relocation name, as
+    /// every type of an unlinked table is null. The given function matches the other
+    /// types, and null specification types without a name match nothing.
+    pub(crate) fn find_unwind_end<F>(
+        &self,
+        chain: &[&ExceptionAction],
+        relocation_names: &HashMap<u32, String>,
+        matches: F,
+    ) -> Result<Option<usize>, ActionChainError>
+    where
+        F: Fn(&Relocation) -> bool,
+    {
+        let is_unknown = |reference: &Relocation| {
+            reference.address == 0 && !relocation_names.contains_key(&reference.offset)
+        };
+
+        for (i, action) in chain.iter().enumerate() {
+            let types = action.reference_offsets();
+            let found = match self.chain_operands(action)? {
+                ActionOperands::CatchBlock { .. } | ActionOperands::CatchBlock32 { .. } => types
+                    .iter()
+                    .any(|catch_type| is_unknown(catch_type) || matches(catch_type)),
+                ActionOperands::Specification { .. } => !types
+                    .iter()
+                    .any(|spec_type| !is_unknown(spec_type) && matches(spec_type)),
+                ActionOperands::Terminate => true,
+                _ => false,
+            };
+            if found {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Simulates an exception of the given type thrown at the given PC, as done by the
+    /// CodeWarrior runtime, returning the steps it takes in this function.
+    ///
+    /// As with the runtime, the action chain is first searched for a matching catch
+    /// clause, a throw specification which doesn't allow the type, or a terminate
+    /// action. If a terminate action comes first, `terminate` is called without
+    /// running anything else. Otherwise the actions up to the one found are run in
+    /// order: objects are destroyed, and conditional actions are skipped if their
+    /// condition isn't set in the frame model.
+    ///
+    /// Catch clauses match if their type is the thrown type's address, or if they
+    /// catch everything. A thrown type of `None` only matches `catch (...)`. Types are
+    /// matched by address, so this is meant for linked tables, see
+    /// `simulate_throw_matching` for unlinked ones.
+    pub fn simulate_throw(
+        &self,
+        pc: u32,
+        thrown_type: Option<u32>,
+        frame: &FrameModel,
+    ) -> Result<UnwindTrace, ActionChainError> {
+        self.simulate_throw_matching(pc, frame, &HashMap::new(), |catch_type| {
+            thrown_type == Some(catch_type.address)
+        })
+    }
+
+    /// Simulates a throw like `simulate_throw`, using the given function to check
+    /// whether the thrown exception matches a catch clause or specification type. The
+    /// types are given with their offset in the table, so unlinked tables can be
+    /// matched by relocation name.
+    ///
+    /// Catch clauses with a null type are `catch (...)`, unless the given relocation
+    /// names (keyed by their offset in the table) have a name for them, in which case
+    /// they are passed to the function like any other type.
+    pub fn simulate_throw_matching<F>(
+        &self,
+        pc: u32,
+        frame: &FrameModel,
+        relocation_names: &HashMap<u32, String>,
+        matches: F,
+    ) -> Result<UnwindTrace, ActionChainError>
+    where
+        F: Fn(&Relocation) -> bool,
+    {
+        let pc_action = self.find_pc_action(pc).cloned();
+        let chain = match &pc_action {
+            Some(pcaction) => self.pc_action_chain(pcaction)?,
+            None => vec![],
+        };
+
+        let end = self.find_unwind_end(&chain, relocation_names, &matches)?;
+
+        let mut steps: Vec<UnwindStep> = vec![];
+        if let Some(index) = end {
+            if let ExAction::Terminate = chain[index].action_type {
+                steps.push(UnwindStep::Terminate {
+                    action_offset: chain[index].action_offset,
+                });
+                return Ok(UnwindTrace {
+                    pc,
+                    pc_action,
+                    steps,
+                    outcome: UnwindOutcome::Terminated,
+                });
+            }
+        }
+
+        let mut outcome = UnwindOutcome::Propagated;
+        let run = match end {
+            Some(index) => &chain[..=index],
+            None => &chain[..],
+        };
+        for (i, action) in run.iter().enumerate() {
+            let is_end = end == Some(i);
+            let action_offset = action.action_offset;
+            //Dtor and catch type actions reference a single symbol
+            let reference = || {
+                let mut references = action.reference_offsets();
+                references.pop().unwrap_or(Relocation {
+                    offset: action_offset + 2,
+                    address: 0,
+                })
+            };
+            let destroy = |object: UnwindObject, elements: Option<u32>| UnwindStep::Destroy {
+                action_offset,
+                action_type: action.action_type,
+                object,
+                elements,
+                dtor: reference(),
+            };
+            let skip = |condition: Location| UnwindStep::SkipCondition {
+                action_offset,
+                condition,
+            };
+
+            let step = match self.chain_operands(action)? {
+                ActionOperands::EndOfList => continue,
+                ActionOperands::Branch { target_offset } => UnwindStep::Branch {
+                    action_offset,
+                    target_offset: target_offset as u32,
+                },
+                ActionOperands::DestroyLocal { local, .. } => {
+                    destroy(UnwindObject::Local(local), None)
+                }
+                ActionOperands::DestroyLocalCond {
+                    condition, local, ..
+                } => match frame.condition(&condition) {
+                    true => destroy(UnwindObject::Local(local), None),
+                    false => skip(condition),
+                },
+                ActionOperands::DestroyLocalPointer { pointer, .. } => {
+                    destroy(UnwindObject::Pointer(pointer), None)
+                }
+                ActionOperands::DestroyLocalArray {
+                    array, elements, ..
+                } => destroy(UnwindObject::Local(array), Some(elements as u32)),
+                ActionOperands::DestroyBase { member, .. }
+                | ActionOperands::DestroyMember { member, .. } => {
+                    destroy(UnwindObject::Member(member), None)
+                }
+                ActionOperands::DestroyMemberCond {
+                    condition, member, ..
+                } => match frame.condition(&condition) {
+                    true => destroy(UnwindObject::Member(member), None),
+                    false => skip(condition),
+                },
+                ActionOperands::DestroyMemberArray {
+                    member, elements, ..
+                } => destroy(UnwindObject::Member(member), Some(elements)),
+                ActionOperands::DeletePointer { pointer, .. } => UnwindStep::Delete {
+                    action_offset,
+                    pointer,
+                    delete: reference(),
+                },
+                ActionOperands::DeletePointerCond {
+                    condition, pointer, ..
+                } => match frame.condition(&condition) {
+                    true => UnwindStep::Delete {
+                        action_offset,
+                        pointer,
+                        delete: reference(),
+                    },
+                    false => skip(condition),
+                },
+                ActionOperands::CatchBlock {
+                    catch_pc_offset,
+                    catch_info,
+                    ..
+                } if is_end => {
+                    outcome = UnwindOutcome::Caught {
+                        handler_pc: catch_pc_offset as u32,
+                    };
+                    UnwindStep::Catch {
+                        action_offset,
+                        catch_type: reference(),
+                        handler_pc: catch_pc_offset as u32,
+                        catch_info,
+                    }
+                }
+                ActionOperands::CatchBlock32 {
+                    catch_pc_offset,
+                    catch_info,
+                    ..
+                } if is_end => {
+                    outcome = UnwindOutcome::Caught {
+                        handler_pc: catch_pc_offset,
+                    };
+                    UnwindStep::Catch {
+                        action_offset,
+                        catch_type: reference(),
+                        handler_pc: catch_pc_offset,
+                        catch_info,
+                    }
+                }
+                ActionOperands::CatchBlock { .. } | ActionOperands::CatchBlock32 { .. } => {
+                    UnwindStep::SkipCatch {
+                        action_offset,
+                        catch_type: reference(),
+                    }
+                }
+                ActionOperands::ActiveCatchBlock { catch_info } => {
+                    UnwindStep::DestroyCaughtException {
+                        action_offset,
+                        catch_info,
+                    }
+                }
+                ActionOperands::Specification {
+                    pc_offset,
+                    catch_info,
+                    ..
+                } if is_end => {
+                    outcome = UnwindOutcome::Unexpected {
+                        handler_pc: pc_offset,
+                    };
+                    UnwindStep::Unexpected {
+                        action_offset,
+                        handler_pc: pc_offset,
+                        catch_info,
+                    }
+                }
+                ActionOperands::Specification { .. } => {
+                    UnwindStep::PassSpecification { action_offset }
+                }
+                //Terminate actions end the search above
+                ActionOperands::Terminate => UnwindStep::Terminate { action_offset },
+            };
+            steps.push(step);
+        }
+
+        Ok(UnwindTrace {
+            pc,
+            pc_action,
+            steps,
+            outcome,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameModel, UnwindOutcome, UnwindStep};
+    use crate::text::parse_text;
+    use crate::{ExceptionTableData, Location};
+
+    const CATCH_TYPE: u32 = 0x80002000;
+    const OTHER_TYPE: u32 = 0x80003000;
+
+    const TEXT: &str = r#"
+.flags
+.pc_range 0x0, 0x10, destroy
+.pc_range 0x10, 0x20, terminate
+.pc_range 0x20, 0x30, spec
+
+destroy:
+    destroy_local_cond condition=0x10, local_offset=0x8, dtor_address=0x80001000
+    branch target_offset=catches
+terminate:
+    destroy_local local_offset=0xC, dtor_address=0x80001004
+    terminate end
+catches:
+    catch_block catch_type=0x80002000, catch_pc_offset=0x100, cinfo_ref=0x20
+    catch_block catch_type=0, catch_pc_offset=0x200, cinfo_ref=0x20, end
+spec:
+    specification spec=[0x80002000], pc_offset=0x300, cinfo_ref=0x20, end
+"#;
+
+    fn table() -> ExceptionTableData {
+        parse_text(TEXT).unwrap().0
+    }
+
+    #[test]
+    fn follows_branches_to_catch() {
+        let table = table();
+        let trace = table
+            .simulate_throw(0x4, Some(CATCH_TYPE), &FrameModel::new())
+            .unwrap();
+        assert!(matches!(
+            trace.steps[..],
+            [
+                UnwindStep::Destroy { .. },
+                UnwindStep::Branch { .. },
+                UnwindStep::Catch {
+                    handler_pc: 0x100,
+                    ..
+                }
+            ]
+        ));
+        assert_eq!(trace.outcome, UnwindOutcome::Caught { handler_pc: 0x100 });
+
+        //Other types skip the first clause and land in the catch (...) clause
+        let trace = table
+            .simulate_throw(0x4, Some(OTHER_TYPE), &FrameModel::new())
+            .unwrap();
+        assert!(matches!(
+            trace.steps[2..],
+            [
+                UnwindStep::SkipCatch { .. },
+                UnwindStep::Catch {
+                    handler_pc: 0x200,
+                    ..
+                }
+            ]
+        ));
+        assert_eq!(trace.outcome, UnwindOutcome::Caught { handler_pc: 0x200 });
+    }
+
+    #[test]
+    fn skips_unset_conditions() {
+        let table = table();
+        let condition = Location::Stack {
+            base: table.frame_base(),
+            offset: 0x10,
+        };
+        let mut frame = FrameModel::new();
+        frame.conditions.insert(condition, false);
+
+        let trace = table.simulate_throw(0x4, Some(CATCH_TYPE), &frame).unwrap();
+        match &trace.steps[0] {
+            UnwindStep::SkipCondition {
+                condition: skipped, ..
+            } => assert_eq!(*skipped, condition),
+            step => panic!("unexpected step {step:?}"),
+        }
+        assert_eq!(trace.outcome, UnwindOutcome::Caught { handler_pc: 0x100 });
+    }
+
+    #[test]
+    fn terminate_comes_first() {
+        //The local isn't destroyed, as terminate is called right away
+        let trace = table()
+            .simulate_throw(0x14, Some(CATCH_TYPE), &FrameModel::new())
+            .unwrap();
+        assert!(matches!(trace.steps[..], [UnwindStep::Terminate { .. }]));
+        assert_eq!(trace.outcome, UnwindOutcome::Terminated);
+    }
+
+    #[test]
+    fn specifications() {
+        let table = table();
+        let trace = table
+            .simulate_throw(0x24, Some(CATCH_TYPE), &FrameModel::new())
+            .unwrap();
+        assert!(matches!(
+            trace.steps[..],
+            [UnwindStep::PassSpecification { .. }]
+        ));
+        assert_eq!(trace.outcome, UnwindOutcome::Propagated);
+
+        let trace = table
+            .simulate_throw(0x24, Some(OTHER_TYPE), &FrameModel::new())
+            .unwrap();
+        assert!(matches!(
+            trace.steps[..],
+            [UnwindStep::Unexpected {
+                handler_pc: 0x300,
+                ..
+            }]
+        ));
+        assert_eq!(
+            trace.outcome,
+            UnwindOutcome::Unexpected { handler_pc: 0x300 }
+        );
+    }
+
+    #[test]
+    fn unknown_specification_types() {
+        //A null type without a relocation name doesn't allow anything
+        let text = r#"
+.flags
+.pc_range 0x0, 0x10, 0x10
+specification spec=[0], pc_offset=0x300, cinfo_ref=0x20, end
+"#;
+        let (table, names) = parse_text(text).unwrap();
+        let trace = table
+            .simulate_throw_matching(0x4, &FrameModel::new(), &names, |_| true)
+            .unwrap();
+        assert_eq!(
+            trace.outcome,
+            UnwindOutcome::Unexpected { handler_pc: 0x300 }
+        );
+    }
+
+    #[test]
+    fn propagates_outside_of_ranges() {
+        let trace = table()
+            .simulate_throw(0x34, None, &FrameModel::new())
+            .unwrap();
+        assert!(trace.pc_action.is_none());
+        assert!(trace.steps.is_empty());
+        assert_eq!(trace.outcome, UnwindOutcome::Propagated);
+    }
+}
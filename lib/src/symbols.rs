@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::{ExceptionTableData, Relocation};
+
+#[derive(Error, Debug)]
+pub enum SymbolMapError {
+    #[error("Line {0}: expected a symbol entry of the form name = section:0xADDRESS;")]
+    InvalidLine(usize),
+}
+
+/// Trait for looking up the names of the functions and types referenced by a table
+/// in a linked binary.
+pub trait SymbolResolver {
+    /// Returns the name of the symbol at the given address, if there is one.
+    fn resolve(&self, address: u32) -> Option<&str>;
+}
+
+impl SymbolResolver for HashMap<u32, String> {
+    fn resolve(&self, address: u32) -> Option<&str> {
+        self.get(&address).map(String::as_str)
+    }
+}
+
+impl SymbolResolver for BTreeMap<u32, String> {
+    fn resolve(&self, address: u32) -> Option<&str> {
+        self.get(&address).map(String::as_str)
+    }
+}
+
+/// Struct for the symbols of a linked binary, keyed by address.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    pub symbols: BTreeMap<u32, String>,
+}
+
+impl SymbolResolver for SymbolMap {
+    fn resolve(&self, address: u32) -> Option<&str> {
+        self.symbols.resolve(address)
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    let text = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn is_hex_value(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a symbol, unless the address already has one.
+    pub fn insert(&mut self, address: u32, name: &str) {
+        self.symbols
+            .entry(address)
+            .or_insert_with(|| String::from(name));
+    }
+
+    /// Reads the symbols of a map file written by the CodeWarrior linker.
+    ///
+    /// Symbols are read from the section layouts, with or without the file offset
+    /// column, and from the list of linker generated symbols. Section symbols (such as
+    /// `.text`) and unused symbols are skipped, and lines which aren't symbols are
+    /// ignored.
+    pub fn from_cw_map(text: &str) -> Self {
+        let mut map = Self::new();
+        let mut in_linker_symbols = false;
+
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if trimmed.ends_with("section layout") {
+                in_linker_symbols = false;
+                continue;
+            }
+            if trimmed.starts_with("Linker generated symbols") {
+                in_linker_symbols = true;
+                continue;
+            }
+
+            let tokens: Vec<&str> = trimmed.split_whitespace().collect();
+            if in_linker_symbols {
+                //Linker generated symbols are listed as the name followed by the address
+                if let [name, address] = tokens[..] {
+                    if let Some(address) = parse_hex(address) {
+                        map.insert(address, name);
+                    }
+                }
+                continue;
+            }
+
+            //Section layout lines: starting address, size, virtual address, then the
+            //file offset in newer maps, the alignment and the symbol name
+            if tokens.len() < 5 || !tokens[..3].iter().all(|token| is_hex_value(token)) {
+                continue;
+            }
+            let has_file_offset = tokens[3].len() == 8 && is_hex_value(tokens[3]);
+            let name_index = if has_file_offset { 5 } else { 4 };
+            let address = parse_hex(tokens[2]);
+            let name = tokens.get(name_index);
+            let (Some(address), Some(name)) = (address, name) else {
+                continue;
+            };
+            if !name.starts_with('.') {
+                map.insert(address, name);
+            }
+        }
+
+        map
+    }
+
+    /// Reads the symbols of a `symbols.txt` file written by decomp-toolkit, with lines
+    /// of the form `name = section:0xADDRESS; // type:function size:0x4C`.
+    ///
+    /// Labels are only used for addresses without any other symbol.
+    pub fn from_symbols_txt(text: &str) -> Result<Self, SymbolMapError> {
+        let mut map = Self::new();
+        let mut labels: Vec<(u32, String)> = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let (entry, attributes) = match line.split_once("//") {
+                Some((entry, attributes)) => (entry.trim(), attributes),
+                None => (line.trim(), ""),
+            };
+            if entry.is_empty() {
+                continue;
+            }
+
+            let invalid = || SymbolMapError::InvalidLine(line_num);
+            let (name, value) = entry.rsplit_once('=').ok_or_else(invalid)?;
+            let name = name.trim();
+            let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+                Some(quoted) => quoted,
+                None => name,
+            };
+            let value = value.trim().strip_suffix(';').ok_or_else(invalid)?;
+            //The section is left out for absolute symbols
+            let address = match value.rsplit_once(':') {
+                Some((_, address)) => address.trim(),
+                None => value.trim(),
+            };
+            let address = parse_hex(address).ok_or_else(invalid)?;
+            if name.is_empty() {
+                return Err(invalid());
+            }
+
+            if attributes
+                .split_whitespace()
+                .any(|attr| attr == "type:label")
+            {
+                labels.push((address, String::from(name)));
+            } else {
+                map.insert(address, name);
+            }
+        }
+
+        for (address, name) in labels {
+            map.insert(address, &name);
+        }
+
+        Ok(map)
+    }
+}
+
+impl ExceptionTableData {
+    /// Returns the addresses of every function and type referenced by the table (dtors,
+    /// catch types and specification types), along with their offset in the table.
+    pub fn symbol_references(&self) -> Vec<Relocation> {
+        self.exception_actions
+            .iter()
+            .flat_map(|action| action.reference_offsets())
+            .collect()
+    }
+
+    /// Names the functions and types referenced by the table using the given resolver,
+    /// returning the names keyed by their offset in the table, as used by the rendering
+    /// functions. References which the resolver has no symbol for are left out.
+    pub fn resolve_symbols(&self, resolver: &dyn SymbolResolver) -> HashMap<u32, String> {
+        self.symbol_references()
+            .into_iter()
+            .filter_map(|reference| {
+                let name = resolver.resolve(reference.address)?;
+                Some((reference.offset, String::from(name)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SymbolMap, SymbolMapError, SymbolResolver};
+
+    /// Excerpt of a map with file offsets, as written by newer linkers.
+    const CW_MAP: &str = "\
+Link map of __start
+ 1] __start (func,weak) found in os.a __start.c
+  2] __init_registers (func,local) found in os.a __start.c
+
+.init section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000310 80003100 00000100  1 .init \t__start.o 
+  00000000 00004c 80003100 00000100  4 __start \t__start.o 
+  UNUSED   000024 ........ ........    __check_pad3 __start.o 
+
+.text section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000098 800034a0 000005a0  1 .text \tmain.o 
+  00000000 00004c 800034a0 000005a0  4 main \tmain.o 
+  0000004c 00004c 800034ec 000005ec  4 __dt__3FooFv \tmain.o 
+
+.data section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000008 80005678 00002678  8 __RTTI__3Foo \tmain.o 
+
+Memory map:
+                   Starting Size     File
+                   address           Offset
+           .init   80003100 00000310 00000100
+           .text   800034a0 00000098 000005a0
+
+Linker generated symbols:
+                _stack_addr 803e0000
+              _db_stack_addr 803f0000
+";
+
+    /// Excerpt of a map without file offsets, as written by older linkers.
+    const OLD_CW_MAP: &str = "\
+.text section layout
+  Starting        Virtual
+  address  Size   address
+  -----------------------
+  00000000 000098 800034a0  1 .text \tmain.o 
+  00000000 00004c 800034a0  4 main \tmain.o 
+  0000004c 00004c 800034ec  4 __dt__3FooFv \tmain.o 
+";
+
+    const SYMBOLS_TXT: &str = "\
+__start = .init:0x80003100; // type:function size:0x4C scope:global
+main = .text:0x800034A0; // type:function size:0x4C
+lbl_800034A0 = .text:0x800034A0; // type:label scope:local
+lbl_800034D0 = .text:0x800034D0; // type:label scope:local
+
+\"__dt__Q23std3FooFv\" = .text:0x800034EC; // type:function size:0x4C
+_stack_addr = 0x803E0000; // type:object
+";
+
+    #[test]
+    fn cw_map() {
+        let map = SymbolMap::from_cw_map(CW_MAP);
+        assert_eq!(map.resolve(0x80003100), Some("__start"));
+        assert_eq!(map.resolve(0x800034A0), Some("main"));
+        assert_eq!(map.resolve(0x800034EC), Some("__dt__3FooFv"));
+        assert_eq!(map.resolve(0x80005678), Some("__RTTI__3Foo"));
+        assert_eq!(map.resolve(0x803E0000), Some("_stack_addr"));
+        assert_eq!(map.resolve(0x803F0000), Some("_db_stack_addr"));
+        //Section symbols, unused symbols and the memory map are skipped
+        assert_eq!(map.symbols.len(), 6);
+    }
+
+    #[test]
+    fn old_cw_map() {
+        let map = SymbolMap::from_cw_map(OLD_CW_MAP);
+        assert_eq!(map.symbols.len(), 2);
+        assert_eq!(map.resolve(0x800034A0), Some("main"));
+        assert_eq!(map.resolve(0x800034EC), Some("__dt__3FooFv"));
+    }
+
+    #[test]
+    fn symbols_txt() {
+        let map = SymbolMap::from_symbols_txt(SYMBOLS_TXT).unwrap();
+        assert_eq!(map.resolve(0x80003100), Some("__start"));
+        //Labels don't replace other symbols at the same address
+        assert_eq!(map.resolve(0x800034A0), Some("main"));
+        assert_eq!(map.resolve(0x800034D0), Some("lbl_800034D0"));
+        assert_eq!(map.resolve(0x800034EC), Some("__dt__Q23std3FooFv"));
+        assert_eq!(map.resolve(0x803E0000), Some("_stack_addr"));
+        assert_eq!(map.symbols.len(), 5);
+    }
+
+    #[test]
+    fn malformed_symbols_txt() {
+        let lines = [
+            "main = .text:0x800034A0 // type:function",
+            "main .text:0x800034A0; // type:function",
+            "main = .text:0x800034G0;",
+            " = .text:0x800034A0;",
+            "\"\" = .text:0x800034A0;",
+        ];
+        for line in lines {
+            let text = format!("__start = .init:0x80003100;\n{line}\n");
+            assert!(
+                matches!(
+                    SymbolMap::from_symbols_txt(&text),
+                    Err(SymbolMapError::InvalidLine(2))
+                ),
+                "{line}"
+            );
+        }
+    }
+}
@@ -0,0 +1,1042 @@
+//! Text format for exception tables, which can be edited by hand and parsed back.
+//!
+//! A table is written as a list of directives for the header and PC actions, followed
+//! by the actions themselves:
+//!
+//! ```text
+//! .flags has_frame_pointer, gpr_save_range=3
+//! .pc_range 0x80001000, 0x80001010, action_1C
+//! .pc_range 0x80001010, 0x80001020, none
+//!
+//! action_1C:
+//!     destroy_local local_offset=0x8, dtor_address="__dt__3FooFv"
+//!     branch target_offset=action_30, end
+//! ```
+//!
+//! - `.flags` takes the names of the set flag bits (`has_elf_vector`, `large_frame`,
+//!   `has_frame_pointer`, `saved_cr`), along with `fpr_save_range=N`, `gpr_save_range=N`
+//!   and `unknown_bits=N` for bits 0 and 2.
+//! - `.et_field` sets the et field, which is zero by default.
+//! - `.pc_range` takes the start and end PC of the range, and the action it runs, which
+//!   is either a label, an action offset or `none`.
+//! - Actions are written as the action type followed by the fields of the action data,
+//!   named as in `ExActionData`. `param=N` sets the action param, and `end` sets the end
+//!   bit. Unknown fields (`unk0`, `unk4`, `unk8`) can be left out when they are zero, and
+//!   the type count of specifications is taken from the length of the `spec` list.
+//! - Dtor addresses, catch types and specification types can be given as a quoted symbol
+//!   name, optionally followed by `@` and the address stored in the table.
+//! - Branch targets can be given as a label or an action offset.
+//! - Actions whose data doesn't match their type are written with a `bytes=[...]` list
+//!   holding the raw data.
+//!
+//! Numbers are either decimal or hex with a `0x` prefix, and comments start with `;`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use thiserror::Error;
+
+use crate::{
+    ExAction, ExceptionAction, ExceptionTableData, PCAction, HEADER_SIZE, PC_ACTION_SIZE,
+    UNKNOWN_FLAG_BITS,
+};
+
+#[derive(Error, Debug)]
+pub enum TextParseError {
+    #[error("Line {0}: {1}")]
+    Syntax(usize, String),
+    #[error("Line {0}: unknown directive {1}")]
+    UnknownDirective(usize, String),
+    #[error("Line {0}: unknown action type {1}")]
+    UnknownActionType(usize, String),
+    #[error("Line {0}: unknown field {1}")]
+    UnknownField(usize, String),
+    #[error("Line {0}: missing field {1}")]
+    MissingField(usize, String),
+    #[error("Line {0}: invalid value for {1}")]
+    InvalidValue(usize, String),
+    #[error("Line {0}: label {1} is defined more than once")]
+    DuplicateLabel(usize, String),
+    #[error("Line {0}: label {1} is not defined")]
+    UndefinedLabel(usize, String),
+    #[error("Line {0}: label {1} is not followed by an action")]
+    LabelWithoutAction(usize, String),
+}
+
+/// Names of the action types, indexed by their value.
+const ACTION_TYPE_NAMES: [&str; 17] = [
+    "end_of_list",
+    "branch",
+    "destroy_local",
+    "destroy_local_cond",
+    "destroy_local_pointer",
+    "destroy_local_array",
+    "destroy_base",
+    "destroy_member",
+    "destroy_member_cond",
+    "destroy_member_array",
+    "delete_pointer",
+    "delete_pointer_cond",
+    "catch_block",
+    "active_catch_block",
+    "terminate",
+    "specification",
+    "catch_block32",
+];
+
+/// Names of the flag bits in the `.flags` directive, along with their bit.
+const FLAG_NAMES: [(&str, u16); 4] = [
+    ("has_elf_vector", 1),
+    ("large_frame", 3),
+    ("has_frame_pointer", 4),
+    ("saved_cr", 5),
+];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FieldKind {
+    /// Plain number.
+    Value,
+    /// Number which is usually zero, and can be left out.
+    Unknown,
+    /// Function or type address, which can be given as a symbol name.
+    Address,
+    /// Action offset, which can be given as a label.
+    Target,
+    /// Type count of a specification, taken from the length of the type list.
+    Count,
+    /// Specification type list, following the fixed size fields.
+    AddressList,
+}
+
+/// Field of the action data, in the order they are stored in.
+struct Field {
+    name: &'static str,
+    size: u32,
+    kind: FieldKind,
+}
+
+/// Creates a `Field` as a constant expression, so the field lists can be borrowed statically.
+macro_rules! field {
+    ($name:literal, $size:literal, $kind:ident) => {
+        Field {
+            name: $name,
+            size: $size,
+            kind: FieldKind::$kind,
+        }
+    };
+}
+
+const DTOR_ADDRESS: Field = field!("dtor_address", 4, Address);
+
+/// Returns the fields of the action data for the given action type.
+fn action_fields(action_type: ExAction) -> &'static [Field] {
+    match action_type {
+        ExAction::EndOfList | ExAction::Terminate => &[],
+        ExAction::Branch => &[field!("target_offset", 2, Target)],
+        ExAction::DestroyLocal => &[field!("local_offset", 2, Value), DTOR_ADDRESS],
+        ExAction::DestroyLocalCond => &[
+            field!("condition", 2, Value),
+            field!("local_offset", 2, Value),
+            field!("unk4", 2, Unknown),
+            DTOR_ADDRESS,
+        ],
+        ExAction::DestroyLocalPointer => &[field!("local_pointer", 2, Value), DTOR_ADDRESS],
+        ExAction::DestroyLocalArray => &[
+            field!("local_array", 2, Value),
+            field!("elements", 2, Value),
+            field!("element_size", 2, Value),
+            DTOR_ADDRESS,
+        ],
+        ExAction::DestroyBase | ExAction::DestroyMember => &[
+            field!("object_pointer", 2, Value),
+            field!("member_offset", 4, Value),
+            DTOR_ADDRESS,
+        ],
+        ExAction::DestroyMemberCond => &[
+            field!("condition", 2, Value),
+            field!("object_pointer", 2, Value),
+            field!("member_offset", 4, Value),
+            field!("unk8", 2, Unknown),
+            DTOR_ADDRESS,
+        ],
+        ExAction::DestroyMemberArray => &[
+            field!("object_pointer", 2, Value),
+            field!("member_offset", 4, Value),
+            field!("elements", 4, Value),
+            field!("element_size", 4, Value),
+            DTOR_ADDRESS,
+        ],
+        ExAction::DeletePointer => &[field!("object_pointer", 2, Value), DTOR_ADDRESS],
+        ExAction::DeletePointerCond => &[
+            field!("condition", 2, Value),
+            field!("object_pointer", 2, Value),
+            field!("unk4", 2, Unknown),
+            DTOR_ADDRESS,
+        ],
+        ExAction::CatchBlock => &[
+            field!("unk0", 2, Unknown),
+            field!("catch_type", 4, Address),
+            field!("catch_pc_offset", 2, Value),
+            field!("cinfo_ref", 2, Value),
+        ],
+        ExAction::ActiveCatchBlock => &[field!("cinfo_ref", 2, Value)],
+        ExAction::Specification => &[
+            field!("specs", 2, Count),
+            field!("pc_offset", 4, Value),
+            field!("cinfo_ref", 4, Value),
+            field!("spec", 0, AddressList),
+        ],
+        ExAction::CatchBlock32 => &[
+            field!("unk0", 2, Unknown),
+            field!("catch_type", 4, Address),
+            field!("catch_pc_offset", 4, Value),
+            field!("cinfo_ref", 4, Value),
+        ],
+    }
+}
+
+fn action_type_name(action_type: ExAction) -> &'static str {
+    ACTION_TYPE_NAMES[action_type.to_int() as usize]
+}
+
+fn action_type_from_name(name: &str) -> Option<ExAction> {
+    let index = ACTION_TYPE_NAMES.iter().position(|&n| n == name)?;
+    ExAction::from_int(index as i32)
+}
+
+fn label_name(action_offset: u32) -> String {
+    format!("action_{action_offset:X}")
+}
+
+/// Writes a symbol name as a quoted string, escaping quotes and backslashes.
+fn write_symbol(out: &mut String, name: &str, address: u32) {
+    out.push('"');
+    for c in name.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    if address != 0 {
+        let _ = write!(out, "@{address:#X}");
+    }
+}
+
+fn read_field(bytes: &[u8], offset: usize, size: u32) -> u32 {
+    bytes[offset..offset + size as usize]
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as u32)
+}
+
+impl ExceptionTableData {
+    /// Converts the table into the text format described in the `text` module, which
+    /// `parse_text` reads back into the same table.
+    ///
+    /// Symbol names are taken from the given relocation names, which are keyed by their
+    /// offset in the table.
+    pub fn to_text(&self, relocation_names: &HashMap<u32, String>) -> String {
+        let mut out = String::new();
+
+        //Write the header
+        let mut flags: Vec<String> = FLAG_NAMES
+            .iter()
+            .filter(|(_, bit)| (self.flag_val >> bit) & 1 != 0)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        let fpr_save_range = (self.flag_val >> 6) & 0b11111;
+        let gpr_save_range = (self.flag_val >> 11) & 0b11111;
+        let unknown_bits = self.flag_val & UNKNOWN_FLAG_BITS;
+        if fpr_save_range != 0 {
+            flags.push(format!("fpr_save_range={fpr_save_range}"));
+        }
+        if gpr_save_range != 0 {
+            flags.push(format!("gpr_save_range={gpr_save_range}"));
+        }
+        if unknown_bits != 0 {
+            flags.push(format!("unknown_bits={unknown_bits:#X}"));
+        }
+        if flags.is_empty() {
+            out += ".flags\n";
+        } else {
+            let _ = writeln!(out, ".flags {}", flags.join(", "));
+        }
+        if self.et_field != 0 {
+            let _ = writeln!(out, ".et_field {:#X}", self.et_field);
+        }
+
+        //Labels are only written for actions which are referenced
+        let mut labelled: Vec<u32> = vec![];
+        let mut target = |offset: u32| -> String {
+            if offset != 0 && self.action_index_at_offset(offset).is_some() {
+                labelled.push(offset);
+                label_name(offset)
+            } else if offset == 0 {
+                String::from("none")
+            } else {
+                format!("{offset:#X}")
+            }
+        };
+
+        for pcaction in &self.pc_actions {
+            let _ = writeln!(
+                out,
+                ".pc_range {:#X}, {:#X}, {}",
+                pcaction.start_pc,
+                pcaction.end_pc,
+                target(pcaction.action_offset)
+            );
+        }
+
+        //Write the actions
+        let mut lines: Vec<(u32, String)> = vec![];
+        for action in &self.exception_actions {
+            let mut line = String::from(action_type_name(action.action_type));
+            let mut operands: Vec<String> = vec![];
+            if action.action_param != 0 {
+                operands.push(format!("param={:#X}", action.action_param));
+            }
+
+            let fields = action_fields(action.action_type);
+            let data_offset = action.action_offset + 2;
+            let mut offset: usize = 0;
+            let mut count: u32 = 0;
+
+            //Check that the data matches the type first, so the fields can be read directly
+            let expected_size = action
+                .try_get_exaction_data()
+                .ok()
+                .map(|data| data.data_size());
+            if expected_size == Some(action.bytes.len() as u32) {
+                for field in fields {
+                    let field_offset = offset;
+                    offset += field.size as usize;
+
+                    if let FieldKind::AddressList = field.kind {
+                        let mut entries: Vec<String> = vec![];
+                        for i in 0..count {
+                            let entry_offset = field_offset + (i as usize) * 4;
+                            let value = read_field(&action.bytes, entry_offset, 4);
+                            let mut entry = String::new();
+                            match relocation_names.get(&(data_offset + entry_offset as u32)) {
+                                Some(name) => write_symbol(&mut entry, name, value),
+                                None => entry += &format!("{value:#X}"),
+                            }
+                            entries.push(entry);
+                        }
+                        operands.push(format!("{}=[{}]", field.name, entries.join(", ")));
+                        continue;
+                    }
+
+                    let value = read_field(&action.bytes, field_offset, field.size);
+                    let mut operand = format!("{}=", field.name);
+                    match field.kind {
+                        FieldKind::Count => {
+                            //The count is given by the length of the list
+                            count = value;
+                            continue;
+                        }
+                        FieldKind::Unknown if value == 0 => continue,
+                        FieldKind::Address => {
+                            match relocation_names.get(&(data_offset + field_offset as u32)) {
+                                Some(name) => write_symbol(&mut operand, name, value),
+                                None => operand += &format!("{value:#X}"),
+                            }
+                        }
+                        FieldKind::Target => operand += &target(value),
+                        _ => operand += &format!("{value:#X}"),
+                    }
+                    operands.push(operand);
+                }
+            } else {
+                let bytes: Vec<String> = action
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{byte:#04X}"))
+                    .collect();
+                operands.push(format!("bytes=[{}]", bytes.join(", ")));
+            }
+
+            if action.has_end_bit {
+                operands.push(String::from("end"));
+            }
+            if !operands.is_empty() {
+                line.push(' ');
+                line += &operands.join(", ");
+            }
+            lines.push((action.action_offset, line));
+        }
+
+        if !lines.is_empty() {
+            out += "\n";
+        }
+        for (action_offset, line) in lines {
+            if labelled.contains(&action_offset) {
+                let _ = writeln!(out, "{}:", label_name(action_offset));
+            }
+            let _ = writeln!(out, "    {line}");
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(u32),
+    Str(String),
+    Colon,
+    Comma,
+    Equals,
+    At,
+    LBracket,
+    RBracket,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn tokenize(line: &str, line_num: usize) -> Result<Vec<Token>, TextParseError> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ';' => break, //The rest of the line is a comment
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ':' | ',' | '=' | '@' | '[' | ']' => {
+                chars.next();
+                tokens.push(match c {
+                    ':' => Token::Colon,
+                    ',' => Token::Comma,
+                    '=' => Token::Equals,
+                    '@' => Token::At,
+                    '[' => Token::LBracket,
+                    _ => Token::RBracket,
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => text.push(escaped),
+                            None => break,
+                        },
+                        Some(c) => text.push(c),
+                        None => {
+                            return Err(TextParseError::Syntax(
+                                line_num,
+                                String::from("unterminated string"),
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            c if is_ident_char(c) => {
+                let mut text = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !is_ident_char(c) {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                if text.starts_with(|c: char| c.is_ascii_digit()) {
+                    let value = parse_number(&text).ok_or_else(|| {
+                        TextParseError::Syntax(line_num, format!("invalid number {text}"))
+                    })?;
+                    tokens.push(Token::Number(value));
+                } else {
+                    tokens.push(Token::Ident(text));
+                }
+            }
+            _ => {
+                return Err(TextParseError::Syntax(
+                    line_num,
+                    format!("unexpected character '{c}'"),
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(u32),
+    Ident(String),
+    Symbol(String, u32),
+    List(Vec<Value>),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Named(String, Value),
+    Positional(Value),
+}
+
+struct LineParser {
+    tokens: Peekable<IntoIter<Token>>,
+    line_num: usize,
+}
+
+impl LineParser {
+    fn syntax_error(&self, message: &str) -> TextParseError {
+        TextParseError::Syntax(self.line_num, String::from(message))
+    }
+
+    fn parse_value(&mut self) -> Result<Value, TextParseError> {
+        match self.tokens.next() {
+            Some(Token::Number(value)) => Ok(Value::Number(value)),
+            Some(Token::Ident(name)) => Ok(Value::Ident(name)),
+            Some(Token::Str(name)) => {
+                if self.tokens.next_if_eq(&Token::At).is_none() {
+                    return Ok(Value::Symbol(name, 0));
+                }
+                match self.tokens.next() {
+                    Some(Token::Number(address)) => Ok(Value::Symbol(name, address)),
+                    _ => Err(self.syntax_error("expected an address after '@'")),
+                }
+            }
+            Some(Token::LBracket) => {
+                let mut values: Vec<Value> = vec![];
+                if self.tokens.next_if_eq(&Token::RBracket).is_some() {
+                    return Ok(Value::List(values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    match self.tokens.next() {
+                        Some(Token::Comma) => {}
+                        Some(Token::RBracket) => break,
+                        _ => return Err(self.syntax_error("expected ',' or ']'")),
+                    }
+                }
+                Ok(Value::List(values))
+            }
+            _ => Err(self.syntax_error("expected a value")),
+        }
+    }
+
+    fn parse_operands(&mut self) -> Result<Vec<Operand>, TextParseError> {
+        let mut operands: Vec<Operand> = vec![];
+        if self.tokens.peek().is_none() {
+            return Ok(operands);
+        }
+
+        loop {
+            let value = self.parse_value()?;
+            let operand = match value {
+                Value::Ident(name) if self.tokens.next_if_eq(&Token::Equals).is_some() => {
+                    Operand::Named(name, self.parse_value()?)
+                }
+                value => Operand::Positional(value),
+            };
+            operands.push(operand);
+
+            match self.tokens.next() {
+                Some(Token::Comma) => {}
+                None => break,
+                _ => return Err(self.syntax_error("expected ','")),
+            }
+        }
+
+        Ok(operands)
+    }
+}
+
+/// Action read from the text, before its labels are resolved.
+struct ParsedAction {
+    line_num: usize,
+    action_type: ExAction,
+    operands: Vec<Operand>,
+    action_offset: u32,
+}
+
+impl ParsedAction {
+    /// Returns the size of the action data, which is needed to lay out the actions before
+    /// resolving labels.
+    fn data_size(&self) -> u32 {
+        let mut size = self.action_type.base_data_size();
+        for operand in &self.operands {
+            match operand {
+                Operand::Named(name, Value::List(values)) if name == "bytes" => {
+                    return values.len() as u32;
+                }
+                Operand::Named(name, Value::List(values))
+                    if name == "spec" && matches!(self.action_type, ExAction::Specification) =>
+                {
+                    size = self.action_type.base_data_size() + (values.len() as u32) * 4;
+                }
+                _ => {}
+            }
+        }
+        size
+    }
+}
+
+/// Resolves the values of parsed actions and PC actions into numbers.
+struct Resolver<'a> {
+    labels: &'a HashMap<String, u32>,
+    relocation_names: HashMap<u32, String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn number(
+        &self,
+        value: &Value,
+        line_num: usize,
+        name: &str,
+        size: u32,
+    ) -> Result<u32, TextParseError> {
+        match value {
+            Value::Number(number) if size == 4 || *number >> (size * 8) == 0 => Ok(*number),
+            _ => Err(TextParseError::InvalidValue(line_num, String::from(name))),
+        }
+    }
+
+    fn target(&self, value: &Value, line_num: usize, name: &str) -> Result<u32, TextParseError> {
+        match value {
+            Value::Ident(label) if label == "none" => Ok(0),
+            Value::Ident(label) => match self.labels.get(label) {
+                Some(&offset) => Ok(offset),
+                None => Err(TextParseError::UndefinedLabel(line_num, label.clone())),
+            },
+            _ => self.number(value, line_num, name, 4),
+        }
+    }
+
+    /// Resolves an address, recording the symbol name at the given table offset if any.
+    fn address(
+        &mut self,
+        value: &Value,
+        line_num: usize,
+        name: &str,
+        offset: u32,
+    ) -> Result<u32, TextParseError> {
+        match value {
+            Value::Symbol(symbol, address) => {
+                self.relocation_names.insert(offset, symbol.clone());
+                Ok(*address)
+            }
+            _ => self.number(value, line_num, name, 4),
+        }
+    }
+
+    fn action(&mut self, parsed: &ParsedAction) -> Result<ExceptionAction, TextParseError> {
+        let line_num = parsed.line_num;
+        let mut exaction = ExceptionAction::new();
+        exaction.action_offset = parsed.action_offset;
+        exaction.action_type = parsed.action_type;
+
+        let mut values: HashMap<&str, &Value> = HashMap::new();
+        for operand in &parsed.operands {
+            match operand {
+                Operand::Positional(Value::Ident(name)) if name == "end" => {
+                    exaction.has_end_bit = true;
+                }
+                Operand::Named(name, value) => {
+                    if values.insert(name, value).is_some() {
+                        return Err(TextParseError::Syntax(
+                            line_num,
+                            format!("field {name} is given more than once"),
+                        ));
+                    }
+                }
+                Operand::Positional(_) => {
+                    return Err(TextParseError::Syntax(
+                        line_num,
+                        String::from("expected a named field"),
+                    ))
+                }
+            }
+        }
+
+        if let Some(value) = values.remove("param") {
+            exaction.action_param = self.number(value, line_num, "param", 1)? as u8;
+        }
+
+        //Actions with raw data don't have any other fields
+        if let Some(value) = values.remove("bytes") {
+            let Value::List(list) = value else {
+                return Err(TextParseError::InvalidValue(
+                    line_num,
+                    String::from("bytes"),
+                ));
+            };
+            for byte in list {
+                exaction
+                    .bytes
+                    .push(self.number(byte, line_num, "bytes", 1)? as u8);
+            }
+        } else {
+            let data_offset = parsed.action_offset + 2;
+            let mut spec: &[Value] = &[];
+            if let ExAction::Specification = parsed.action_type {
+                match values.remove("spec") {
+                    Some(Value::List(list)) if list.len() <= 0xFFFF => spec = list,
+                    Some(_) => {
+                        return Err(TextParseError::InvalidValue(line_num, String::from("spec")))
+                    }
+                    None => {}
+                }
+            }
+
+            for field in action_fields(parsed.action_type) {
+                let offset = data_offset + exaction.bytes.len() as u32;
+                let value = match field.kind {
+                    FieldKind::Count => spec.len() as u32,
+                    FieldKind::AddressList => {
+                        for entry in spec {
+                            let offset = data_offset + exaction.bytes.len() as u32;
+                            let address = self.address(entry, line_num, field.name, offset)?;
+                            exaction.bytes.extend_from_slice(&address.to_be_bytes());
+                        }
+                        continue;
+                    }
+                    kind => match values.remove(field.name) {
+                        Some(value) => match kind {
+                            FieldKind::Address => {
+                                self.address(value, line_num, field.name, offset)?
+                            }
+                            FieldKind::Target => {
+                                let target = self.target(value, line_num, field.name)?;
+                                if target > 0xFFFF {
+                                    return Err(TextParseError::InvalidValue(
+                                        line_num,
+                                        String::from(field.name),
+                                    ));
+                                }
+                                target
+                            }
+                            _ => self.number(value, line_num, field.name, field.size)?,
+                        },
+                        None if kind == FieldKind::Unknown => 0,
+                        None => {
+                            return Err(TextParseError::MissingField(
+                                line_num,
+                                String::from(field.name),
+                            ))
+                        }
+                    },
+                };
+                let bytes = value.to_be_bytes();
+                exaction
+                    .bytes
+                    .extend_from_slice(&bytes[(4 - field.size as usize)..]);
+            }
+        }
+
+        //Every field should have been used by now
+        if let Some(name) = values.keys().next() {
+            return Err(TextParseError::UnknownField(line_num, String::from(*name)));
+        }
+
+        Ok(exaction)
+    }
+}
+
+/// Parses a table in the text format described in the `text` module, returning the table
+/// and the symbol names it references, keyed by their offset in the table.
+pub fn parse_text(
+    text: &str,
+) -> Result<(ExceptionTableData, HashMap<u32, String>), TextParseError> {
+    let mut extab_data = ExceptionTableData::new();
+    let mut pc_ranges: Vec<(usize, Vec<Operand>)> = vec![];
+    let mut actions: Vec<ParsedAction> = vec![];
+    let mut pending_labels: Vec<(usize, String)> = vec![];
+    let mut label_indices: HashMap<String, usize> = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let tokens = tokenize(line, line_num)?;
+        let mut parser = LineParser {
+            tokens: tokens.into_iter().peekable(),
+            line_num,
+        };
+
+        let name = match parser.tokens.next() {
+            Some(Token::Ident(name)) => name,
+            None => continue,
+            _ => return Err(parser.syntax_error("expected a directive, label or action")),
+        };
+
+        //Check for a label, which may be followed by a directive or action on the same line
+        let name = if parser.tokens.next_if_eq(&Token::Colon).is_some() {
+            if label_indices.contains_key(&name) || pending_labels.iter().any(|(_, l)| *l == name) {
+                return Err(TextParseError::DuplicateLabel(line_num, name));
+            }
+            pending_labels.push((line_num, name));
+            match parser.tokens.next() {
+                Some(Token::Ident(name)) => name,
+                None => continue,
+                _ => return Err(parser.syntax_error("expected a directive or action")),
+            }
+        } else {
+            name
+        };
+
+        let operands = parser.parse_operands()?;
+
+        if name.starts_with('.') {
+            match name.as_str() {
+                ".flags" => parse_flags(&mut extab_data, &operands, line_num)?,
+                ".et_field" => {
+                    extab_data.et_field = match operands.as_slice() {
+                        [Operand::Positional(Value::Number(value))] if *value <= 0xFFFF => {
+                            *value as u16
+                        }
+                        _ => {
+                            return Err(TextParseError::InvalidValue(
+                                line_num,
+                                String::from(".et_field"),
+                            ))
+                        }
+                    };
+                }
+                ".pc_range" => pc_ranges.push((line_num, operands)),
+                _ => return Err(TextParseError::UnknownDirective(line_num, name)),
+            }
+            continue;
+        }
+
+        let action_type = match action_type_from_name(&name) {
+            Some(action_type) => action_type,
+            None => return Err(TextParseError::UnknownActionType(line_num, name)),
+        };
+        for (_, label) in pending_labels.drain(..) {
+            label_indices.insert(label, actions.len());
+        }
+        actions.push(ParsedAction {
+            line_num,
+            action_type,
+            operands,
+            action_offset: 0,
+        });
+    }
+
+    if let Some((line_num, label)) = pending_labels.pop() {
+        return Err(TextParseError::LabelWithoutAction(line_num, label));
+    }
+
+    //Lay out the actions after the range entries and the terminator
+    let mut offset = HEADER_SIZE + (pc_ranges.len() as u32) * PC_ACTION_SIZE + 4;
+    for action in &mut actions {
+        action.action_offset = offset;
+        offset += 2 + action.data_size();
+    }
+    let labels: HashMap<String, u32> = label_indices
+        .into_iter()
+        .map(|(label, index)| (label, actions[index].action_offset))
+        .collect();
+
+    let mut resolver = Resolver {
+        labels: &labels,
+        relocation_names: HashMap::new(),
+    };
+
+    for (line_num, operands) in &pc_ranges {
+        let line_num = *line_num;
+        let (start, end, target) = match operands.as_slice() {
+            [Operand::Positional(start), Operand::Positional(end), Operand::Positional(target)] => {
+                (start, end, target)
+            }
+            _ => {
+                return Err(TextParseError::Syntax(
+                    line_num,
+                    String::from("expected a start PC, end PC and action"),
+                ))
+            }
+        };
+        let mut pcaction = PCAction::new();
+        pcaction.start_pc = resolver.number(start, line_num, "start PC", 4)?;
+        pcaction.end_pc = resolver.number(end, line_num, "end PC", 4)?;
+        pcaction.action_offset = resolver.target(target, line_num, "action")?;
+        extab_data.pc_actions.push(pcaction);
+    }
+
+    for action in &actions {
+        let exaction = resolver.action(action)?;
+
+        //Collect the dtor relocations, like when decoding a table
+        if exaction.has_dtor_ref() {
+            extab_data.relocations.extend(exaction.reference_offsets());
+        }
+        extab_data.exception_actions.push(exaction);
+    }
+
+    Ok((extab_data, resolver.relocation_names))
+}
+
+fn parse_flags(
+    extab_data: &mut ExceptionTableData,
+    operands: &[Operand],
+    line_num: usize,
+) -> Result<(), TextParseError> {
+    let mut flag_val: u16 = 0;
+
+    for operand in operands {
+        match operand {
+            Operand::Positional(Value::Ident(name)) => {
+                match FLAG_NAMES.iter().find(|(flag, _)| flag == name) {
+                    Some((_, bit)) => flag_val |= 1 << bit,
+                    None => return Err(TextParseError::UnknownField(line_num, name.clone())),
+                }
+            }
+            Operand::Named(name, Value::Number(value)) => {
+                let (shift, mask) = match name.as_str() {
+                    "fpr_save_range" => (6, 0b11111),
+                    "gpr_save_range" => (11, 0b11111),
+                    "unknown_bits" => (0, UNKNOWN_FLAG_BITS as u32),
+                    _ => return Err(TextParseError::UnknownField(line_num, name.clone())),
+                };
+                if value & !mask != 0 {
+                    return Err(TextParseError::InvalidValue(line_num, name.clone()));
+                }
+                flag_val |= (*value as u16) << shift;
+            }
+            _ => {
+                return Err(TextParseError::Syntax(
+                    line_num,
+                    String::from("expected a flag name or value"),
+                ))
+            }
+        }
+    }
+
+    extab_data.flag_val = flag_val;
+    extab_data.calculate_flag_values();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::parse_text;
+    use crate::fixtures::{DESTROY_LOCALS_TABLE, DESTROY_LOCAL_TABLE, EMPTY_TABLE};
+    use crate::{decode_extab, encode_extab, ExAction, ExtabEncodeError};
+
+    const TEXT: &str = r#"
+.flags has_frame_pointer, gpr_save_range=2
+.pc_range 0x10, 0x30, cleanup
+.pc_range 0x30, 0x40, handler ; Only catches
+
+cleanup:
+    destroy_local local_offset=0x8, dtor_address="__dt__3FooFv"
+    branch target_offset=handler
+    destroy_local local_offset=12, dtor_address="__dt__3BarFv"@0x80001234, end
+handler: catch_block catch_type="Baz"@0x80005678, catch_pc_offset=0x20, cinfo_ref=0x10, end
+"#;
+
+    #[rustfmt::skip]
+    const TABLE: [u8; 0x38] = [
+        0x10, 0x10, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x08, 0x00, 0x18, //PC range
+        0x00, 0x00, 0x00, 0x30, 0x00, 0x04, 0x00, 0x2C, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x02, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, //Destroy local
+        0x01, 0x00, 0x00, 0x2C, //Branch
+        0x82, 0x00, 0x00, 0x0C, 0x80, 0x00, 0x12, 0x34, //Destroy local
+        0x8C, 0x00, 0x00, 0x00, 0x80, 0x00, 0x56, 0x78, //Catch block, catch type
+        0x00, 0x20, 0x00, 0x10, //Handler PC, catch info
+    ];
+
+    fn table_names() -> HashMap<u32, String> {
+        [
+            (0x1C, "__dt__3FooFv"),
+            (0x28, "__dt__3BarFv"),
+            (0x30, "Baz"),
+        ]
+        .into_iter()
+        .map(|(offset, name)| (offset, String::from(name)))
+        .collect()
+    }
+
+    #[test]
+    fn parse_to_bytes() {
+        let (table, names) = parse_text(TEXT).unwrap();
+        assert_eq!(encode_extab(&table).unwrap(), TABLE);
+        assert_eq!(names, table_names());
+
+        let decoded = decode_extab(&TABLE).unwrap();
+        assert!(decoded.has_frame_pointer);
+        assert_eq!(decoded.gpr_save_range, 2);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let table = decode_extab(&TABLE).unwrap();
+        let text = table.to_text(&table_names());
+        assert!(text.contains("branch target_offset=action_2C"));
+        assert!(text.contains(r#"dtor_address="__dt__3BarFv"@0x80001234"#));
+
+        let (parsed, names) = parse_text(&text).unwrap();
+        assert_eq!(encode_extab(&parsed).unwrap(), TABLE);
+        assert_eq!(names, table_names());
+    }
+
+    #[test]
+    fn fixture_round_trip() {
+        for bytes in [
+            &EMPTY_TABLE[..],
+            &DESTROY_LOCAL_TABLE,
+            &DESTROY_LOCALS_TABLE,
+        ] {
+            let table = decode_extab(bytes).unwrap();
+            let (parsed, names) = parse_text(&table.to_text(&HashMap::new())).unwrap();
+            assert!(names.is_empty());
+            assert_eq!(encode_extab(&parsed).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn raw_bytes_round_trip() {
+        //Action data which doesn't match its type is written as raw bytes
+        let mut table = decode_extab(&TABLE).unwrap();
+        table.exception_actions[3].bytes.truncate(4);
+
+        let text = table.to_text(&HashMap::new());
+        assert!(text.contains("catch_block bytes=[0x00, 0x00, 0x80, 0x00], end"));
+
+        let (parsed, names) = parse_text(&text).unwrap();
+        assert!(names.is_empty());
+        assert_eq!(parsed.to_text(&HashMap::new()), text);
+
+        //Such data can be shown and parsed, but not encoded
+        assert!(matches!(
+            encode_extab(&parsed),
+            Err(ExtabEncodeError::InvalidActionData(
+                0x2C,
+                4,
+                ExAction::CatchBlock
+            ))
+        ));
+    }
+
+    #[test]
+    fn undefined_labels() {
+        let text = TEXT.replace("target_offset=handler", "target_offset=missing");
+        assert!(matches!(
+            parse_text(&text),
+            Err(super::TextParseError::UndefinedLabel(8, label)) if label == "missing"
+        ));
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::dol::Dol;
+use crate::scope::demangle_class_name;
+use crate::{
+    ActionChainError, ActionOperands, ExActionData, ExceptionTableData, PCAction, Relocation,
+};
+
+//Longest type name read from a binary
+const MAX_TYPE_NAME_LEN: u32 = 0x400;
+
+#[derive(Error, Debug)]
+pub enum TypeInfoError {
+    #[error("Type info at {0:#010X} is outside of the binary")]
+    UnmappedTypeInfo(u32),
+    #[error("Name of the type info at {0:#010X} is not a valid string")]
+    InvalidName(u32),
+    #[error("Base class list of the type info at {0:#010X} is outside of the binary")]
+    UnmappedBaseList(u32),
+}
+
+/// Trait for reading the memory of a linked binary by address.
+pub trait MemoryReader {
+    /// Returns the given number of bytes at the address, if they are all mapped.
+    fn read(&self, address: u32, size: u32) -> Option<&[u8]>;
+}
+
+impl MemoryReader for Dol<'_> {
+    fn read(&self, address: u32, size: u32) -> Option<&[u8]> {
+        Dol::read(self, address, size)
+    }
+}
+
+/// Base class of a type, along with the offset of the base in an object of the type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeBase {
+    pub type_address: u32,
+    pub offset: u32,
+}
+
+/// Type info of a class, as referenced by catch clauses and throw specifications.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeInfo {
+    pub name: String,
+    /// Direct base classes of the type.
+    pub bases: Vec<TypeBase>,
+}
+
+impl TypeInfo {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            bases: vec![],
+        }
+    }
+}
+
+/// Struct for the type infos of a binary, keyed by address.
+#[derive(Debug, Clone, Default)]
+pub struct TypeHierarchy {
+    pub types: BTreeMap<u32, TypeInfo>,
+}
+
+fn read_u32(reader: &dyn MemoryReader, address: u32) -> Option<u32> {
+    let bytes = reader.read(address, 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_string(reader: &dyn MemoryReader, address: u32) -> Option<String> {
+    let mut bytes: Vec<u8> = vec![];
+    for i in 0..MAX_TYPE_NAME_LEN {
+        match reader.read(address.checked_add(i)?, 1)?[0] {
+            0 => return String::from_utf8(bytes).ok(),
+            byte => bytes.push(byte),
+        }
+    }
+    None
+}
+
+impl TypeHierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a type, replacing any type already at the address.
+    pub fn insert(&mut self, address: u32, type_info: TypeInfo) {
+        self.types.insert(address, type_info);
+    }
+
+    /// Returns the address of the type with the given name.
+    pub fn find_by_name(&self, name: &str) -> Option<u32> {
+        self.types
+            .iter()
+            .find(|(_, type_info)| type_info.name == name)
+            .map(|(address, _)| *address)
+    }
+
+    /// Returns the offset of the base class in objects of the derived type, if the
+    /// derived type is the base type or derives from it. Bases which aren't in the
+    /// hierarchy are only matched by address.
+    pub fn base_offset(&self, derived: u32, base: u32) -> Option<u32> {
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut pending: Vec<(u32, u32)> = vec![(derived, 0)];
+
+        while let Some((address, offset)) = pending.pop() {
+            if address == base {
+                return Some(offset);
+            }
+            //The bases of a type can be reached more than once through multiple inheritance
+            if !visited.insert(address) {
+                continue;
+            }
+            if let Some(type_info) = self.types.get(&address) {
+                for type_base in type_info.bases.iter().rev() {
+                    pending.push((
+                        type_base.type_address,
+                        offset.wrapping_add(type_base.offset),
+                    ));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns whether a catch clause or specification with the given type allows an
+    /// exception of the thrown type.
+    ///
+    /// Null types aren't matched here, as they are only `catch (...)` in linked tables,
+    /// see `ExceptionTableData::select_handler`.
+    pub fn catches(&self, thrown_type: u32, catch_type: u32) -> bool {
+        self.base_offset(thrown_type, catch_type).is_some()
+    }
+
+    /// Returns the address of the type referenced by a catch clause or specification.
+    /// Null types are looked up by their relocation name, either as a type name or as
+    /// the name of its `__RTTI__` record.
+    pub fn resolve_reference(
+        &self,
+        reference: &Relocation,
+        relocation_names: &HashMap<u32, String>,
+    ) -> Option<u32> {
+        if reference.address != 0 {
+            return Some(reference.address);
+        }
+        let name = relocation_names.get(&reference.offset)?;
+        self.find_by_name(name).or_else(|| {
+            let class_name = demangle_class_name(name)?;
+            self.find_by_name(&class_name)
+        })
+    }
+
+    /// Reads the type info at the given address from a binary, along with the type
+    /// infos of its bases.
+    ///
+    /// Type infos are the `__RTTI__` records written by CodeWarrior: a pointer to the
+    /// type name, followed by a pointer to the list of base classes, which is null for
+    /// classes without bases. Each entry of the list is a pointer to the base's type
+    /// info and the offset of the base, and the list ends with a null pointer.
+    pub fn read_type(
+        &mut self,
+        reader: &dyn MemoryReader,
+        address: u32,
+    ) -> Result<(), TypeInfoError> {
+        let mut pending: Vec<u32> = vec![address];
+
+        while let Some(address) = pending.pop() {
+            if self.types.contains_key(&address) {
+                continue;
+            }
+
+            let name_address =
+                read_u32(reader, address).ok_or(TypeInfoError::UnmappedTypeInfo(address))?;
+            let base_list = read_u32(reader, address.wrapping_add(4))
+                .ok_or(TypeInfoError::UnmappedTypeInfo(address))?;
+            let name =
+                read_string(reader, name_address).ok_or(TypeInfoError::InvalidName(address))?;
+
+            let mut bases: Vec<TypeBase> = vec![];
+            if base_list != 0 {
+                let mut entry = base_list;
+                loop {
+                    let type_address =
+                        read_u32(reader, entry).ok_or(TypeInfoError::UnmappedBaseList(address))?;
+                    if type_address == 0 {
+                        break;
+                    }
+                    let offset = read_u32(reader, entry.wrapping_add(4))
+                        .ok_or(TypeInfoError::UnmappedBaseList(address))?;
+                    bases.push(TypeBase {
+                        type_address,
+                        offset,
+                    });
+                    pending.push(type_address);
+                    entry = entry.wrapping_add(8);
+                }
+            }
+
+            self.types.insert(address, TypeInfo { name, bases });
+        }
+
+        Ok(())
+    }
+
+    /// Reads the type infos of every catch and specification type referenced by the
+    /// given table from a binary.
+    pub fn read_table_types(
+        &mut self,
+        reader: &dyn MemoryReader,
+        table: &ExceptionTableData,
+    ) -> Result<(), TypeInfoError> {
+        for action in &table.exception_actions {
+            let types = match action.try_get_exaction_data() {
+                Ok(ExActionData::CatchBlock { catch_type, .. })
+                | Ok(ExActionData::CatchBlock32 { catch_type, .. }) => vec![catch_type],
+                Ok(ExActionData::Specification { spec, .. }) => spec,
+                _ => continue,
+            };
+            for address in types.into_iter().filter(|address| *address != 0) {
+                self.read_type(reader, address)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handler selected by the runtime for an exception thrown in a PC range.
+#[derive(Debug, Clone)]
+pub enum HandlerSelection {
+    /// Caught by the catch clause of the given action. The base offset is the offset
+    /// of the caught type in the thrown object.
+    Catch {
+        action_offset: u32,
+        catch_type: Relocation,
+        handler_pc: u32,
+        base_offset: u32,
+    },
+    /// Not allowed by the throw specification of the given action, so `unexpected` is
+    /// called from its handler.
+    Unexpected { action_offset: u32, handler_pc: u32 },
+    /// `terminate` is called by the given action.
+    Terminate { action_offset: u32 },
+    /// Not handled in this function.
+    Propagate,
+}
+
+/// Handler selected for a PC range.
+#[derive(Debug, Clone)]
+pub struct HandlerMatch {
+    pub pc_action: PCAction,
+    pub selection: HandlerSelection,
+}
+
+impl ExceptionTableData {
+    /// Returns the handler the runtime selects for an exception of the thrown type
+    /// thrown in the given PC range. Catch clauses match types which are the thrown
+    /// type or one of its bases in the hierarchy.
+    ///
+    /// Types which aren't relocated are matched by the given relocation names, keyed by
+    /// their offset in the table, see `TypeHierarchy::resolve_reference`. Null catch
+    /// types without a name are `catch (...)`.
+    pub fn select_handler(
+        &self,
+        pc_action: &PCAction,
+        thrown_type: u32,
+        types: &TypeHierarchy,
+        relocation_names: &HashMap<u32, String>,
+    ) -> Result<HandlerSelection, ActionChainError> {
+        let chain = self.pc_action_chain(pc_action)?;
+        let resolve = |reference: &Relocation| types.resolve_reference(reference, relocation_names);
+        let matches = |reference: &Relocation| {
+            resolve(reference).map_or(false, |address| types.catches(thrown_type, address))
+        };
+        let Some(index) = self.find_unwind_end(&chain, relocation_names, matches)? else {
+            return Ok(HandlerSelection::Propagate);
+        };
+
+        let action = chain[index];
+        let action_offset = action.action_offset;
+        let catch = |handler_pc: u32| {
+            let Some(catch_type) = action.reference_offsets().pop() else {
+                return HandlerSelection::Terminate { action_offset };
+            };
+            let base_offset = resolve(&catch_type)
+                .and_then(|address| types.base_offset(thrown_type, address))
+                .unwrap_or(0);
+            HandlerSelection::Catch {
+                action_offset,
+                catch_type,
+                handler_pc,
+                base_offset,
+            }
+        };
+        let selection = match self.chain_operands(action)? {
+            ActionOperands::CatchBlock {
+                catch_pc_offset, ..
+            } => catch(catch_pc_offset as u32),
+            ActionOperands::CatchBlock32 {
+                catch_pc_offset, ..
+            } => catch(catch_pc_offset),
+            ActionOperands::Specification { pc_offset, .. } => HandlerSelection::Unexpected {
+                action_offset,
+                handler_pc: pc_offset,
+            },
+            _ => HandlerSelection::Terminate { action_offset },
+        };
+        Ok(selection)
+    }
+
+    /// Returns the handler the runtime selects for an exception of the thrown type in
+    /// each PC range of the table.
+    pub fn select_handlers(
+        &self,
+        thrown_type: u32,
+        types: &TypeHierarchy,
+        relocation_names: &HashMap<u32, String>,
+    ) -> Result<Vec<HandlerMatch>, ActionChainError> {
+        self.pc_actions
+            .iter()
+            .map(|pc_action| {
+                Ok(HandlerMatch {
+                    pc_action: pc_action.clone(),
+                    selection: self.select_handler(
+                        pc_action,
+                        thrown_type,
+                        types,
+                        relocation_names,
+                    )?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HandlerSelection, TypeBase, TypeHierarchy, TypeInfo};
+    use crate::text::parse_text;
+
+    const BASE: u32 = 0x80002000;
+    const DERIVED: u32 = 0x80002010;
+    const OTHER: u32 = 0x80002020;
+
+    /// Unlinked table, whose types are only given by their relocation names.
+    const TEXT: &str = r#"
+.flags
+.pc_range 0x0, 0x10, catches
+.pc_range 0x10, 0x20, named_spec
+.pc_range 0x20, 0x30, unnamed_spec
+
+catches:
+    catch_block catch_type="__RTTI__4Base", catch_pc_offset=0x100, cinfo_ref=0x20
+    catch_block catch_type=0, catch_pc_offset=0x200, cinfo_ref=0x20, end
+named_spec:
+    specification spec=["__RTTI__4Base"], pc_offset=0x300, cinfo_ref=0x20, end
+unnamed_spec:
+    specification spec=[0], pc_offset=0x400, cinfo_ref=0x20, end
+"#;
+
+    fn hierarchy() -> TypeHierarchy {
+        let mut types = TypeHierarchy::new();
+        types.insert(BASE, TypeInfo::new("Base"));
+        types.insert(OTHER, TypeInfo::new("Other"));
+        let mut derived = TypeInfo::new("Derived");
+        derived.bases.push(TypeBase {
+            type_address: BASE,
+            offset: 8,
+        });
+        types.insert(DERIVED, derived);
+        types
+    }
+
+    #[test]
+    fn base_offsets() {
+        let types = hierarchy();
+        assert_eq!(types.base_offset(DERIVED, BASE), Some(8));
+        assert_eq!(types.base_offset(DERIVED, DERIVED), Some(0));
+        assert_eq!(types.base_offset(BASE, DERIVED), None);
+        assert!(!types.catches(OTHER, BASE));
+    }
+
+    #[test]
+    fn catch_by_relocation_name() {
+        let (table, names) = parse_text(TEXT).unwrap();
+        let types = hierarchy();
+        let handlers = table.select_handlers(DERIVED, &types, &names).unwrap();
+        assert!(matches!(
+            handlers[0].selection,
+            HandlerSelection::Catch {
+                handler_pc: 0x100,
+                base_offset: 8,
+                ..
+            }
+        ));
+        assert!(matches!(handlers[1].selection, HandlerSelection::Propagate));
+
+        //Other types land in the catch (...) clause, and aren't allowed by the named
+        //specification
+        let handlers = table.select_handlers(OTHER, &types, &names).unwrap();
+        assert!(matches!(
+            handlers[0].selection,
+            HandlerSelection::Catch {
+                handler_pc: 0x200,
+                base_offset: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            handlers[1].selection,
+            HandlerSelection::Unexpected {
+                handler_pc: 0x300,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unnamed_specification_types_allow_nothing() {
+        let (table, names) = parse_text(TEXT).unwrap();
+        let pc_action = table.find_pc_action(0x20).unwrap();
+        let selection = table
+            .select_handler(pc_action, BASE, &hierarchy(), &names)
+            .unwrap();
+        assert!(matches!(
+            selection,
+            HandlerSelection::Unexpected {
+                handler_pc: 0x400,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::{
+    ActionChainError, ExAction, ExActionData, ExceptionTableData, HEADER_SIZE, PC_ACTION_SIZE,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Enum for the problems found by `ExceptionTableData::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiagnosticKind {
+    /// The PC range starts before the previous one.
+    UnsortedPCRange {
+        start_pc: u32,
+        previous_start_pc: u32,
+    },
+    /// The PC range overlaps the previous one.
+    OverlappingPCRange { start_pc: u32, previous_end_pc: u32 },
+    /// The PC range does not cover any instructions.
+    EmptyPCRange { start_pc: u32 },
+    /// The PC action's action offset does not point to the start of an action.
+    InvalidActionOffset { action_offset: u32 },
+    /// The branch target does not point to the start of an action.
+    InvalidBranchTarget { target_offset: u16 },
+    /// The action chain of the PC action loops back to an earlier action.
+    ActionChainCycle { action_offset: u32 },
+    /// The action data is not the size its action type requires.
+    InvalidActionDataSize { expected: u32, actual: u32 },
+    /// The last action of the table does not have the end bit set.
+    MissingEndBit,
+    /// The action cannot be reached from any PC action.
+    UnreachableAction,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::UnsortedPCRange {
+                start_pc,
+                previous_start_pc,
+            } => write!(
+                f,
+                "PC range starting at {start_pc:08X} comes after the range starting at {previous_start_pc:08X}"
+            ),
+            DiagnosticKind::OverlappingPCRange {
+                start_pc,
+                previous_end_pc,
+            } => write!(
+                f,
+                "PC range starting at {start_pc:08X} overlaps the previous range ending at {previous_end_pc:08X}"
+            ),
+            DiagnosticKind::EmptyPCRange { start_pc } => {
+                write!(f, "PC range starting at {start_pc:08X} is empty")
+            }
+            DiagnosticKind::InvalidActionOffset { action_offset } => write!(
+                f,
+                "Action offset {action_offset:06X} does not point to the start of an action"
+            ),
+            DiagnosticKind::InvalidBranchTarget { target_offset } => write!(
+                f,
+                "Branch target {target_offset:06X} does not point to the start of an action"
+            ),
+            DiagnosticKind::ActionChainCycle { action_offset } => {
+                write!(f, "Action chain loops back to the action at {action_offset:06X}")
+            }
+            DiagnosticKind::InvalidActionDataSize { expected, actual } => write!(
+                f,
+                "Action data is {actual} bytes long, but its type requires {expected} bytes"
+            ),
+            DiagnosticKind::MissingEndBit => write!(f, "Last action does not have the end bit set"),
+            DiagnosticKind::UnreachableAction => {
+                write!(f, "Action is not reachable from any PC action")
+            }
+        }
+    }
+}
+
+/// Struct for a problem found in a table, at the given table offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub offset: u32,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {:06X}: {}", self.severity, self.offset, self.kind)
+    }
+}
+
+impl ExceptionTableData {
+    /// Checks the structure of the table, returning a diagnostic for every problem found,
+    /// in order of their offset.
+    ///
+    /// Null actions with a zero param at the end of the table are treated as padding.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        let mut push = |severity: Severity, offset: u32, kind: DiagnosticKind| {
+            diagnostics.push(Diagnostic {
+                severity,
+                offset,
+                kind,
+            });
+        };
+
+        //Check the PC ranges
+        for (i, pcaction) in self.pc_actions.iter().enumerate() {
+            let entry_offset = HEADER_SIZE + (i as u32) * PC_ACTION_SIZE;
+
+            if pcaction.start_pc == pcaction.end_pc {
+                let start_pc = pcaction.start_pc;
+                push(
+                    Severity::Warning,
+                    entry_offset,
+                    DiagnosticKind::EmptyPCRange { start_pc },
+                );
+            }
+            if i > 0 {
+                let previous = &self.pc_actions[i - 1];
+                if pcaction.start_pc < previous.start_pc {
+                    let kind = DiagnosticKind::UnsortedPCRange {
+                        start_pc: pcaction.start_pc,
+                        previous_start_pc: previous.start_pc,
+                    };
+                    push(Severity::Warning, entry_offset, kind);
+                } else if pcaction.start_pc < previous.end_pc {
+                    let kind = DiagnosticKind::OverlappingPCRange {
+                        start_pc: pcaction.start_pc,
+                        previous_end_pc: previous.end_pc,
+                    };
+                    push(Severity::Error, entry_offset, kind);
+                }
+            }
+
+            let action_offset = pcaction.action_offset;
+            if action_offset != 0 && self.action_index_at_offset(action_offset).is_none() {
+                let kind = DiagnosticKind::InvalidActionOffset { action_offset };
+                push(Severity::Error, entry_offset, kind);
+            }
+        }
+
+        //Check the actions themselves
+        let num_actions = self.exception_actions.len() - self.num_padding_actions();
+        let actions = &self.exception_actions[..num_actions];
+
+        for action in actions {
+            let offset = action.action_offset;
+
+            match action.try_get_exaction_data() {
+                Ok(data) => {
+                    let expected = data.data_size();
+                    let actual = action.bytes.len() as u32;
+                    if expected != actual {
+                        let kind = DiagnosticKind::InvalidActionDataSize { expected, actual };
+                        push(Severity::Error, offset, kind);
+                    }
+
+                    if let ExActionData::Branch { target_offset } = data {
+                        if self.action_index_at_offset(target_offset as u32).is_none() {
+                            let kind = DiagnosticKind::InvalidBranchTarget { target_offset };
+                            push(Severity::Error, offset, kind);
+                        }
+                    }
+                }
+                Err(_) => {
+                    let kind = DiagnosticKind::InvalidActionDataSize {
+                        expected: action.action_type.base_data_size(),
+                        actual: action.bytes.len() as u32,
+                    };
+                    push(Severity::Error, offset, kind);
+                }
+            }
+        }
+
+        if let Some(last_action) = actions.last() {
+            if !last_action.has_end_bit {
+                push(
+                    Severity::Error,
+                    last_action.action_offset,
+                    DiagnosticKind::MissingEndBit,
+                );
+            }
+        }
+
+        //Follow the action chain of every PC action, to find cycles and unreachable actions
+        let mut reachable: HashSet<u32> = HashSet::new();
+        let mut reported_cycles: HashSet<u32> = HashSet::new();
+        let mut chains_complete = true;
+
+        for (i, pcaction) in self.pc_actions.iter().enumerate() {
+            let entry_offset = HEADER_SIZE + (i as u32) * PC_ACTION_SIZE;
+            match self.pc_action_chain(pcaction) {
+                Ok(chain) => {
+                    reachable.extend(chain.iter().map(|action| action.action_offset));
+                }
+                Err(ActionChainError::Cycle(action_offset)) => {
+                    chains_complete = false;
+                    if reported_cycles.insert(action_offset) {
+                        let kind = DiagnosticKind::ActionChainCycle { action_offset };
+                        push(Severity::Error, entry_offset, kind);
+                    }
+                }
+                //Other chain errors are already covered by the checks above
+                Err(_) => chains_complete = false,
+            }
+        }
+
+        //Actions past the point where a chain fails may still be reachable, so only
+        //report unreachable actions when every chain could be followed
+        if chains_complete {
+            for action in actions {
+                if !reachable.contains(&action.action_offset) {
+                    push(
+                        Severity::Warning,
+                        action.action_offset,
+                        DiagnosticKind::UnreachableAction,
+                    );
+                }
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+        diagnostics
+    }
+
+    /// Returns the number of null actions with a zero param at the end of the table,
+    /// which are padding rather than actual actions.
+    fn num_padding_actions(&self) -> usize {
+        self.exception_actions
+            .iter()
+            .rev()
+            .take_while(|action| {
+                matches!(action.action_type, ExAction::EndOfList)
+                    && !action.has_end_bit
+                    && action.action_param == 0
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiagnosticKind, Severity};
+    use crate::decode_extab;
+    use crate::fixtures::DESTROY_LOCALS_TABLE;
+
+    /// Returns a table with two destroy local actions, padded with zeros.
+    fn valid_table() -> Vec<u8> {
+        [&DESTROY_LOCALS_TABLE[..], &[0; 4]].concat()
+    }
+
+    /// Table whose only PC range starts at a pair of branches to each other.
+    #[rustfmt::skip]
+    const CYCLE_TABLE: [u8; 28] = [
+        0x00, 0x08, 0x00, 0x00, //Flags
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x04, 0x00, 0x10, //PC range
+        0x00, 0x00, 0x00, 0x00, //Terminator
+        0x01, 0x00, 0x00, 0x14, //Branch to the next branch
+        0x01, 0x00, 0x00, 0x10, //Branch back to the previous branch
+        0x80, 0x00, 0x00, 0x00, //End of list, padding
+    ];
+
+    fn kinds(bytes: &[u8]) -> Vec<(Severity, u32, DiagnosticKind)> {
+        decode_extab(bytes)
+            .unwrap()
+            .validate()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.offset, diagnostic.kind))
+            .collect()
+    }
+
+    #[test]
+    fn valid_table_has_no_diagnostics() {
+        assert_eq!(kinds(&valid_table()), []);
+    }
+
+    #[test]
+    fn reports_unsorted_overlapping_and_empty_ranges() {
+        let mut table = decode_extab(&valid_table()).unwrap();
+        table.pc_actions[1].start_pc = 0x10;
+        table.pc_actions[1].end_pc = 0x10;
+        let diagnostics = table.validate();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.offset == 0xC));
+        assert!(diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::EmptyPCRange { start_pc: 0x10 }));
+        assert!(diagnostics.iter().any(|diagnostic| diagnostic.kind
+            == DiagnosticKind::UnsortedPCRange {
+                start_pc: 0x10,
+                previous_start_pc: 0x20
+            }));
+
+        table.pc_actions[1].start_pc = 0x30;
+        table.pc_actions[1].end_pc = 0x40;
+        assert_eq!(
+            table.validate()[0].kind,
+            DiagnosticKind::OverlappingPCRange {
+                start_pc: 0x30,
+                previous_end_pc: 0x40
+            }
+        );
+    }
+
+    #[test]
+    fn reports_invalid_offsets_and_unreachable_actions() {
+        let mut table = decode_extab(&valid_table()).unwrap();
+        table.pc_actions[0].action_offset = 0x1A;
+        table.pc_actions[1].action_offset = 0x18;
+        assert_eq!(
+            table.validate()[0].kind,
+            DiagnosticKind::InvalidActionOffset {
+                action_offset: 0x1A
+            }
+        );
+
+        table.pc_actions.remove(0);
+        table.pc_actions[0].action_offset = 0x20;
+        let diagnostics = table.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].offset, 0x18);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnreachableAction);
+    }
+
+    #[test]
+    fn reports_missing_end_bit() {
+        let mut table = decode_extab(&valid_table()).unwrap();
+        table.exception_actions[1].has_end_bit = false;
+        assert_eq!(table.validate()[0].kind, DiagnosticKind::MissingEndBit);
+        assert_eq!(table.validate()[0].offset, 0x20);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        assert_eq!(
+            kinds(&CYCLE_TABLE),
+            [(
+                Severity::Error,
+                0x4,
+                DiagnosticKind::ActionChainCycle {
+                    action_offset: 0x10
+                }
+            )]
+        );
+    }
+}
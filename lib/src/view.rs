@@ -0,0 +1,381 @@
+use crate::{mem_utils, ExAction, ExActionData, ExceptionAction, ExtabDecodeError, PCAction};
+
+/// Offset of the PC action range entries, after the flag value and et field.
+const PC_ACTIONS_OFFSET: i32 = 4;
+
+/// Borrowed view over the bytes of an exception table.
+///
+/// Unlike `decode_extab`, this does not copy any data. The header and PC action list
+/// are checked when the view is created, while the actions are only decoded as they
+/// are iterated over.
+#[derive(Debug, Copy, Clone)]
+pub struct ExceptionTable<'a> {
+    data: &'a [u8],
+    flag_val: u16,
+    et_field: u16,
+    actions_offset: i32,
+}
+
+impl<'a> ExceptionTable<'a> {
+    /// Creates a view over the given table data, checking its header and PC action list.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ExtabDecodeError> {
+        let length = data.len() as i32;
+
+        //If the array is empty, return an error.
+        if length < 8 {
+            return Err(ExtabDecodeError::ArrayTooSmall(length as u32));
+        }
+
+        let mut offset: i32 = 0;
+        let flag_val =
+            mem_utils::read_uint16(data, &mut offset, true).ok_or(ExtabDecodeError::Internal)?;
+        let et_field =
+            mem_utils::read_uint16(data, &mut offset, true).ok_or(ExtabDecodeError::Internal)?;
+
+        //Check whether the table is 8 bytes but the terminator isn't zero. If so,
+        //throw an error.
+        let terminator =
+            mem_utils::read_uint32(data, &mut offset, false).ok_or(ExtabDecodeError::Internal)?;
+        if length == 8 && terminator != 0 {
+            return Err(ExtabDecodeError::InvalidSmallTableTerminator);
+        }
+
+        //Check the range entries until we hit the terminator (32 bit zero value)
+        loop {
+            let entry_offset = offset as u32;
+            let truncated = || ExtabDecodeError::TruncatedRangeEntry(entry_offset);
+            let start_pc = mem_utils::read_uint32(data, &mut offset, true).ok_or_else(truncated)?;
+            if start_pc == 0 {
+                break;
+            }
+
+            let range_size: u32 =
+                (mem_utils::read_uint16(data, &mut offset, true).ok_or_else(truncated)? as u32) * 4; //range size is encoded as size >> 2
+            if start_pc.checked_add(range_size).is_none() {
+                return Err(ExtabDecodeError::InvalidPCRange(entry_offset));
+            }
+            mem_utils::read_uint16(data, &mut offset, true).ok_or_else(truncated)?;
+        }
+
+        Ok(Self {
+            data,
+            flag_val,
+            et_field,
+            actions_offset: offset,
+        })
+    }
+
+    /// Returns the bytes of the whole table.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn flag_val(&self) -> u16 {
+        self.flag_val
+    }
+
+    pub fn et_field(&self) -> u16 {
+        self.et_field
+    }
+
+    /// Returns the offset of the first action, after the range entries and the terminator.
+    pub fn actions_offset(&self) -> u32 {
+        self.actions_offset as u32
+    }
+
+    /// Returns an iterator over the PC actions of the table.
+    pub fn pc_actions(&self) -> PCActionIter<'a> {
+        PCActionIter {
+            data: self.data,
+            offset: PC_ACTIONS_OFFSET,
+        }
+    }
+
+    /// Returns an iterator over the actions of the table, decoding each action entry as
+    /// it is reached. Iteration stops after the first invalid entry.
+    pub fn actions(&self) -> ActionIter<'a> {
+        ActionIter {
+            data: self.data,
+            offset: self.actions_offset,
+        }
+    }
+}
+
+/// Iterator over the PC actions of an `ExceptionTable`.
+#[derive(Debug, Clone)]
+pub struct PCActionIter<'a> {
+    data: &'a [u8],
+    offset: i32,
+}
+
+impl<'a> Iterator for PCActionIter<'a> {
+    type Item = PCAction;
+
+    fn next(&mut self) -> Option<PCAction> {
+        //The list was checked when creating the table, so reads only fail at the terminator
+        let start_pc = mem_utils::read_uint32(self.data, &mut self.offset, false)?;
+        if start_pc == 0 {
+            return None;
+        }
+        self.offset += 4;
+
+        let mut pcaction = PCAction::new();
+        pcaction.start_pc = start_pc;
+        let range_size: u32 =
+            (mem_utils::read_uint16(self.data, &mut self.offset, true)? as u32) * 4;
+        pcaction.end_pc = pcaction.start_pc + range_size;
+        pcaction.action_offset = mem_utils::read_uint16(self.data, &mut self.offset, true)? as u32;
+        Some(pcaction)
+    }
+}
+
+/// Borrowed view over an action entry of an `ExceptionTable`.
+#[derive(Debug, Copy, Clone)]
+pub struct ExceptionActionRef<'a> {
+    pub action_offset: u32,
+    pub action_type: ExAction,
+    pub action_param: u8,
+    pub has_end_bit: bool,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> ExceptionActionRef<'a> {
+    /// Decodes the action data from the byte array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the byte array is too short for the action type. Actions returned by
+    /// `ActionIter` always have enough data.
+    #[deprecated(note = "use `try_get_exaction_data`, which returns an error instead of panicking")]
+    pub fn get_exaction_data(&self) -> ExActionData {
+        match self.try_get_exaction_data() {
+            Ok(data) => data,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Decodes the action data from the byte array.
+    ///
+    /// Returns an error if the byte array is too short for the action type.
+    pub fn try_get_exaction_data(&self) -> Result<ExActionData, ExtabDecodeError> {
+        ExActionData::from_bytes(self.action_type, self.bytes).ok_or(
+            ExtabDecodeError::TruncatedActionPayload(self.action_type, self.action_offset),
+        )
+    }
+
+    /// Copies the action into an owned `ExceptionAction`.
+    pub fn to_owned_action(&self) -> ExceptionAction {
+        ExceptionAction {
+            action_offset: self.action_offset,
+            action_type: self.action_type,
+            action_param: self.action_param,
+            has_end_bit: self.has_end_bit,
+            bytes: self.bytes.to_vec(),
+        }
+    }
+}
+
+/// Iterator over the actions of an `ExceptionTable`.
+#[derive(Debug, Clone)]
+pub struct ActionIter<'a> {
+    data: &'a [u8],
+    offset: i32,
+}
+
+impl<'a> ActionIter<'a> {
+    fn parse_action_entry(&mut self) -> Result<ExceptionActionRef<'a>, ExtabDecodeError> {
+        let action_offset = self.offset as u32;
+        let truncated_header = || ExtabDecodeError::TruncatedActionHeader(action_offset);
+        let action_type_byte =
+            mem_utils::read_byte(self.data, &mut self.offset, true).ok_or_else(truncated_header)?;
+        let has_end_bit = (action_type_byte & 0x80) != 0;
+        let action_type_value: u32 = (action_type_byte & 0x7F) as u32;
+        let action_type = match ExAction::from_int(action_type_value as i32) {
+            Some(action) => action,
+            None => {
+                return Err(ExtabDecodeError::InvalidActionValue(
+                    action_type_value,
+                    action_offset,
+                ))
+            }
+        };
+        let action_param =
+            mem_utils::read_byte(self.data, &mut self.offset, true).ok_or_else(truncated_header)?;
+
+        let truncated = || ExtabDecodeError::TruncatedActionPayload(action_type, action_offset);
+
+        //Since the way action data is stored is too varied, we just keep the remaining data as a byte
+        //array to be decoded later.
+        let mut size: i32 = action_type.base_data_size() as i32;
+
+        if let ExAction::Specification = action_type {
+            //Calculate the length of the array, and add it to the base size
+            let length = mem_utils::read_uint16(self.data, &mut self.offset, false)
+                .ok_or_else(truncated)? as i32;
+            size += length * 4;
+        }
+
+        let start_index = self.offset as usize;
+        let end_index = start_index + size as usize;
+        let bytes = self
+            .data
+            .get(start_index..end_index)
+            .ok_or_else(truncated)?;
+        self.offset += size;
+
+        Ok(ExceptionActionRef {
+            action_offset,
+            action_type,
+            action_param,
+            has_end_bit,
+            bytes,
+        })
+    }
+}
+
+impl<'a> Iterator for ActionIter<'a> {
+    type Item = Result<ExceptionActionRef<'a>, ExtabDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset as usize >= self.data.len() {
+            return None;
+        }
+
+        let result = self.parse_action_entry();
+        if result.is_err() {
+            //Stop after the first error, since the following entries can't be found
+            self.offset = self.data.len() as i32;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExceptionActionRef, ExceptionTable};
+    use crate::fixtures::{DESTROY_LOCALS_TABLE, DESTROY_LOCAL_TABLE, EMPTY_TABLE};
+    use crate::{decode_extab, ExAction, ExActionData, ExtabDecodeError};
+
+    #[test]
+    fn parse() {
+        let table = ExceptionTable::parse(&DESTROY_LOCALS_TABLE).unwrap();
+        assert_eq!(table.flag_val(), 0x1008);
+        assert_eq!(table.et_field(), 0);
+        assert_eq!(table.actions_offset(), 0x18);
+        assert_eq!(table.data().len(), DESTROY_LOCALS_TABLE.len());
+
+        let empty = ExceptionTable::parse(&EMPTY_TABLE).unwrap();
+        assert_eq!(empty.pc_actions().count(), 0);
+        assert_eq!(empty.actions().count(), 0);
+    }
+
+    #[test]
+    fn invalid_tables() {
+        let mut overflowing_range = DESTROY_LOCAL_TABLE;
+        overflowing_range[4..8].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFC]);
+        let tables: [(&[u8], ExtabDecodeError); 4] = [
+            (&EMPTY_TABLE[..4], ExtabDecodeError::ArrayTooSmall(4)),
+            (
+                &DESTROY_LOCAL_TABLE[..8],
+                ExtabDecodeError::InvalidSmallTableTerminator,
+            ),
+            (
+                &DESTROY_LOCAL_TABLE[..10],
+                ExtabDecodeError::TruncatedRangeEntry(4),
+            ),
+            (&overflowing_range, ExtabDecodeError::InvalidPCRange(4)),
+        ];
+        for (data, expected) in tables {
+            let error = ExceptionTable::parse(data).unwrap_err();
+            assert_eq!(error.to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn pc_actions() {
+        let table = ExceptionTable::parse(&DESTROY_LOCALS_TABLE).unwrap();
+        let ranges: Vec<(u32, u32, u32)> = table
+            .pc_actions()
+            .map(|pcaction| (pcaction.start_pc, pcaction.end_pc, pcaction.action_offset))
+            .collect();
+        assert_eq!(ranges, vec![(0x20, 0x40, 0x18), (0x40, 0x50, 0x20)]);
+    }
+
+    #[test]
+    fn actions_match_decoder() {
+        let table = ExceptionTable::parse(&DESTROY_LOCALS_TABLE).unwrap();
+        let decoded = decode_extab(&DESTROY_LOCALS_TABLE).unwrap();
+        let actions: Vec<ExceptionActionRef> = table.actions().map(Result::unwrap).collect();
+        assert_eq!(actions.len(), decoded.exception_actions.len());
+
+        for (action, expected) in actions.iter().zip(&decoded.exception_actions) {
+            let owned = action.to_owned_action();
+            assert_eq!(owned.action_offset, expected.action_offset);
+            assert_eq!(owned.action_param, expected.action_param);
+            assert_eq!(owned.has_end_bit, expected.has_end_bit);
+            assert_eq!(owned.bytes, expected.bytes);
+        }
+        assert!(!actions[0].has_end_bit);
+        assert!(actions[1].has_end_bit);
+        assert!(matches!(
+            actions[1].try_get_exaction_data(),
+            Ok(ExActionData::DestroyLocal {
+                local_offset: 0xC,
+                dtor_address: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn actions_stop_after_first_error() {
+        //The destroy local action after the invalid one is never reached
+        let mut data = DESTROY_LOCALS_TABLE;
+        data[0x18] = 0x7F;
+        let table = ExceptionTable::parse(&data).unwrap();
+        let mut actions = table.actions();
+        assert!(matches!(
+            actions.next(),
+            Some(Err(ExtabDecodeError::InvalidActionValue(0x7F, 0x18)))
+        ));
+        assert!(actions.next().is_none());
+
+        //Actions before the error are still given
+        let table = ExceptionTable::parse(&DESTROY_LOCALS_TABLE[..0x24]).unwrap();
+        let mut actions = table.actions();
+        assert!(matches!(actions.next(), Some(Ok(_))));
+        assert!(matches!(
+            actions.next(),
+            Some(Err(ExtabDecodeError::TruncatedActionPayload(
+                ExAction::DestroyLocal,
+                0x20
+            )))
+        ));
+        assert!(actions.next().is_none());
+
+        let table = ExceptionTable::parse(&DESTROY_LOCALS_TABLE[..0x21]).unwrap();
+        let results: Vec<_> = table.actions().collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(ExtabDecodeError::TruncatedActionHeader(0x20))
+        ));
+    }
+
+    #[test]
+    fn truncated_action_data() {
+        let action = ExceptionActionRef {
+            action_offset: 0x10,
+            action_type: ExAction::DestroyLocal,
+            action_param: 0,
+            has_end_bit: true,
+            bytes: &[0x00, 0x08],
+        };
+        assert!(matches!(
+            action.try_get_exaction_data(),
+            Err(ExtabDecodeError::TruncatedActionPayload(
+                ExAction::DestroyLocal,
+                0x10
+            ))
+        ));
+    }
+}